@@ -108,10 +108,10 @@ fn list_sql_files(kind: &str, suffix: &str) -> io::Result<Vec<PathBuf>> {
         for entry in fs::read_dir(dir)? {
             let entry = entry?;
             let path = entry.path();
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                if name.ends_with(suffix) {
-                    files.push(path);
-                }
+            if let Some(name) = path.file_name().and_then(|n| n.to_str())
+                && name.ends_with(suffix)
+            {
+                files.push(path);
             }
         }
     }
@@ -212,5 +212,5 @@ fn undo_last(kind: &str) -> io::Result<()> {
 }
 
 fn to_io_err(err: sqlx::Error) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, err.to_string())
+    io::Error::other(err.to_string())
 }
@@ -22,7 +22,7 @@ fn main() -> io::Result<()> {
 
     write_file_if_missing(
         entity_dir.join("mod.rs"),
-        &format!("pub mod controller;\npub mod dto;\npub mod repo;\npub mod service;\n"),
+        "pub mod controller;\npub mod dto;\npub mod repo;\npub mod service;\n",
     )?;
 
     write_file_if_missing(
@@ -81,6 +81,7 @@ impl {{ENTITY}}Controller {
             status_code: 200,
             headers,
             body,
+            stream: None,
         }
     }
 
@@ -94,6 +95,7 @@ impl {{ENTITY}}Controller {
             status_code: 200,
             headers,
             body,
+            stream: None,
         }
     }
 
@@ -106,6 +108,7 @@ impl {{ENTITY}}Controller {
             status_code: 201,
             headers,
             body,
+            stream: None,
         }
     }
 
@@ -119,6 +122,7 @@ impl {{ENTITY}}Controller {
             status_code: 200,
             headers,
             body,
+            stream: None,
         }
     }
 
@@ -132,6 +136,7 @@ impl {{ENTITY}}Controller {
             status_code: 200,
             headers,
             body,
+            stream: None,
         }
     }
 }
@@ -0,0 +1,709 @@
+use std::fmt;
+
+use super::DbParam;
+
+/// Quotes an identifier for Postgres: wraps it in double quotes and doubles any quote
+/// inside, so a column or table name can never end the identifier early.
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// A statement with its bind parameters, numbered `$1`, `$2`, ... in order.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub sql: String,
+    pub params: Vec<DbParam>,
+}
+
+/// Appends `value` to `params` and returns its placeholder.
+fn placeholder(params: &mut Vec<DbParam>, value: DbParam) -> String {
+    params.push(value);
+    format!("${}", params.len())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Like,
+    ILike,
+}
+
+impl CompareOp {
+    fn as_sql(self) -> &'static str {
+        match self {
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "<>",
+            CompareOp::Lt => "<",
+            CompareOp::Lte => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Gte => ">=",
+            CompareOp::Like => "LIKE",
+            CompareOp::ILike => "ILIKE",
+        }
+    }
+}
+
+/// A `WHERE` condition. Columns are quoted and values are always bound, never spliced
+/// into the SQL.
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Compare {
+        column: String,
+        op: CompareOp,
+        value: DbParam,
+    },
+    /// `column = ANY($n)`, with an array parameter.
+    In {
+        column: String,
+        values: DbParam,
+    },
+    /// `column <> ALL($n)`, with an array parameter.
+    NotIn {
+        column: String,
+        values: DbParam,
+    },
+    IsNull(String),
+    IsNotNull(String),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+#[allow(dead_code)]
+impl Condition {
+    pub fn compare(column: &str, op: CompareOp, value: impl Into<DbParam>) -> Self {
+        Condition::Compare {
+            column: column.to_string(),
+            op,
+            value: value.into(),
+        }
+    }
+
+    pub fn eq(column: &str, value: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::Eq, value)
+    }
+
+    pub fn ne(column: &str, value: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::Ne, value)
+    }
+
+    pub fn lt(column: &str, value: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::Lt, value)
+    }
+
+    pub fn lte(column: &str, value: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::Lte, value)
+    }
+
+    pub fn gt(column: &str, value: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::Gt, value)
+    }
+
+    pub fn gte(column: &str, value: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::Gte, value)
+    }
+
+    pub fn like(column: &str, pattern: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::Like, pattern)
+    }
+
+    pub fn ilike(column: &str, pattern: impl Into<DbParam>) -> Self {
+        Self::compare(column, CompareOp::ILike, pattern)
+    }
+
+    pub fn is_in(column: &str, values: impl Into<DbParam>) -> Self {
+        Condition::In {
+            column: column.to_string(),
+            values: values.into(),
+        }
+    }
+
+    pub fn not_in(column: &str, values: impl Into<DbParam>) -> Self {
+        Condition::NotIn {
+            column: column.to_string(),
+            values: values.into(),
+        }
+    }
+
+    pub fn is_null(column: &str) -> Self {
+        Condition::IsNull(column.to_string())
+    }
+
+    pub fn is_not_null(column: &str) -> Self {
+        Condition::IsNotNull(column.to_string())
+    }
+
+    pub fn and(conditions: Vec<Condition>) -> Self {
+        Condition::And(conditions)
+    }
+
+    pub fn or(conditions: Vec<Condition>) -> Self {
+        Condition::Or(conditions)
+    }
+
+    fn render(&self, params: &mut Vec<DbParam>) -> String {
+        match self {
+            Condition::Compare { column, op, value } => format!(
+                "{} {} {}",
+                quote_ident(column),
+                op.as_sql(),
+                placeholder(params, value.clone())
+            ),
+            Condition::In { column, values } => format!(
+                "{} = ANY({})",
+                quote_ident(column),
+                placeholder(params, values.clone())
+            ),
+            Condition::NotIn { column, values } => format!(
+                "{} <> ALL({})",
+                quote_ident(column),
+                placeholder(params, values.clone())
+            ),
+            Condition::IsNull(column) => format!("{} IS NULL", quote_ident(column)),
+            Condition::IsNotNull(column) => format!("{} IS NOT NULL", quote_ident(column)),
+            Condition::And(conditions) => Self::join(conditions, "AND", "TRUE", params),
+            Condition::Or(conditions) => Self::join(conditions, "OR", "FALSE", params),
+            Condition::Not(condition) => format!("NOT ({})", condition.render(params)),
+        }
+    }
+
+    fn join(
+        conditions: &[Condition],
+        separator: &str,
+        empty: &str,
+        params: &mut Vec<DbParam>,
+    ) -> String {
+        if conditions.is_empty() {
+            return empty.to_string();
+        }
+        let parts = conditions
+            .iter()
+            .map(|condition| format!("({})", condition.render(params)))
+            .collect::<Vec<_>>();
+        parts.join(&format!(" {} ", separator))
+    }
+}
+
+/// `!condition` negates it.
+impl std::ops::Not for Condition {
+    type Output = Condition;
+
+    fn not(self) -> Condition {
+        Condition::Not(Box::new(self))
+    }
+}
+
+/// Renders ` WHERE ...` for the conditions, all of which must hold.
+fn where_clause(conditions: &[Condition], params: &mut Vec<DbParam>) -> String {
+    match conditions {
+        [] => String::new(),
+        [condition] => format!(" WHERE {}", condition.render(params)),
+        conditions => format!(
+            " WHERE {}",
+            Condition::join(conditions, "AND", "TRUE", params)
+        ),
+    }
+}
+
+fn returning_clause(columns: &[String]) -> String {
+    if columns.is_empty() {
+        return String::new();
+    }
+    let columns = columns
+        .iter()
+        .map(|column| quote_ident(column))
+        .collect::<Vec<_>>();
+    format!(" RETURNING {}", columns.join(", "))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// `ORDER BY` on a column that is not in the sortable whitelist.
+    UnsortableColumn(String),
+    /// An `UPDATE` of this table without any column to set.
+    NoAssignments(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::UnsortableColumn(column) => {
+                write!(f, "Cannot sort by '{}'", column)
+            }
+            BuildError::NoAssignments(table) => {
+                write!(f, "UPDATE {} has no columns to set", table)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+#[derive(Debug, Clone)]
+enum Column {
+    Name(String),
+    /// Trusted SQL, e.g. `COUNT(*) OVER () AS total`. Never built from input.
+    Expr(String),
+}
+
+/// A `SELECT` from one table.
+///
+/// ```ignore
+/// let statement = Select::from("USER")
+///     .columns(&["id", "username"])
+///     .filter(Condition::ilike("username", "%ann%"))
+///     .order_by("username", Order::Asc)?
+///     .limit(10)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Select {
+    table: String,
+    columns: Vec<Column>,
+    conditions: Vec<Condition>,
+    sortable: Option<Vec<String>>,
+    order_by: Vec<(String, Order)>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[allow(dead_code)]
+impl Select {
+    pub fn from(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            columns: vec![],
+            conditions: vec![],
+            sortable: None,
+            order_by: vec![],
+            limit: None,
+            offset: None,
+        }
+    }
+
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns
+            .extend(columns.iter().map(|c| Column::Name(c.to_string())));
+        self
+    }
+
+    /// Adds a raw SQL expression to the select list. Only pass trusted SQL.
+    pub fn column_expr(mut self, expr: &str) -> Self {
+        self.columns.push(Column::Expr(expr.to_string()));
+        self
+    }
+
+    /// Adds a condition; all conditions are combined with `AND`.
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// The columns `order_by` accepts. Defaults to the selected column names.
+    pub fn sortable(mut self, columns: &[&str]) -> Self {
+        self.sortable = Some(columns.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    pub fn is_sortable(&self, column: &str) -> bool {
+        match &self.sortable {
+            Some(sortable) => sortable.iter().any(|c| c == column),
+            None => self
+                .columns
+                .iter()
+                .any(|c| matches!(c, Column::Name(name) if name == column)),
+        }
+    }
+
+    /// Appends a sort key. Fails when `column` is not sortable, so it is safe to pass
+    /// a column name taken from the request.
+    pub fn order_by(mut self, column: &str, order: Order) -> Result<Self, BuildError> {
+        if !self.is_sortable(column) {
+            return Err(BuildError::UnsortableColumn(column.to_string()));
+        }
+        self.order_by.push((column.to_string(), order));
+        Ok(self)
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn build(&self) -> Statement {
+        let mut params = vec![];
+        let columns = if self.columns.is_empty() {
+            "*".to_string()
+        } else {
+            self.columns
+                .iter()
+                .map(|column| match column {
+                    Column::Name(name) => quote_ident(name),
+                    Column::Expr(expr) => expr.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut sql = format!("SELECT {} FROM {}", columns, quote_ident(&self.table));
+        sql.push_str(&where_clause(&self.conditions, &mut params));
+        if !self.order_by.is_empty() {
+            let keys = self
+                .order_by
+                .iter()
+                .map(|(column, order)| {
+                    let direction = match order {
+                        Order::Asc => "ASC",
+                        Order::Desc => "DESC",
+                    };
+                    format!("{} {}", quote_ident(column), direction)
+                })
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(" ORDER BY {}", keys.join(", ")));
+        }
+        if let Some(offset) = self.offset {
+            sql.push_str(&format!(
+                " OFFSET {}",
+                placeholder(&mut params, offset.into())
+            ));
+        }
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(
+                " LIMIT {}",
+                placeholder(&mut params, limit.into())
+            ));
+        }
+        Statement { sql, params }
+    }
+
+    /// `SELECT COUNT(*) AS total` over the same table and conditions.
+    pub fn count(&self) -> Statement {
+        let mut params = vec![];
+        let mut sql = format!("SELECT COUNT(*) AS total FROM {}", quote_ident(&self.table));
+        sql.push_str(&where_clause(&self.conditions, &mut params));
+        Statement { sql, params }
+    }
+}
+
+/// An `INSERT` of one row.
+#[derive(Debug, Clone)]
+pub struct Insert {
+    table: String,
+    values: Vec<(String, DbParam)>,
+    returning: Vec<String>,
+}
+
+#[allow(dead_code)]
+impl Insert {
+    pub fn into(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            values: vec![],
+            returning: vec![],
+        }
+    }
+
+    pub fn value(mut self, column: &str, value: impl Into<DbParam>) -> Self {
+        self.values.push((column.to_string(), value.into()));
+        self
+    }
+
+    pub fn returning(mut self, columns: &[&str]) -> Self {
+        self.returning = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    pub fn build(&self) -> Statement {
+        let mut params = vec![];
+        let mut sql = format!("INSERT INTO {}", quote_ident(&self.table));
+        if self.values.is_empty() {
+            sql.push_str(" DEFAULT VALUES");
+        } else {
+            let columns = self
+                .values
+                .iter()
+                .map(|(column, _)| quote_ident(column))
+                .collect::<Vec<_>>();
+            let values = self
+                .values
+                .iter()
+                .map(|(_, value)| placeholder(&mut params, value.clone()))
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(
+                " ({}) VALUES ({})",
+                columns.join(", "),
+                values.join(", ")
+            ));
+        }
+        sql.push_str(&returning_clause(&self.returning));
+        Statement { sql, params }
+    }
+}
+
+/// An `UPDATE` of the rows matching every condition.
+#[derive(Debug, Clone)]
+pub struct Update {
+    table: String,
+    assignments: Vec<(String, DbParam)>,
+    conditions: Vec<Condition>,
+    returning: Vec<String>,
+}
+
+#[allow(dead_code)]
+impl Update {
+    pub fn table(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            assignments: vec![],
+            conditions: vec![],
+            returning: vec![],
+        }
+    }
+
+    pub fn set(mut self, column: &str, value: impl Into<DbParam>) -> Self {
+        self.assignments.push((column.to_string(), value.into()));
+        self
+    }
+
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn returning(mut self, columns: &[&str]) -> Self {
+        self.returning = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Fails without any `set`, since `UPDATE ... SET` needs at least one column.
+    pub fn build(&self) -> Result<Statement, BuildError> {
+        if self.assignments.is_empty() {
+            return Err(BuildError::NoAssignments(self.table.clone()));
+        }
+        let mut params = vec![];
+        let assignments = self
+            .assignments
+            .iter()
+            .map(|(column, value)| {
+                format!(
+                    "{} = {}",
+                    quote_ident(column),
+                    placeholder(&mut params, value.clone())
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut sql = format!(
+            "UPDATE {} SET {}",
+            quote_ident(&self.table),
+            assignments.join(", ")
+        );
+        sql.push_str(&where_clause(&self.conditions, &mut params));
+        sql.push_str(&returning_clause(&self.returning));
+        Ok(Statement { sql, params })
+    }
+}
+
+/// A `DELETE` of the rows matching every condition.
+#[derive(Debug, Clone)]
+pub struct Delete {
+    table: String,
+    conditions: Vec<Condition>,
+    returning: Vec<String>,
+}
+
+#[allow(dead_code)]
+impl Delete {
+    pub fn from(table: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            conditions: vec![],
+            returning: vec![],
+        }
+    }
+
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn returning(mut self, columns: &[&str]) -> Self {
+        self.returning = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    pub fn build(&self) -> Statement {
+        let mut params = vec![];
+        let mut sql = format!("DELETE FROM {}", quote_ident(&self.table));
+        sql.push_str(&where_clause(&self.conditions, &mut params));
+        sql.push_str(&returning_clause(&self.returning));
+        Statement { sql, params }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(statement: &Statement) -> String {
+        format!("{:?}", statement.params)
+    }
+
+    #[test]
+    fn quotes_identifiers() {
+        assert_eq!(quote_ident("USER"), "\"USER\"");
+        assert_eq!(quote_ident("created at"), "\"created at\"");
+        assert_eq!(quote_ident("a\"b"), "\"a\"\"b\"");
+        assert_eq!(
+            quote_ident("x\"; DROP TABLE \"USER"),
+            "\"x\"\"; DROP TABLE \"\"USER\""
+        );
+        assert_eq!(quote_ident(""), "\"\"");
+    }
+
+    #[test]
+    fn numbers_select_placeholders_in_order() {
+        let statement = Select::from("USER")
+            .columns(&["id", "username"])
+            .filter(Condition::ilike("username", "%ann%"))
+            .filter(Condition::or(vec![
+                Condition::gt("age", 18i64),
+                !Condition::is_in("id", vec![1i64, 2]),
+                Condition::is_null("deleted_at"),
+            ]))
+            .order_by("username", Order::Desc)
+            .unwrap()
+            .offset(20)
+            .limit(10)
+            .build();
+        assert_eq!(
+            statement.sql,
+            "SELECT \"id\", \"username\" FROM \"USER\" WHERE (\"username\" ILIKE $1) AND \
+             ((\"age\" > $2) OR (NOT (\"id\" = ANY($3))) OR (\"deleted_at\" IS NULL)) \
+             ORDER BY \"username\" DESC OFFSET $4 LIMIT $5"
+        );
+        assert_eq!(
+            params(&statement),
+            r#"[Text("%ann%"), Int64(18), Int64Array([1, 2]), Int64(20), Int64(10)]"#
+        );
+    }
+
+    #[test]
+    fn renders_empty_groups_as_constants() {
+        let statement = Select::from("T")
+            .filter(Condition::and(vec![]))
+            .filter(Condition::or(vec![]))
+            .build();
+        assert_eq!(
+            statement.sql,
+            "SELECT * FROM \"T\" WHERE (TRUE) AND (FALSE)"
+        );
+        assert!(statement.params.is_empty());
+    }
+
+    #[test]
+    fn counts_with_the_same_conditions() {
+        let select = Select::from("USER")
+            .columns(&["id"])
+            .filter(Condition::eq("username", "ann"))
+            .limit(10);
+        let count = select.count();
+        assert_eq!(
+            count.sql,
+            "SELECT COUNT(*) AS total FROM \"USER\" WHERE \"username\" = $1"
+        );
+        assert_eq!(params(&count), r#"[Text("ann")]"#);
+    }
+
+    #[test]
+    fn orders_only_by_sortable_columns() {
+        let select = Select::from("USER").columns(&["id", "username"]);
+        assert!(select.clone().order_by("username", Order::Asc).is_ok());
+        assert_eq!(
+            select.clone().order_by("password", Order::Asc).unwrap_err(),
+            BuildError::UnsortableColumn("password".to_string())
+        );
+
+        let select = select
+            .column_expr("COUNT(*) OVER () AS total")
+            .sortable(&["created_at"]);
+        assert!(select.clone().order_by("created_at", Order::Asc).is_ok());
+        assert!(select.order_by("id", Order::Asc).is_err());
+    }
+
+    #[test]
+    fn numbers_insert_values() {
+        let statement = Insert::into("USER")
+            .value("username", "ann")
+            .value("age", 30i64)
+            .returning(&["id"])
+            .build();
+        assert_eq!(
+            statement.sql,
+            "INSERT INTO \"USER\" (\"username\", \"age\") VALUES ($1, $2) RETURNING \"id\""
+        );
+        assert_eq!(params(&statement), r#"[Text("ann"), Int64(30)]"#);
+
+        let statement = Insert::into("T").build();
+        assert_eq!(statement.sql, "INSERT INTO \"T\" DEFAULT VALUES");
+    }
+
+    #[test]
+    fn numbers_update_conditions_after_assignments() {
+        let statement = Update::table("USER")
+            .set("password", "hash")
+            .set("age", 31i64)
+            .filter(Condition::eq("username", "ann"))
+            .returning(&["id", "username"])
+            .build()
+            .unwrap();
+        assert_eq!(
+            statement.sql,
+            "UPDATE \"USER\" SET \"password\" = $1, \"age\" = $2 WHERE \"username\" = $3 \
+             RETURNING \"id\", \"username\""
+        );
+        assert_eq!(
+            params(&statement),
+            r#"[Text("hash"), Int64(31), Text("ann")]"#
+        );
+    }
+
+    #[test]
+    fn rejects_updates_without_assignments() {
+        let error = Update::table("USER")
+            .filter(Condition::eq("username", "ann"))
+            .build()
+            .unwrap_err();
+        assert_eq!(error, BuildError::NoAssignments("USER".to_string()));
+        assert_eq!(error.to_string(), "UPDATE USER has no columns to set");
+    }
+
+    #[test]
+    fn deletes_with_conditions() {
+        let statement = Delete::from("USER")
+            .filter(Condition::ne("username", "root"))
+            .filter(Condition::is_not_null("deleted_at"))
+            .returning(&["id"])
+            .build();
+        assert_eq!(
+            statement.sql,
+            "DELETE FROM \"USER\" WHERE (\"username\" <> $1) AND (\"deleted_at\" IS NOT NULL) \
+             RETURNING \"id\""
+        );
+        assert_eq!(params(&statement), r#"[Text("root")]"#);
+    }
+}
@@ -0,0 +1,333 @@
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+use super::{DbParam, DbType, POOL, bind_all, compact_sql, is_read_only};
+use crate::util::app_env::is_production;
+use crate::util::signing::hex;
+
+/// Distinct statements tracked before the rest are counted under `other`.
+const MAX_STATEMENTS: usize = 500;
+
+/// Totals for one statement since startup.
+#[derive(Debug, Clone, Default)]
+pub struct StatementStats {
+    /// The normalized SQL (see `normalize`).
+    pub statement: String,
+    pub calls: u64,
+    pub errors: u64,
+    /// Rows returned, or affected by `execute`.
+    pub rows: u64,
+    pub slow: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+fn stats() -> &'static Mutex<HashMap<String, StatementStats>> {
+    static STATS: OnceLock<Mutex<HashMap<String, StatementStats>>> = OnceLock::new();
+    STATS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Per-statement totals by `fingerprint`, slowest in total first.
+pub fn statement_stats() -> Vec<(String, StatementStats)> {
+    let mut stats = stats()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(fingerprint, stats)| (fingerprint.clone(), stats.clone()))
+        .collect::<Vec<_>>();
+    stats.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+    stats
+}
+
+/// `sql` with comments dropped, whitespace collapsed and literals replaced by `?`, so
+/// the same statement with other inline values normalizes the same way.
+fn normalize(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut space = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                chars.find(|&c| c == '\n');
+                space = true;
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                chars.find(|&c| std::mem::replace(&mut last, c) == '*' && c == '/');
+                space = true;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                space = true;
+                continue;
+            }
+            _ => {}
+        }
+        if space && !out.is_empty() {
+            out.push(' ');
+        }
+        space = false;
+        let after_word = out
+            .chars()
+            .next_back()
+            .is_some_and(|p| p.is_alphanumeric() || p == '_' || p == '$' || p == '"');
+        match c {
+            '\'' => {
+                // A doubled quote is an escaped quote inside the literal.
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+                out.push('?');
+            }
+            c if c.is_ascii_digit() && !after_word => {
+                while chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || *c == '.')
+                    .is_some()
+                {}
+                out.push('?');
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A short hash of the normalized statement, used as its key in the stats and its
+/// `/metrics` label: stable across restarts and instances, and bounded in length.
+fn fingerprint(normalized: &str) -> String {
+    let digest = Sha256::digest(normalized.as_bytes());
+    hex(&digest[..8])
+}
+
+/// `DB_SLOW_QUERY_MS` (default 500). `0` logs every statement, `off` none.
+fn slow_threshold() -> Option<Duration> {
+    static THRESHOLD: OnceLock<Option<Duration>> = OnceLock::new();
+    *THRESHOLD.get_or_init(|| match env::var("DB_SLOW_QUERY_MS") {
+        Ok(v) if v.eq_ignore_ascii_case("off") => None,
+        Ok(v) => Some(Duration::from_millis(v.parse().unwrap_or(500))),
+        Err(_) => Some(Duration::from_millis(500)),
+    })
+}
+
+/// `DB_EXPLAIN_SLOW=on`, ignored outside `APP_ENV=development`: `EXPLAIN ANALYZE` runs the
+/// statement a second time, which production should not pay for.
+fn explain_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        let on = env::var("DB_EXPLAIN_SLOW")
+            .map(|v| matches!(v.to_ascii_lowercase().as_str(), "on" | "true" | "1"))
+            .unwrap_or(false);
+        on && !is_production() && slow_threshold().is_some()
+    })
+}
+
+/// Times one statement; `finish` records it and logs it when slow.
+pub(super) struct QueryTimer<'a> {
+    sql: &'a str,
+    types: Vec<DbType>,
+    /// A copy of the parameters, kept only when slow selects get explained.
+    explain_params: Option<Vec<DbParam>>,
+    started: Instant,
+}
+
+impl<'a> QueryTimer<'a> {
+    pub(super) fn start(sql: &'a str, params: &[DbParam]) -> Self {
+        Self {
+            sql,
+            types: params.iter().map(DbParam::ty).collect(),
+            explain_params: explain_enabled().then(|| params.to_vec()),
+            started: Instant::now(),
+        }
+    }
+
+    /// `rows` is the number of rows returned or affected, or the error.
+    pub(super) fn finish(self, rows: Result<u64, &sqlx::Error>) {
+        let elapsed = self.started.elapsed();
+        let slow = slow_threshold().is_some_and(|threshold| elapsed >= threshold);
+        let statement = compact_sql(self.sql);
+        let normalized = normalize(self.sql);
+        let mut key = fingerprint(&normalized);
+
+        {
+            let mut stats = stats().lock().unwrap_or_else(|e| e.into_inner());
+            if !stats.contains_key(&key) && stats.len() >= MAX_STATEMENTS {
+                key = "other".to_string();
+            }
+            let entry = stats.entry(key.clone()).or_insert_with(|| StatementStats {
+                statement: if key == "other" {
+                    "other".to_string()
+                } else {
+                    normalized
+                },
+                ..StatementStats::default()
+            });
+            entry.calls += 1;
+            match rows {
+                Ok(rows) => entry.rows += rows,
+                Err(_) => entry.errors += 1,
+            }
+            if slow {
+                entry.slow += 1;
+            }
+            entry.total += elapsed;
+            entry.max = entry.max.max(elapsed);
+        }
+
+        if !slow {
+            return;
+        }
+        let types = self
+            .types
+            .iter()
+            .map(|ty| ty.pg_name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let outcome = match rows {
+            Ok(rows) => format!("{} rows", rows),
+            Err(err) => format!("failed: {}", err),
+        };
+        eprintln!(
+            "Slow query {} ({} ms, {}) from {}: {} -- params: [{}]",
+            key,
+            elapsed.as_millis(),
+            outcome,
+            caller().unwrap_or_else(|| "unknown caller".to_string()),
+            statement,
+            types
+        );
+
+        if let Some(params) = self.explain_params
+            && rows.is_ok()
+            && is_select(self.sql)
+        {
+            explain(self.sql.to_string(), statement, params);
+        }
+    }
+}
+
+/// Only plain `SELECT`s are explained, since `EXPLAIN ANALYZE` executes the statement.
+/// Locking selects (`FOR UPDATE`, `FOR SHARE`) are left out too: run again they would
+/// take row locks on the primary.
+fn is_select(sql: &str) -> bool {
+    sql.split_whitespace()
+        .next()
+        .is_some_and(|word| word.eq_ignore_ascii_case("SELECT"))
+        && is_read_only(sql)
+}
+
+/// Logs the plan of a slow select, measured on the primary in the background. `sql` is
+/// the statement as it was run (comments and line breaks matter to the parser), and
+/// `statement` its compacted form for the log.
+///
+/// The plan is captured on a separate connection, outside any transaction the statement
+/// ran in: it does not see that transaction's uncommitted rows or snapshot, and caches
+/// are warm from the first run, so its timings can differ from the slow run.
+fn explain(sql: String, statement: String, params: Vec<DbParam>) {
+    let Some(pool) = POOL.get() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let sql = format!("EXPLAIN (ANALYZE, BUFFERS) {}", sql);
+        match bind_all(&sql, params).fetch_all(pool).await {
+            Ok(rows) => {
+                let plan = rows
+                    .iter()
+                    .filter_map(|row| sqlx::Row::try_get::<String, _>(row, 0).ok())
+                    .collect::<Vec<_>>()
+                    .join("\n  ");
+                eprintln!("EXPLAIN ANALYZE {}\n  {}", statement, plan);
+            }
+            Err(err) => eprintln!("EXPLAIN ANALYZE failed for {}: {}", statement, err),
+        }
+    });
+}
+
+/// The innermost function outside the db layer that the statement was run from, e.g.
+/// `domain::user::repo::UserRepo::get_one (./src/domain/user/repo.rs:160:9)`. Read from
+/// a backtrace, so only slow statements pay for it; without debug info there is no
+/// file and line.
+fn caller() -> Option<String> {
+    let trace = Backtrace::force_capture().to_string();
+    let prefix = concat!(env!("CARGO_CRATE_NAME"), "::");
+    let mut lines = trace.lines().map(str::trim).peekable();
+    while let Some(line) = lines.next() {
+        let Some((_, symbol)) = line.split_once(": ") else {
+            continue;
+        };
+        let symbol = symbol.trim_start_matches('<');
+        let Some(path) = symbol.strip_prefix(prefix) else {
+            continue;
+        };
+        if path.starts_with("db::") || path.starts_with("util::pagination::") {
+            continue;
+        }
+        let name = path
+            .split(" as ")
+            .next()
+            .unwrap_or(path)
+            .replace("::{{closure}}", "");
+        return Some(
+            match lines.peek().and_then(|next| next.strip_prefix("at ")) {
+                Some(location) => format!("{} ({})", name, location),
+                None => name,
+            },
+        );
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_only_plain_selects() {
+        assert!(is_select("SELECT \"id\" FROM \"USER\" WHERE \"id\" = $1"));
+        assert!(is_select("\n    select count(*) from t"));
+        assert!(!is_select("SELECT * FROM t WHERE id = $1 FOR UPDATE"));
+        assert!(!is_select("SELECT * FROM t FOR SHARE SKIP LOCKED"));
+        assert!(!is_select("INSERT INTO t (a) VALUES ($1) RETURNING id"));
+        assert!(!is_select(
+            "WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d"
+        ));
+        assert!(!is_select("VALUES (1)"));
+    }
+
+    #[test]
+    fn normalizes_literals_comments_and_whitespace() {
+        assert_eq!(
+            normalize(
+                "SELECT \"id\"\n  FROM \"USER\" -- by name\n  WHERE name = 'O''Brien' AND age > 42 LIMIT $1"
+            ),
+            "SELECT \"id\" FROM \"USER\" WHERE name = ? AND age > ? LIMIT $1"
+        );
+        assert_eq!(
+            normalize("SELECT /* hint */ x1, t2.col, 1.5e3 FROM t2 WHERE id = $12"),
+            "SELECT x1, t2.col, ? FROM t2 WHERE id = $12"
+        );
+        assert_eq!(normalize("SELECT 'a''"), "SELECT ?");
+    }
+
+    #[test]
+    fn fingerprints_normalized_statements() {
+        let a = fingerprint(&normalize("SELECT * FROM t WHERE id = 1"));
+        let b = fingerprint(&normalize("SELECT * FROM t\n  WHERE id = 2"));
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 16);
+
+        // Statements sharing a long prefix are still told apart.
+        let prefix = format!("SELECT {} FROM t", "c, ".repeat(100));
+        let c = fingerprint(&normalize(&format!("{} ORDER BY a", prefix)));
+        let d = fingerprint(&normalize(&format!("{} ORDER BY b", prefix)));
+        assert_ne!(c, d);
+    }
+}
@@ -0,0 +1,186 @@
+use std::env;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use sqlx::postgres::PgListener;
+use tokio::sync::broadcast;
+
+use super::{Executor, pool};
+
+/// A `NOTIFY` received on one of the listened channels.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub channel: String,
+    pub payload: String,
+}
+
+/// What a subscriber receives.
+#[derive(Debug, Clone)]
+pub enum Received {
+    Notification(Arc<Notification>),
+    /// Notifications may have been lost, either because the subscriber fell behind
+    /// or because the listener connection dropped. Clients should reload their state.
+    Gap,
+}
+
+struct Hub {
+    channels: Vec<String>,
+    sender: broadcast::Sender<Received>,
+}
+
+static HUB: OnceLock<Hub> = OnceLock::new();
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+fn hub() -> &'static Hub {
+    HUB.get_or_init(|| {
+        let channels = env::var("DB_LISTEN_CHANNELS")
+            .unwrap_or_else(|_| "user_changes".to_string())
+            .split(',')
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+        let capacity = env::var("DB_NOTIFY_BUFFER")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|&v| v > 0)
+            .unwrap_or(1024);
+        Hub {
+            channels,
+            sender: broadcast::channel(capacity).0,
+        }
+    })
+}
+
+/// Channels from `DB_LISTEN_CHANNELS` (comma-separated, default `user_changes`).
+pub fn channels() -> &'static [String] {
+    &hub().channels
+}
+
+/// Starts the listener task on the current runtime. It holds a dedicated connection
+/// outside of the pool, `LISTEN`s on every configured channel and republishes each
+/// notification to the subscribers of all worker threads. A lost connection is
+/// re-established with exponential backoff; once it is back, subscribers receive a
+/// `Gap` since anything sent in between is lost.
+pub fn start() -> Option<&'static [String]> {
+    let channels = channels();
+    if channels.is_empty() {
+        return None;
+    }
+    tokio::spawn(async move {
+        let mut backoff = Duration::from_millis(250);
+        let mut reconnecting = false;
+        loop {
+            match listen(channels).await {
+                Ok(mut listener) => {
+                    if reconnecting {
+                        eprintln!("DB listener reconnected");
+                        let _ = hub().sender.send(Received::Gap);
+                    }
+                    reconnecting = true;
+                    backoff = Duration::from_millis(250);
+                    run(&mut listener).await;
+                }
+                Err(err) => eprintln!("DB listener failed to connect: {}", err),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+    Some(channels)
+}
+
+async fn listen(channels: &[String]) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool()).await?;
+    listener
+        .listen_all(channels.iter().map(String::as_str))
+        .await?;
+    Ok(listener)
+}
+
+/// Forwards notifications until the connection fails.
+async fn run(listener: &mut PgListener) {
+    loop {
+        // `try_recv` reports a dropped connection as `Ok(None)`; the caller reconnects
+        // explicitly so the gap is announced and the backoff applies.
+        match listener.try_recv().await {
+            Ok(Some(notification)) => {
+                // Sending only fails when nobody is subscribed.
+                let _ = hub()
+                    .sender
+                    .send(Received::Notification(Arc::new(Notification {
+                        channel: notification.channel().to_string(),
+                        payload: notification.payload().to_string(),
+                    })));
+            }
+            Ok(None) => {
+                eprintln!("DB listener connection lost, reconnecting");
+                return;
+            }
+            Err(err) => {
+                eprintln!("DB listener error, reconnecting: {}", err);
+                return;
+            }
+        }
+    }
+}
+
+/// A subscription to some of the listened channels. Each subscriber has its own
+/// queue of `DB_NOTIFY_BUFFER` notifications (default 1024); a subscriber that falls
+/// further behind receives a `Gap`.
+pub struct Subscription {
+    channels: Vec<String>,
+    receiver: broadcast::Receiver<Received>,
+}
+
+#[allow(dead_code)]
+pub fn subscribe(channels: &[&str]) -> Subscription {
+    for channel in channels {
+        if !hub().channels.iter().any(|c| c == channel) {
+            eprintln!(
+                "Subscribed to '{}', which is not in DB_LISTEN_CHANNELS",
+                channel
+            );
+        }
+    }
+    Subscription {
+        channels: channels.iter().map(|c| c.to_string()).collect(),
+        receiver: hub().sender.subscribe(),
+    }
+}
+
+impl Subscription {
+    /// The next notification on one of the subscribed channels. Cancel-safe.
+    pub async fn recv(&mut self) -> Option<Received> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(Received::Notification(notification)) => {
+                    if self.channels.contains(&notification.channel) {
+                        return Some(Received::Notification(notification));
+                    }
+                }
+                Ok(Received::Gap) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    return Some(Received::Gap);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Sends a notification through `executor`. Run it on the transaction of a write so
+/// subscribers only hear about the change once it commits, and not at all on rollback.
+#[allow(dead_code)]
+pub async fn notify(
+    executor: &mut impl Executor,
+    channel: &str,
+    payload: &str,
+) -> Result<(), sqlx::Error> {
+    executor
+        .execute(
+            "SELECT pg_notify($1, $2)",
+            vec![channel.into(), payload.into()],
+        )
+        .await?;
+    Ok(())
+}
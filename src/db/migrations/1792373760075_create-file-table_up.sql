@@ -0,0 +1,11 @@
+CREATE TABLE
+    IF NOT EXISTS "FILE" (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid (),
+        filename TEXT NOT NULL,
+        content_type TEXT NOT NULL,
+        size BIGINT NOT NULL,
+        checksum_sha256 TEXT NOT NULL,
+        storage_backend TEXT NOT NULL,
+        storage_key TEXT NOT NULL UNIQUE,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now ()
+    );
@@ -0,0 +1,2 @@
+DROP TRIGGER IF EXISTS user_changes_notify ON "USER";
+DROP FUNCTION IF EXISTS notify_user_changes ();
@@ -0,0 +1,20 @@
+CREATE OR REPLACE FUNCTION notify_user_changes () RETURNS TRIGGER AS $$
+DECLARE
+    changed RECORD;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed := OLD;
+    ELSE
+        changed := NEW;
+    END IF;
+    PERFORM pg_notify(
+        'user_changes',
+        json_build_object('op', lower(TG_OP), 'id', changed.id, 'username', changed.username)::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER user_changes_notify
+AFTER INSERT OR UPDATE OR DELETE ON "USER"
+FOR EACH ROW EXECUTE FUNCTION notify_user_changes ();
@@ -0,0 +1,2 @@
+ALTER TABLE "USER"
+DROP COLUMN IF EXISTS created_at;
@@ -0,0 +1,2 @@
+ALTER TABLE "USER"
+ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now ();
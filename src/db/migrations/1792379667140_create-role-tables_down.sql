@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS "USER_ROLE";
+
+DROP TABLE IF EXISTS "ROLE";
@@ -0,0 +1,12 @@
+CREATE TABLE
+    IF NOT EXISTS "ROLE" (
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid (),
+        name TEXT NOT NULL UNIQUE
+    );
+
+CREATE TABLE
+    IF NOT EXISTS "USER_ROLE" (
+        user_id UUID NOT NULL REFERENCES "USER" (id) ON DELETE CASCADE,
+        role_id UUID NOT NULL REFERENCES "ROLE" (id) ON DELETE CASCADE,
+        PRIMARY KEY (user_id, role_id)
+    );
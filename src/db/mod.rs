@@ -1,19 +1,36 @@
-use sqlx::PgPool;
-use sqlx::Row;
-use sqlx::postgres::{PgPoolOptions, PgRow};
-use std::env;
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
+use sqlx::{PgExecutor, PgPool, Postgres, Row};
+use std::fs;
+use std::path::Path;
 use std::sync::OnceLock;
+use std::time::Duration;
 
-static POOL: OnceLock<PgPool> = OnceLock::new();
+use crate::telemetry::{Span, SpanKind};
+use instrument::QueryTimer;
 
-fn build_database_url() -> String {
-    let host = env::var("DB_HOST").unwrap_or_else(|_| "localhost".to_string());
-    let port = env::var("DB_PORT").unwrap_or_else(|_| "5432".to_string());
-    let user = env::var("DB_USER").unwrap_or_else(|_| "postgres".to_string());
-    let pass = env::var("DB_PASS").unwrap_or_else(|_| "postgres".to_string());
-    let name = env::var("DB_NAME").unwrap_or_else(|_| "postgres".to_string());
-    format!("postgres://{}:{}@{}:{}/{}", user, pass, host, port, name)
-}
+pub mod builder;
+mod instrument;
+pub mod listener;
+mod options;
+mod param;
+mod pools;
+mod transaction;
+
+#[allow(unused_imports)]
+pub use instrument::{StatementStats, statement_stats};
+#[allow(unused_imports)]
+pub use options::{ConnectFuture, ConnectHook, on_connect};
+#[allow(unused_imports)]
+pub use param::{DbParam, DbType, DbValue};
+#[allow(unused_imports)]
+pub use pools::{Reader, init_named_pools, named, named_pools, read_your_writes, reader};
+#[allow(unused_imports)]
+pub use transaction::{
+    IsolationLevel, Transaction, TxFuture, begin, begin_with, is_retryable, transaction,
+};
+
+static POOL: OnceLock<PgPool> = OnceLock::new();
 
 pub async fn init_pool() -> Result<&'static PgPool, sqlx::Error> {
     // ANSI color codes
@@ -27,20 +44,15 @@ pub async fn init_pool() -> Result<&'static PgPool, sqlx::Error> {
         return Ok(pool);
     }
 
-    let database_url = build_database_url();
-    let max_connections = env::var("DB_MAX_CONNECTIONS")
-        .ok()
-        .and_then(|v| v.parse::<u32>().ok())
-        .unwrap_or(10);
+    let connect_options = options::connect_options("primary")?;
+    let pool_options = options::pool_options("primary", Duration::from_secs(30));
+    let max_connections = pool_options.get_max_connections();
 
     println!("{CYAN}Connecting to database...{RESET}");
 
     println!("{GREEN}Max pool connections:{RESET} {YELLOW}{max_connections}{RESET}");
 
-    let pool = PgPoolOptions::new()
-        .max_connections(max_connections)
-        .connect(&database_url)
-        .await?;
+    let pool = pool_options.connect_with(connect_options).await?;
 
     println!("{CYAN}DB pool initialized successfully!{RESET}");
 
@@ -48,6 +60,7 @@ pub async fn init_pool() -> Result<&'static PgPool, sqlx::Error> {
     Ok(POOL.get().expect("DB pool initialized"))
 }
 
+/// The primary pool, for writes and anything that must see the latest data.
 #[allow(dead_code)]
 pub fn pool() -> &'static PgPool {
     POOL.get().expect("DB pool not initialized")
@@ -130,33 +143,259 @@ pub async fn applied_seed_ids() -> Result<Vec<String>, sqlx::Error> {
         .collect())
 }
 
+/// Ids of the `<id>_<name>_up.sql` files in `dir` that are not recorded in `_migrations`.
+#[allow(dead_code)]
+pub async fn pending_migration_ids(dir: &Path) -> Result<Vec<String>, sqlx::Error> {
+    let mut on_disk = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if name.ends_with("_up.sql")
+            && let Some((id, _)) = name.split_once('_')
+        {
+            on_disk.push(id.to_string());
+        }
+    }
+    on_disk.sort();
+
+    let applied = applied_migration_ids().await?;
+    Ok(on_disk
+        .into_iter()
+        .filter(|id| !applied.contains(id))
+        .collect())
+}
+
+/// Runs a script over the simple query protocol, so migrations may hold several
+/// statements (e.g. a function and the trigger that uses it).
 #[allow(dead_code)]
 pub async fn execute_sql(sql: &str) -> Result<(), sqlx::Error> {
-    sqlx::query(sql).execute(pool()).await?;
+    sqlx::raw_sql(sql).execute(pool()).await?;
     Ok(())
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub enum DbParam {
-    Int32(i32),
-    Int64(i64),
-    Float64(f64),
-    Bool(bool),
-    Text(String),
+/// Collapses the whitespace of multi-line SQL so it reads well in a trace or log.
+fn compact_sql(sql: &str) -> String {
+    sql.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-#[allow(dead_code)]
-pub async fn query(sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+fn query_span(sql: &str) -> Span {
+    let mut span = Span::child("db.query", SpanKind::Client);
+    if span.is_recording() {
+        let statement = compact_sql(sql);
+        let operation = statement
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_ascii_uppercase();
+        span.set_name(format!("db.query {}", operation));
+        span.set_attribute("db.system", "postgresql");
+        span.set_attribute("db.operation", operation);
+        span.set_attribute("db.statement", statement);
+    }
+    span
+}
+
+fn bind_all<'q>(sql: &'q str, params: Vec<DbParam>) -> Query<'q, Postgres, PgArguments> {
     let mut q = sqlx::query(sql);
     for param in params {
-        q = match param {
-            DbParam::Int32(v) => q.bind(v),
-            DbParam::Int64(v) => q.bind(v),
-            DbParam::Float64(v) => q.bind(v),
-            DbParam::Bool(v) => q.bind(v),
-            DbParam::Text(v) => q.bind(v),
-        };
+        q = param.bind(q);
+    }
+    q
+}
+
+/// Runs `sql` on `executor` (the pool or a connection) and returns every row.
+async fn fetch_all<'e, E: PgExecutor<'e>>(
+    executor: E,
+    sql: &str,
+    params: Vec<DbParam>,
+) -> Result<Vec<PgRow>, sqlx::Error> {
+    let mut span = query_span(sql);
+    let timer = QueryTimer::start(sql, &params);
+    let result = bind_all(sql, params).fetch_all(executor).await;
+    timer.finish(result.as_ref().map(|rows| rows.len() as u64));
+    match &result {
+        Ok(rows) => span.set_attribute("db.response.returned_rows", rows.len() as i64),
+        Err(err) => span.set_error(err.to_string()),
+    }
+    result
+}
+
+/// Runs `sql` on `executor` and returns the number of affected rows.
+async fn execute<'e, E: PgExecutor<'e>>(
+    executor: E,
+    sql: &str,
+    params: Vec<DbParam>,
+) -> Result<u64, sqlx::Error> {
+    let mut span = query_span(sql);
+    let timer = QueryTimer::start(sql, &params);
+    let result = bind_all(sql, params)
+        .execute(executor)
+        .await
+        .map(|done| done.rows_affected());
+    timer.finish(result.as_ref().copied());
+    match &result {
+        Ok(affected) => span.set_attribute("db.response.affected_rows", *affected as i64),
+        Err(err) => span.set_error(err.to_string()),
+    }
+    result
+}
+
+#[allow(dead_code)]
+pub async fn query(sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+    fetch_all(pool(), sql, params).await
+}
+
+/// Like `query`, mapping every row into `T`.
+#[allow(dead_code)]
+pub async fn query_as<T: FromRow>(sql: &str, params: Vec<DbParam>) -> Result<Vec<T>, sqlx::Error> {
+    pool().query_as(sql, params).await
+}
+
+/// Like `query`, mapping the first row into `T`, if there is one.
+#[allow(dead_code)]
+pub async fn query_optional_as<T: FromRow>(
+    sql: &str,
+    params: Vec<DbParam>,
+) -> Result<Option<T>, sqlx::Error> {
+    pool().query_optional_as(sql, params).await
+}
+
+/// A type built from a result row, by column name. Columns it does not read are ignored.
+pub trait FromRow: Sized {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error>;
+}
+
+/// Reads `column`, or `None` when the statement did not select it (e.g. a sparse
+/// fieldset). A selected `NULL` is still an error unless `T` is itself an `Option`.
+pub fn try_get_selected<T>(row: &PgRow, column: &str) -> Result<Option<T>, sqlx::Error>
+where
+    T: for<'r> sqlx::Decode<'r, Postgres> + sqlx::Type<Postgres>,
+{
+    match row.try_get(column) {
+        Ok(value) => Ok(Some(value)),
+        Err(sqlx::Error::ColumnNotFound(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Where repository methods run their SQL: `db::pool()` for autocommit statements, or
+/// a `Transaction` (or savepoint) so they take part in it. Takes `&mut` borrows too,
+/// so one executor can be passed down to several calls.
+#[allow(async_fn_in_trait)]
+pub trait Executor {
+    async fn query(&mut self, sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error>;
+
+    async fn execute(&mut self, sql: &str, params: Vec<DbParam>) -> Result<u64, sqlx::Error>;
+
+    async fn query_as<T: FromRow>(
+        &mut self,
+        sql: &str,
+        params: Vec<DbParam>,
+    ) -> Result<Vec<T>, sqlx::Error> {
+        self.query(sql, params)
+            .await?
+            .iter()
+            .map(T::from_row)
+            .collect()
+    }
+
+    /// Maps the first row, failing with `RowNotFound` when there is none. Meant for
+    /// statements that always return a row, such as `INSERT ... RETURNING`.
+    async fn query_one_as<T: FromRow>(
+        &mut self,
+        sql: &str,
+        params: Vec<DbParam>,
+    ) -> Result<T, sqlx::Error> {
+        self.query_optional_as(sql, params)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn query_optional_as<T: FromRow>(
+        &mut self,
+        sql: &str,
+        params: Vec<DbParam>,
+    ) -> Result<Option<T>, sqlx::Error> {
+        self.query(sql, params)
+            .await?
+            .first()
+            .map(T::from_row)
+            .transpose()
+    }
+}
+
+/// Writes on the primary (`execute`, and `query` with anything but a plain `SELECT`,
+/// e.g. `INSERT ... RETURNING`) count as writes for `read_your_writes`.
+impl Executor for &PgPool {
+    async fn query(&mut self, sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+        if !is_read_only(sql) && is_primary(self) {
+            pools::note_write();
+        }
+        fetch_all(*self, sql, params).await
+    }
+
+    async fn execute(&mut self, sql: &str, params: Vec<DbParam>) -> Result<u64, sqlx::Error> {
+        if is_primary(self) {
+            pools::note_write();
+        }
+        execute(*self, sql, params).await
+    }
+}
+
+/// Whether `pool` is the primary or a clone of it. Clones share the pool's state, so
+/// its `options` are compared rather than the `PgPool` handles.
+fn is_primary(pool: &PgPool) -> bool {
+    POOL.get()
+        .is_some_and(|primary| std::ptr::eq(primary.options(), pool.options()))
+}
+
+/// A `SELECT` (or `VALUES`/`TABLE`/`SHOW`) without a locking clause such as
+/// `FOR UPDATE`, i.e. a statement a replica could run.
+fn is_read_only(sql: &str) -> bool {
+    let words = sql
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('(').to_ascii_uppercase())
+        .collect::<Vec<_>>();
+    let reads = words
+        .first()
+        .is_some_and(|word| matches!(word.as_str(), "SELECT" | "VALUES" | "TABLE" | "SHOW"));
+    let locks = words.windows(2).any(|pair| {
+        pair[0] == "FOR" && matches!(pair[1].as_str(), "UPDATE" | "SHARE" | "NO" | "KEY")
+    });
+    reads && !locks
+}
+
+impl<E: Executor + ?Sized> Executor for &mut E {
+    async fn query(&mut self, sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+        (**self).query(sql, params).await
+    }
+
+    async fn execute(&mut self, sql: &str, params: Vec<DbParam>) -> Result<u64, sqlx::Error> {
+        (**self).execute(sql, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plain_selects_are_read_only() {
+        assert!(is_read_only("SELECT id FROM \"USER\" WHERE id = $1"));
+        assert!(is_read_only("\n  select count(*) AS total FROM t"));
+        assert!(is_read_only("(SELECT 1) UNION (SELECT 2)"));
+        assert!(is_read_only("VALUES (1), (2)"));
+        assert!(is_read_only("SELECT format FROM t"));
+
+        assert!(!is_read_only("SELECT * FROM t WHERE id = $1 FOR UPDATE"));
+        assert!(!is_read_only(
+            "SELECT * FROM t FOR NO KEY UPDATE SKIP LOCKED"
+        ));
+        assert!(!is_read_only("select * from t for share"));
+        assert!(!is_read_only("INSERT INTO t (a) VALUES ($1) RETURNING id"));
+        assert!(!is_read_only(
+            "WITH d AS (DELETE FROM t RETURNING id) SELECT * FROM d"
+        ));
+        assert!(!is_read_only("UPDATE t SET a = 1"));
+        assert!(!is_read_only(""));
     }
-    q.fetch_all(pool()).await
 }
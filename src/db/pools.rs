@@ -0,0 +1,254 @@
+use std::cell::Cell;
+use std::env;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sqlx::PgPool;
+use sqlx::postgres::PgRow;
+
+use super::options::{connect_options, pool_options};
+use super::{DbParam, Executor, fetch_all, pool};
+
+static NAMED: OnceLock<Vec<NamedPool>> = OnceLock::new();
+static NEXT_REPLICA: AtomicUsize = AtomicUsize::new(0);
+
+tokio::task_local! {
+    /// Whether the current request has run a statement on the primary.
+    static WROTE: Cell<bool>;
+}
+
+/// A pool besides the primary, configured through `DB_<NAME>_*` (see `options`).
+struct NamedPool {
+    name: String,
+    pool: PgPool,
+    replica: bool,
+    /// Unix time (ms) until which reads skip this replica after a connection failure.
+    down_until: AtomicU64,
+}
+
+fn list(var: &str) -> Vec<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// How long a replica that failed to connect is left out of the read rotation.
+fn replica_retry() -> Duration {
+    static RETRY: OnceLock<Duration> = OnceLock::new();
+    *RETRY.get_or_init(|| {
+        Duration::from_secs(
+            env::var("DB_REPLICA_RETRY_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+        )
+    })
+}
+
+/// Creates the pools named in `DB_REPLICAS` (read replicas) and `DB_POOLS` (other
+/// named pools, e.g. `analytics`). They connect lazily, so a replica that is down at
+/// startup only sends reads to the primary until it comes up.
+pub async fn init_named_pools() -> Result<Vec<(&'static str, &'static PgPool)>, sqlx::Error> {
+    if NAMED.get().is_none() {
+        let replicas = list("DB_REPLICAS");
+        let mut names = replicas.clone();
+        names.extend(
+            list("DB_POOLS")
+                .into_iter()
+                .filter(|n| !replicas.contains(n)),
+        );
+
+        let mut pools = Vec::new();
+        for name in names {
+            // A short default acquire timeout, so reads fall back to the primary quickly
+            // while a replica is down.
+            let pool = pool_options(&name, Duration::from_secs(5))
+                .connect_lazy_with(connect_options(&name)?);
+            pools.push(NamedPool {
+                replica: replicas.contains(&name),
+                name,
+                pool,
+                down_until: AtomicU64::new(0),
+            });
+        }
+        let _ = NAMED.set(pools);
+    }
+    Ok(named_pools())
+}
+
+/// The pools created by `init_named_pools`, by name.
+pub fn named_pools() -> Vec<(&'static str, &'static PgPool)> {
+    NAMED
+        .get()
+        .map(|pools| {
+            pools
+                .iter()
+                .map(|named| (named.name.as_str(), &named.pool))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The pool named `name` (case-insensitive), or the primary when it is not configured.
+#[allow(dead_code)]
+pub fn named(name: &str) -> &'static PgPool {
+    NAMED
+        .get()
+        .and_then(|pools| {
+            pools
+                .iter()
+                .find(|named| named.name.eq_ignore_ascii_case(name))
+        })
+        .map(|named| &named.pool)
+        .unwrap_or_else(pool)
+}
+
+/// Runs `fut` (one request) so that once it has used the primary, its reads through
+/// `reader()` stay on the primary and see its own writes despite replication lag.
+pub async fn read_your_writes<F: Future>(fut: F) -> F::Output {
+    WROTE.scope(Cell::new(false), fut).await
+}
+
+pub(super) fn note_write() {
+    let _ = WROTE.try_with(|wrote| wrote.set(true));
+}
+
+fn has_written() -> bool {
+    WROTE.try_with(Cell::get).unwrap_or(false)
+}
+
+/// The next replica in rotation that is not marked down.
+fn replica() -> Option<&'static NamedPool> {
+    if has_written() {
+        return None;
+    }
+    let replicas = NAMED
+        .get()?
+        .iter()
+        .filter(|named| named.replica)
+        .collect::<Vec<_>>();
+    if replicas.is_empty() {
+        return None;
+    }
+    let now = now_ms();
+    let start = NEXT_REPLICA.fetch_add(1, Ordering::Relaxed);
+    (0..replicas.len())
+        .map(|i| replicas[(start + i) % replicas.len()])
+        .find(|named| named.down_until.load(Ordering::Relaxed) <= now)
+}
+
+/// Errors meaning the server could not be reached, rather than that the query failed.
+fn is_unavailable(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => true,
+        // connection_exception, admin_shutdown, cannot_connect_now (standby starting)
+        sqlx::Error::Database(db) => db
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code == "57P01" || code == "57P03"),
+        _ => false,
+    }
+}
+
+/// Where read-only statements run: a replica from `DB_REPLICAS` in rotation, or the
+/// primary when there is none, all are down, or the request has already written (see
+/// `read_your_writes`). Statements that fail because a replica is unreachable are
+/// retried on the primary. `execute` always runs on the primary.
+#[derive(Debug, Clone, Copy)]
+pub struct Reader;
+
+pub fn reader() -> Reader {
+    Reader
+}
+
+impl Executor for Reader {
+    async fn query(&mut self, sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+        if let Some(replica) = replica() {
+            match fetch_all(&replica.pool, sql, params.clone()).await {
+                Err(err) if is_unavailable(&err) => {
+                    eprintln!(
+                        "DB replica '{}' unavailable, reading from the primary: {}",
+                        replica.name, err
+                    );
+                    let until = now_ms() + replica_retry().as_millis() as u64;
+                    replica.down_until.store(until, Ordering::Relaxed);
+                }
+                result => return result,
+            }
+        }
+        fetch_all(pool(), sql, params).await
+    }
+
+    async fn execute(&mut self, sql: &str, params: Vec<DbParam>) -> Result<u64, sqlx::Error> {
+        pool().execute(sql, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::init_pool;
+    use sqlx::Row;
+
+    async fn server_port(mut db: impl Executor) -> i32 {
+        let rows = db
+            .query("SELECT inet_server_port()", vec![])
+            .await
+            .expect("query runs");
+        rows[0].get(0)
+    }
+
+    /// Needs a streaming replica of the primary, e.g. the one from the README on port
+    /// 5433: `DB_REPLICAS=replica DB_REPLICA_PORT=5433 cargo test replica -- --ignored`.
+    #[tokio::test]
+    #[ignore = "needs a running replica"]
+    async fn replica_reads_until_the_request_writes() {
+        let _ = dotenv::dotenv();
+        init_pool().await.expect("primary reachable");
+        init_named_pools().await.expect("replica configured");
+
+        read_your_writes(async {
+            let primary = server_port(pool()).await;
+            let replica = server_port(reader()).await;
+            assert_ne!(primary, replica, "reader() should use the replica");
+
+            // A plain select on the primary does not pin the request.
+            assert_eq!(server_port(reader()).await, replica);
+
+            // A write through a clone of the primary does.
+            let clone = pool().clone();
+            (&clone).execute("SELECT 1", vec![]).await.expect("runs");
+            assert_eq!(server_port(reader()).await, primary);
+        })
+        .await;
+
+        // `INSERT ... RETURNING` through `query` does too.
+        read_your_writes(async {
+            let replica = server_port(reader()).await;
+            let insert =
+                "INSERT INTO _seeders (id, name) VALUES ('replica-test', 'test') RETURNING id";
+            pool().query(insert, vec![]).await.expect("runs");
+            assert_ne!(server_port(reader()).await, replica);
+        })
+        .await;
+        pool()
+            .execute("DELETE FROM _seeders WHERE id = 'replica-test'", vec![])
+            .await
+            .expect("runs");
+    }
+}
@@ -0,0 +1,161 @@
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use sqlx::postgres::PgRow;
+use sqlx::{Acquire, Postgres};
+
+use super::{DbParam, Executor, pool};
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn as_sql(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// A transaction holding one pooled connection, or a savepoint inside one. Dropping it
+/// without calling `commit` rolls it back: the `ROLLBACK` (or `ROLLBACK TO SAVEPOINT`)
+/// is queued on the connection and runs before the connection is used again.
+pub struct Transaction<'c> {
+    inner: sqlx::Transaction<'c, Postgres>,
+}
+
+/// Starts a transaction at the server's default isolation level (`READ COMMITTED`).
+#[allow(dead_code)]
+pub async fn begin() -> Result<Transaction<'static>, sqlx::Error> {
+    super::pools::note_write();
+    Ok(Transaction {
+        inner: pool().begin().await?,
+    })
+}
+
+#[allow(dead_code)]
+pub async fn begin_with(isolation: IsolationLevel) -> Result<Transaction<'static>, sqlx::Error> {
+    let mut tx = begin().await?;
+    let sql = format!("SET TRANSACTION ISOLATION LEVEL {}", isolation.as_sql());
+    tx.execute(&sql, vec![]).await?;
+    Ok(tx)
+}
+
+#[allow(dead_code)]
+impl Transaction<'_> {
+    pub async fn query(
+        &mut self,
+        sql: &str,
+        params: Vec<DbParam>,
+    ) -> Result<Vec<PgRow>, sqlx::Error> {
+        super::fetch_all(&mut *self.inner, sql, params).await
+    }
+
+    pub async fn execute(&mut self, sql: &str, params: Vec<DbParam>) -> Result<u64, sqlx::Error> {
+        super::execute(&mut *self.inner, sql, params).await
+    }
+
+    /// Opens a savepoint. Committing it releases the savepoint; dropping or rolling it
+    /// back undoes only what ran since, and the outer transaction stays usable.
+    pub async fn savepoint(&mut self) -> Result<Transaction<'_>, sqlx::Error> {
+        Ok(Transaction {
+            inner: (&mut self.inner).begin().await?,
+        })
+    }
+
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        self.inner.commit().await
+    }
+
+    pub async fn rollback(self) -> Result<(), sqlx::Error> {
+        self.inner.rollback().await
+    }
+}
+
+impl Executor for Transaction<'_> {
+    async fn query(&mut self, sql: &str, params: Vec<DbParam>) -> Result<Vec<PgRow>, sqlx::Error> {
+        Transaction::query(self, sql, params).await
+    }
+
+    async fn execute(&mut self, sql: &str, params: Vec<DbParam>) -> Result<u64, sqlx::Error> {
+        Transaction::execute(self, sql, params).await
+    }
+}
+
+/// `serialization_failure` and `deadlock_detected`: the transaction lost a race and
+/// succeeds when run again from the start.
+pub fn is_retryable(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .and_then(|err| err.code())
+        .is_some_and(|code| code == "40001" || code == "40P01")
+}
+
+/// Retries after a serialization failure, from `DB_TX_MAX_RETRIES` (default 3).
+fn max_retries() -> u32 {
+    static MAX_RETRIES: OnceLock<u32> = OnceLock::new();
+    *MAX_RETRIES.get_or_init(|| {
+        env::var("DB_TX_MAX_RETRIES")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(3)
+    })
+}
+
+/// Delay before rerun `attempt` (from 1): 20ms, doubling up to 640ms.
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(10 << attempt.min(6))
+}
+
+pub type TxFuture<'t, T> = Pin<Box<dyn Future<Output = Result<T, sqlx::Error>> + 't>>;
+
+/// Runs `work` in a transaction and commits it, or rolls it back when `work` fails.
+/// A serialization failure or deadlock, from `work` or from the commit, reruns the
+/// whole closure in a fresh transaction after a short backoff, so `work` must not have
+/// side effects outside the database.
+#[allow(dead_code)]
+pub async fn transaction<T, F>(isolation: IsolationLevel, mut work: F) -> Result<T, sqlx::Error>
+where
+    F: for<'t> FnMut(&'t mut Transaction<'static>) -> TxFuture<'t, T>,
+{
+    let mut attempt = 0;
+    loop {
+        let result = async {
+            let mut tx = begin_with(isolation).await?;
+            let value = work(&mut tx).await?;
+            tx.commit().await?;
+            Ok(value)
+        }
+        .await;
+        match result {
+            Err(err) if is_retryable(&err) && attempt < max_retries() => {
+                attempt += 1;
+                tokio::time::sleep(backoff(attempt)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_a_cap() {
+        assert_eq!(backoff(1), Duration::from_millis(20));
+        assert_eq!(backoff(2), Duration::from_millis(40));
+        assert_eq!(backoff(6), Duration::from_millis(640));
+        assert_eq!(backoff(7), Duration::from_millis(640));
+        assert_eq!(backoff(u32::MAX), Duration::from_millis(640));
+    }
+}
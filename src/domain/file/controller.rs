@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use crate::primitives::http::error::AppError;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::route;
+use crate::routing::{Route, RouteParams};
+use crate::storage::signed_url;
+use crate::util::url::percent_encode;
+
+use super::dto::FileRecord;
+use super::repo::FileRepo;
+use super::service::FileService;
+use uuid::Uuid;
+
+pub struct FileController;
+
+impl FileController {
+    pub fn routes() -> Vec<Route> {
+        vec![
+            Route::new("POST", &["file"], vec![route!(FileController::upload)]),
+            Route::new(
+                "GET",
+                &["file", ":id"],
+                vec![route!(FileController::get_one)],
+            ),
+            Route::new(
+                "GET",
+                &["file", ":id", "download"],
+                vec![route!(FileController::download)],
+            ),
+            Route::new(
+                "DELETE",
+                &["file", ":id"],
+                vec![route!(FileController::delete)],
+            ),
+        ]
+    }
+
+    fn parse_id(params: &RouteParams) -> Result<Uuid, AppError> {
+        let id = params.get("id").unwrap_or("");
+        Uuid::parse_str(id).map_err(|_| {
+            AppError::BadRequest(format!(
+                "Invalid UUID for file id: '{}'. Must be a valid UUID string.",
+                id
+            ))
+        })
+    }
+
+    fn download_path(id: &Uuid) -> String {
+        format!("/file/{}/download", id)
+    }
+
+    /// Metadata plus a freshly signed download link.
+    fn to_json(record: &FileRecord) -> String {
+        let mut value = serde_json::to_value(record).unwrap_or_default();
+        value["download_url"] =
+            signed_url::sign(&Self::download_path(&record.id), signed_url::default_ttl()).into();
+        value.to_string()
+    }
+
+    fn json_headers() -> HashMap<String, String> {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        headers
+    }
+
+    /// `multipart/form-data` with the content in a `file` part. An expected SHA-256 can
+    /// be sent as an `X-Checksum-Sha256` header or a `sha256` field.
+    pub async fn upload(request: &mut Request, _params: &RouteParams) -> Response {
+        let header_checksum = request.header("X-Checksum-Sha256").map(str::to_string);
+        let form = match request.form() {
+            Ok(form) => form,
+            Err(e) => return e.into(),
+        };
+        let expected = header_checksum.or_else(|| form.get("sha256").map(str::to_string));
+        let Some(file) = form.take_file("file") else {
+            return AppError::BadRequest("Missing multipart file part 'file'.".to_string()).into();
+        };
+
+        let service = FileService::new(FileRepo::new());
+        match service.upload(file, expected).await {
+            Ok(record) => {
+                let mut headers = Self::json_headers();
+                headers.insert("Location".to_string(), format!("/file/{}", record.id));
+                Response {
+                    status_code: 201,
+                    headers,
+                    body: Self::to_json(&record),
+                    stream: None,
+                }
+            }
+            Err(e) => e.into(),
+        }
+    }
+
+    pub async fn get_one(_request: &mut Request, params: &RouteParams) -> Response {
+        let _id = match Self::parse_id(params) {
+            Ok(id) => id,
+            Err(e) => return e.into(),
+        };
+
+        let service = FileService::new(FileRepo::new());
+        match service.get_one(_id).await {
+            Ok(record) => Response {
+                status_code: 200,
+                headers: Self::json_headers(),
+                body: Self::to_json(&record),
+                stream: None,
+            },
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Only reachable through a link signed by `get_one`/`upload` that has not expired.
+    pub async fn download(request: &mut Request, params: &RouteParams) -> Response {
+        let _id = match Self::parse_id(params) {
+            Ok(id) => id,
+            Err(e) => return e.into(),
+        };
+        if let Err(e) = signed_url::verify(
+            &Self::download_path(&_id),
+            request.query_params.get("expires").map(String::as_str),
+            request.query_params.get("signature").map(String::as_str),
+        ) {
+            return e.into();
+        }
+
+        let service = FileService::new(FileRepo::new());
+        let (record, blob) = match service.download(_id).await {
+            Ok(found) => found,
+            Err(e) => return e.into(),
+        };
+
+        // The quoted fallback only keeps printable ASCII; `filename*` carries the real name.
+        let fallback: String = record
+            .filename
+            .chars()
+            .map(|c| {
+                if c.is_ascii_graphic() && c != '"' && c != '\\' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), record.content_type.clone());
+        headers.insert("Content-Length".to_string(), blob.size.to_string());
+        headers.insert(
+            "Content-Disposition".to_string(),
+            format!(
+                "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+                fallback,
+                percent_encode(&record.filename)
+            ),
+        );
+        headers.insert("X-Content-Type-Options".to_string(), "nosniff".to_string());
+        headers.insert(
+            "ETag".to_string(),
+            format!("\"{}\"", record.checksum_sha256),
+        );
+        headers.insert(
+            "X-Checksum-Sha256".to_string(),
+            record.checksum_sha256.clone(),
+        );
+        headers.insert("Cache-Control".to_string(), "private, no-store".to_string());
+        Response::streaming(200, headers, blob.stream)
+    }
+
+    pub async fn delete(_request: &mut Request, params: &RouteParams) -> Response {
+        let _id = match Self::parse_id(params) {
+            Ok(id) => id,
+            Err(e) => return e.into(),
+        };
+
+        let service = FileService::new(FileRepo::new());
+        match service.delete(_id).await {
+            Ok(_) => Response {
+                status_code: 204,
+                headers: HashMap::new(),
+                body: "".to_string(),
+                stream: None,
+            },
+            Err(e) => e.into(),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::postgres::PgRow;
+use uuid::Uuid;
+
+use crate::db::FromRow;
+
+/// Metadata of a stored file. The blob itself lives in the configured `storage` backend.
+#[derive(Serialize)]
+pub struct FileRecord {
+    pub id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub checksum_sha256: String,
+    #[serde(skip)]
+    pub storage_backend: String,
+    #[serde(skip)]
+    pub storage_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl FromRow for FileRecord {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            filename: row.try_get("filename")?,
+            content_type: row.try_get("content_type")?,
+            size: row.try_get("size")?,
+            checksum_sha256: row.try_get("checksum_sha256")?,
+            storage_backend: row.try_get("storage_backend")?,
+            storage_key: row.try_get("storage_key")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+pub struct NewFile {
+    pub id: Uuid,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub checksum_sha256: String,
+    pub storage_backend: String,
+    pub storage_key: String,
+}
@@ -0,0 +1,91 @@
+pub struct FileRepo;
+use uuid::Uuid;
+
+use super::dto::{FileRecord, NewFile};
+use crate::db::Executor;
+
+/// Columns shared by every query.
+const COLUMNS: &str = "
+    id,
+    filename,
+    content_type,
+    size,
+    checksum_sha256,
+    storage_backend,
+    storage_key,
+    created_at
+";
+
+impl FileRepo {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub async fn create(
+        &self,
+        mut db: impl Executor,
+        file: NewFile,
+    ) -> Result<FileRecord, sqlx::Error> {
+        let sql = format!(
+            "
+            INSERT
+            INTO
+                \"FILE\" (id, filename, content_type, size, checksum_sha256, storage_backend, storage_key)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING
+                {COLUMNS}
+            "
+        );
+        db.query_one_as(
+            &sql,
+            vec![
+                file.id.into(),
+                file.filename.into(),
+                file.content_type.into(),
+                file.size.into(),
+                file.checksum_sha256.into(),
+                file.storage_backend.into(),
+                file.storage_key.into(),
+            ],
+        )
+        .await
+    }
+
+    pub async fn get_one(
+        &self,
+        mut db: impl Executor,
+        id: Uuid,
+    ) -> Result<Option<FileRecord>, sqlx::Error> {
+        let sql = format!(
+            "
+            SELECT
+                {COLUMNS}
+            FROM
+                \"FILE\"
+            WHERE
+                id = $1
+            "
+        );
+        db.query_optional_as(&sql, vec![id.into()]).await
+    }
+
+    pub async fn delete(
+        &self,
+        mut db: impl Executor,
+        id: Uuid,
+    ) -> Result<Option<FileRecord>, sqlx::Error> {
+        let sql = format!(
+            "
+            DELETE
+            FROM
+                \"FILE\"
+            WHERE
+                id = $1
+            RETURNING
+                {COLUMNS}
+            "
+        );
+        db.query_optional_as(&sql, vec![id.into()]).await
+    }
+}
@@ -0,0 +1,122 @@
+use super::dto::{FileRecord, NewFile};
+use super::repo::FileRepo;
+use crate::db;
+use crate::primitives::http::error::AppError;
+use crate::primitives::http::form::FormFile;
+use crate::storage::{self, Blob, BlobSource, StorageError};
+use uuid::Uuid;
+
+pub struct FileService {
+    repo: FileRepo,
+}
+
+impl FileService {
+    pub fn new(repo: FileRepo) -> Self {
+        Self { repo }
+    }
+
+    /// Verifies the upload against `expected_sha256` (when the client sent one), stores
+    /// the blob, then records its metadata. The blob is removed again if the insert fails.
+    pub async fn upload(
+        &self,
+        file: FormFile,
+        expected_sha256: Option<String>,
+    ) -> Result<FileRecord, AppError> {
+        let bytes;
+        let source = match file.path() {
+            Some(path) => BlobSource::File(path),
+            None => {
+                bytes = file
+                    .bytes()
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Failed to read upload: {}", e)))?;
+                BlobSource::Bytes(&bytes)
+            }
+        };
+
+        let checksum = storage::sha256(source)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to hash upload: {}", e)))?;
+        if let Some(expected) = expected_sha256
+            && !expected.trim().eq_ignore_ascii_case(&checksum)
+        {
+            return Err(AppError::BadRequest(format!(
+                "Checksum mismatch: expected sha256 {}, received content hashes to {}.",
+                expected.trim(),
+                checksum
+            )));
+        }
+
+        let id = Uuid::new_v4();
+        let key = format!("files/{}", id);
+        let store = storage::store();
+        store.put(&key, source, &checksum).await?;
+
+        let record = self
+            .repo
+            .create(
+                db::pool(),
+                NewFile {
+                    id,
+                    filename: file.filename.clone(),
+                    content_type: file.content_type.clone(),
+                    size: file.size as i64,
+                    checksum_sha256: checksum,
+                    storage_backend: store.name().to_string(),
+                    storage_key: key.clone(),
+                },
+            )
+            .await;
+
+        match record {
+            Ok(record) => Ok(record),
+            Err(err) => {
+                if let Err(cleanup) = store.delete(&key).await {
+                    eprintln!("Failed to remove orphaned blob {}: {}", key, cleanup);
+                }
+                Err(err.into())
+            }
+        }
+    }
+
+    fn not_found(id: Uuid) -> AppError {
+        AppError::NotFound(format!("File {} not found.", id))
+    }
+
+    pub async fn get_one(&self, id: Uuid) -> Result<FileRecord, AppError> {
+        self.repo
+            .get_one(db::reader(), id)
+            .await?
+            .ok_or_else(|| Self::not_found(id))
+    }
+
+    pub async fn download(&self, id: Uuid) -> Result<(FileRecord, Blob), AppError> {
+        let record = self.get_one(id).await?;
+        let store = storage::store();
+        if record.storage_backend != store.name() {
+            return Err(AppError::Internal(format!(
+                "File {} is stored in the '{}' backend, but '{}' is configured.",
+                record.id,
+                record.storage_backend,
+                store.name()
+            )));
+        }
+        let blob = store.get(&record.storage_key).await?;
+        Ok((record, blob))
+    }
+
+    /// Deletes the metadata first so the file disappears even if the backend is down;
+    /// a blob that cannot be removed is logged and left behind.
+    pub async fn delete(&self, id: Uuid) -> Result<(), AppError> {
+        let record = self
+            .repo
+            .delete(db::pool(), id)
+            .await?
+            .ok_or_else(|| Self::not_found(id))?;
+        match storage::store().delete(&record.storage_key).await {
+            Ok(()) | Err(StorageError::NotFound(_)) => {}
+            Err(err) => eprintln!("Failed to remove blob {}: {}", record.storage_key, err),
+        }
+        Ok(())
+    }
+}
@@ -1,19 +1,43 @@
 use std::collections::HashMap;
 
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::db::listener::{self, Received};
+use crate::primitives::http::error::AppError;
 use crate::primitives::http::request::Request;
 use crate::primitives::http::response::Response;
-use crate::route;
+use crate::primitives::http::sse::{Event, Sse};
+use crate::primitives::websocket::{Message, WebSocket};
 use crate::routing::{Route, RouteParams};
+use crate::util::fields::FieldSet;
+use crate::util::filter::ListQuery;
+use crate::util::pagination::{CursorPosition, DEFAULT_PAGE_SIZE};
+use crate::{route, websocket};
 
-use super::repo::UserRepo;
+use super::repo::{self, UserRepo};
 use super::service::UserService;
 use uuid::Uuid;
 
+/// Channel the `"USER"` trigger notifies on (see the `notify-user-changes` migration).
+const USER_CHANNEL: &str = "user_changes";
+
 pub struct UserController;
 
 impl UserController {
     pub fn routes() -> Vec<Route> {
         vec![
+            // Declared before `/user/:id`, which would otherwise capture `events`.
+            Route::new(
+                "GET",
+                &["user", "events"],
+                vec![route!(UserController::events)],
+            ),
+            Route::websocket(
+                &["user", "events", "ws"],
+                vec![],
+                websocket!(UserController::events_ws),
+            ),
             Route::new("GET", &["user"], vec![route!(UserController::get_all)]),
             Route::new("POST", &["user"], vec![route!(UserController::create)]),
             Route::new(
@@ -34,139 +58,188 @@ impl UserController {
         ]
     }
 
+    fn parse_id(params: &RouteParams) -> Result<Uuid, AppError> {
+        let id = params.get("id").unwrap_or("");
+        Uuid::parse_str(id).map_err(|_| {
+            AppError::BadRequest(format!(
+                "Invalid UUID for user id: '{}'. Must be a valid UUID string.",
+                id
+            ))
+        })
+    }
+
+    /// `fields` and `include` over `repo::COLUMNS` and `repo::INCLUDES`.
+    fn field_set(request: &Request) -> Result<FieldSet, AppError> {
+        Ok(FieldSet::parse(
+            request.query_params.get("fields").map(String::as_str),
+            request.query_params.get("include").map(String::as_str),
+            repo::COLUMNS,
+            repo::INCLUDES,
+        )?)
+    }
+
+    fn json(status_code: u16, value: &impl Serialize) -> Response {
+        let body = match serde_json::to_string(value) {
+            Ok(body) => body,
+            Err(err) => {
+                return AppError::Internal(format!("Failed to serialize response: {}", err)).into();
+            }
+        };
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        Response {
+            status_code,
+            headers,
+            body,
+            stream: None,
+        }
+    }
+
+    /// Offset pages with `top`/`skip`, or cursor pages once `after` or `before` is
+    /// present (empty for the first page). `count=true` adds the total to cursor pages.
+    /// `filter` and `sort` follow `ListQuery` over `repo::FIELDS`; `fields` and
+    /// `include` pick what each user carries.
     pub async fn get_all(_request: &mut Request, _params: &RouteParams) -> Response {
         // Use query_params from request
-        let top = _request
+        let top: Option<i64> = _request
             .query_params
             .get("top")
             .and_then(|v| v.parse().ok());
 
-        let skip = _request
+        let skip: Option<i64> = _request
             .query_params
             .get("skip")
             .and_then(|v| v.parse().ok());
 
+        if top.is_some_and(|v| v < 0) || skip.is_some_and(|v| v < 0) {
+            return AppError::BadRequest("'top' and 'skip' must not be negative.".to_string())
+                .into();
+        }
+
         let query = _request.query_params.get("query");
 
-        let service = UserService::new(UserRepo::new());
+        let list = match ListQuery::parse(
+            _request.query_params.get("filter").map(String::as_str),
+            _request.query_params.get("sort").map(String::as_str),
+            repo::FIELDS,
+        ) {
+            Ok(list) => list,
+            Err(e) => return AppError::from(e).into(),
+        };
 
-        let mut headers = HashMap::new();
+        let fields = match Self::field_set(_request) {
+            Ok(fields) => fields,
+            Err(e) => return e.into(),
+        };
 
-        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        let service = UserService::new(UserRepo::new());
 
-        match service.get_all_paginated(top, skip, query).await {
-            Ok(body) => Response {
-                status_code: 200,
-                headers,
-                body,
-            },
+        let after = _request.query_params.get("after");
+        let before = _request.query_params.get("before");
+        let position = match (after, before) {
+            (None, None) => None,
+            (Some(_), Some(_)) => {
+                return AppError::BadRequest(
+                    "Use either 'after' or 'before', not both.".to_string(),
+                )
+                .into();
+            }
+            (Some(cursor), None) | (None, Some(cursor)) if cursor.is_empty() => {
+                Some(CursorPosition::First)
+            }
+            (Some(cursor), None) => Some(CursorPosition::After(cursor.clone())),
+            (None, Some(cursor)) => Some(CursorPosition::Before(cursor.clone())),
+        };
 
-            Err(e) => Response {
-                status_code: 500,
-                headers,
-                body: format!("Failed to fetch users: {}", e),
-            },
+        if let Some(position) = position {
+            if skip.is_some() {
+                return AppError::BadRequest(
+                    "'skip' cannot be combined with 'after' or 'before'.".to_string(),
+                )
+                .into();
+            }
+            let with_total = _request
+                .query_params
+                .get("count")
+                .is_some_and(|v| v == "true");
+            return match service
+                .get_all_by_cursor(
+                    &position,
+                    top.unwrap_or(DEFAULT_PAGE_SIZE),
+                    with_total,
+                    query,
+                    &list,
+                    &fields,
+                )
+                .await
+            {
+                Ok(page) => Self::json(200, &page),
+                Err(e) => e.into(),
+            };
+        }
+
+        match service
+            .get_all_paginated(top, skip, query, &list, &fields)
+            .await
+        {
+            Ok(page) => Self::json(200, &page),
+            Err(e) => e.into(),
         }
     }
 
     pub async fn get_one(_request: &mut Request, params: &RouteParams) -> Response {
-        let _id = params.get("id").unwrap_or("");
-
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        let _id = match Self::parse_id(params) {
+            Ok(id) => id,
+            Err(e) => return e.into(),
+        };
 
-        // Validate UUID
-        if Uuid::parse_str(_id).is_err() {
-            return Response {
-                status_code: 400,
-                headers,
-                body: format!(
-                    "{{\"error\":{}}}",
-                    serde_json::json!(format!(
-                        "Invalid UUID for user id: '{}'. Must be a valid UUID string.",
-                        _id
-                    ))
-                ),
-            };
-        }
+        let fields = match Self::field_set(_request) {
+            Ok(fields) => fields,
+            Err(e) => return e.into(),
+        };
 
         let service = UserService::new(UserRepo::new());
-        match service.get_one(_id.to_string()).await {
-            Ok(body) => Response {
-                status_code: 200,
-                headers,
-                body,
-            },
-            Err(e) => Response {
-                status_code: 500,
-                headers,
-                body: format!("{{\"error\":{}}}", serde_json::json!(e.to_string())),
-            },
+        match service.get_one(_id, &fields).await {
+            Ok(user) => Self::json(200, &user),
+            Err(e) => e.into(),
         }
     }
 
     pub async fn create(request: &mut Request, _params: &RouteParams) -> Response {
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "text/plain".to_string());
-
-        let user = match super::dto::UserDto::from_json(&request.body) {
+        let parsed = match &request.form {
+            Some(form) => super::dto::UserDto::from_form(form),
+            None => super::dto::UserDto::from_json(&request.body),
+        };
+        let user = match parsed {
             Ok(user) => user,
-            Err(err) => {
-                return Response {
-                    status_code: 400,
-                    headers,
-                    body: err,
-                };
-            }
+            Err(err) => return AppError::BadRequest(err).into(),
         };
 
         let service = UserService::new(UserRepo::new());
 
-        if let Err(e) = service.create_user(user).await {
-            return Response {
-                status_code: 500,
-                headers,
-                body: format!("Failed to create user: {}", e),
-            };
-        }
-
-        Response {
-            status_code: 201,
-            headers,
-            body: "".to_string(),
+        match service.create_user(user).await {
+            Ok(user) => {
+                let mut response = Self::json(201, &user);
+                if let Some(id) = user.id {
+                    response
+                        .headers
+                        .insert("Location".to_string(), format!("/user/{}", id));
+                }
+                response
+            }
+            Err(e) => e.into(),
         }
     }
 
     pub async fn update(request: &mut Request, params: &RouteParams) -> Response {
-        let _id = params.get("id").unwrap_or("").to_string();
-
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "application/json".to_string());
-
-        // Validate UUID
-        if Uuid::parse_str(&_id).is_err() {
-            return Response {
-                status_code: 400,
-                headers,
-                body: format!(
-                    "{{\"error\":{}}}",
-                    serde_json::json!(format!(
-                        "Invalid UUID for user id: '{}'. Must be a valid UUID string.",
-                        _id
-                    ))
-                ),
-            };
-        }
+        let _id = match Self::parse_id(params) {
+            Ok(id) => id,
+            Err(e) => return e.into(),
+        };
 
         let user = match super::dto::UpdateUserDto::from_json(&request.body) {
             Ok(user) => user,
-            Err(err) => {
-                return Response {
-                    status_code: 400,
-                    headers,
-                    body: err,
-                };
-            }
+            Err(err) => return AppError::BadRequest(err).into(),
         };
 
         let service = UserService::new(UserRepo::new());
@@ -174,50 +247,76 @@ impl UserController {
         match service.update_user(_id, user.password).await {
             Ok(_) => Response {
                 status_code: 200,
-                headers,
+                headers: HashMap::new(),
                 body: "".to_string(),
+                stream: None,
             },
-            Err(e) => Response {
-                status_code: 500,
-                headers,
-                body: format!("Failed to create user: {}", e),
-            },
+            Err(e) => e.into(),
         }
     }
 
     pub async fn delete(_request: &mut Request, params: &RouteParams) -> Response {
-        let _id = params.get("id").unwrap_or("").to_string();
-        let service = UserService::new(UserRepo::new());
-
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        let _id = match Self::parse_id(params) {
+            Ok(id) => id,
+            Err(e) => return e.into(),
+        };
 
-        // Validate UUID
-        if Uuid::parse_str(&_id).is_err() {
-            return Response {
-                status_code: 400,
-                headers,
-                body: format!(
-                    "{{\"error\":{}}}",
-                    serde_json::json!(format!(
-                        "Invalid UUID for user id: '{}'. Must be a valid UUID string.",
-                        _id
-                    ))
-                ),
-            };
-        }
+        let service = UserService::new(UserRepo::new());
 
         match service.delete_user(_id).await {
             Ok(_) => Response {
                 status_code: 200,
-                headers,
+                headers: HashMap::new(),
                 body: "".to_string(),
+                stream: None,
             },
-            Err(e) => Response {
-                status_code: 500,
-                headers,
-                body: format!("Failed to create user: {}", e),
-            },
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Streams `user_changes` notifications as `user` events. A `resync` event means
+    /// some changes were missed and the client should reload.
+    pub async fn events(_request: &mut Request, _params: &RouteParams) -> Response {
+        let mut subscription = listener::subscribe(&[USER_CHANNEL]);
+        let (tx, rx) = mpsc::channel(16);
+        tokio::task::spawn_local(async move {
+            loop {
+                let event = tokio::select! {
+                    _ = tx.closed() => return,
+                    received = subscription.recv() => match received {
+                        Some(Received::Notification(n)) => Event::new(n.payload.clone()).event("user"),
+                        Some(Received::Gap) => Event::new("{}").event("resync"),
+                        None => return,
+                    },
+                };
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Sse::new(rx).into()
+    }
+
+    /// Same feed as `events` over a WebSocket; a gap is sent as `{"op":"resync"}`.
+    pub async fn events_ws(ws: &mut WebSocket) {
+        let mut subscription = listener::subscribe(&[USER_CHANNEL]);
+        loop {
+            tokio::select! {
+                message = ws.recv() => match message {
+                    Some(Message::Close(_)) | None => return,
+                    Some(_) => {}
+                },
+                received = subscription.recv() => {
+                    let text = match received {
+                        Some(Received::Notification(n)) => n.payload.clone(),
+                        Some(Received::Gap) => r#"{"op":"resync"}"#.to_string(),
+                        None => return,
+                    };
+                    if ws.send_text(text).await.is_err() {
+                        return;
+                    }
+                }
+            }
         }
     }
 }
@@ -1,4 +1,77 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::postgres::PgRow;
+use uuid::Uuid;
+
+use crate::db::{FromRow, try_get_selected};
+use crate::primitives::http::form::Form;
+use crate::util::fields::FieldSet;
+
+/// A user as stored, without the password hash. Columns left out of a sparse fieldset
+/// are `None` and not serialized; `roles` is only set when included.
+#[derive(Serialize)]
+pub struct UserRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<RoleRecord>>,
+}
+
+impl UserRecord {
+    /// Drops the columns that were selected but not requested.
+    pub fn retain(&mut self, fields: &FieldSet) {
+        if !fields.contains("id") {
+            self.id = None;
+        }
+        if !fields.contains("username") {
+            self.username = None;
+        }
+        if !fields.contains("created_at") {
+            self.created_at = None;
+        }
+    }
+}
+
+impl FromRow for UserRecord {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: try_get_selected(row, "id")?,
+            username: try_get_selected(row, "username")?,
+            created_at: try_get_selected(row, "created_at")?,
+            roles: None,
+        })
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct RoleRecord {
+    pub id: Uuid,
+    pub name: String,
+}
+
+/// A role together with the user it is assigned to.
+pub struct UserRoleRecord {
+    pub user_id: Uuid,
+    pub role: RoleRecord,
+}
+
+impl FromRow for UserRoleRecord {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            user_id: row.try_get("user_id")?,
+            role: RoleRecord {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+            },
+        })
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct UserDto {
     #[serde(default)]
@@ -11,6 +84,19 @@ impl UserDto {
     pub fn from_json(json: &str) -> Result<Self, String> {
         serde_json::from_str(json).map_err(|e| format!("Invalid user JSON: {}", e))
     }
+
+    pub fn from_form(form: &Form) -> Result<Self, String> {
+        let field = |name: &str| {
+            form.get(name)
+                .map(|v| v.to_string())
+                .ok_or_else(|| format!("Missing form field '{}'", name))
+        };
+        Ok(Self {
+            id: String::new(),
+            username: field("username")?,
+            password: field("password")?,
+        })
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -23,3 +109,44 @@ impl UpdateUserDto {
         serde_json::from_str(json).map_err(|e| format!("Invalid user JSON: {}", e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::user::repo::{COLUMNS, INCLUDES};
+
+    fn user() -> UserRecord {
+        UserRecord {
+            id: Some(Uuid::nil()),
+            username: Some("ada".to_string()),
+            created_at: Some(DateTime::UNIX_EPOCH),
+            roles: None,
+        }
+    }
+
+    #[test]
+    fn serializes_only_the_requested_fields() {
+        let fields = FieldSet::parse(Some("username"), None, COLUMNS, INCLUDES).unwrap();
+        let mut user = user();
+        user.retain(&fields);
+        assert_eq!(
+            serde_json::to_string(&user).unwrap(),
+            r#"{"username":"ada"}"#
+        );
+    }
+
+    #[test]
+    fn serializes_every_field_by_default() {
+        let fields = FieldSet::parse(None, None, COLUMNS, INCLUDES).unwrap();
+        let mut user = user();
+        user.retain(&fields);
+        assert_eq!(
+            serde_json::to_value(&user).unwrap(),
+            serde_json::json!({
+                "id": "00000000-0000-0000-0000-000000000000",
+                "username": "ada",
+                "created_at": "1970-01-01T00:00:00Z",
+            })
+        );
+    }
+}
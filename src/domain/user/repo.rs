@@ -1,139 +1,228 @@
 pub struct UserRepo;
-use serde_json::Value;
-use sqlx::Row;
-use sqlx::postgres::PgRow;
-
-use super::dto::UserDto;
-use crate::db::{self, DbParam};
-use crate::util::pagination::build_paginated_json_query;
+use uuid::Uuid;
+
+use super::dto::{UserDto, UserRecord, UserRoleRecord};
+use crate::db::builder::{Condition, Delete, Insert, Order, Select, Update};
+use crate::db::{DbParam, DbType, Executor};
+use crate::util::fields::FieldSet;
+use crate::util::filter::{self, Field, ListQuery};
+use crate::util::pagination::{
+    CursorError, CursorPage, CursorPosition, Page, SortKey, fetch_cursor_page, fetch_page,
+};
+
+const TABLE: &str = "USER";
+/// What `?fields=` may select.
+pub const COLUMNS: &[&str] = &["id", "username", "created_at"];
+/// What `?include=` may embed.
+pub const INCLUDES: &[&str] = &["roles"];
+/// What `?filter=` and `?sort=` may reference.
+pub const FIELDS: &[Field] = &[
+    Field::new("id", DbType::Uuid).filter_only(),
+    Field::new("username", DbType::Text),
+    Field::new("created_at", DbType::Timestamptz),
+];
+/// Cursor listings walk users by name; the id breaks ties.
+const SORT_KEYS: &[SortKey] = &[
+    SortKey {
+        column: "username",
+        order: Order::Asc,
+        ty: DbType::Text,
+    },
+    SortKey {
+        column: "id",
+        order: Order::Asc,
+        ty: DbType::Uuid,
+    },
+];
 
 impl UserRepo {
     pub fn new() -> Self {
         Self
     }
 
-    pub async fn get_all_paginated(
-        &self,
-        top: Option<i64>,
-        skip: Option<i64>,
-        query: Option<&String>,
-    ) -> Result<String, sqlx::Error> {
-        let mut where_clause = None;
-        let mut where_params = vec![];
-
-        if let Some(q) = query {
-            where_clause = Some("username ILIKE $1");
-            where_params.push(DbParam::Text(format!("%{}%", q)));
+    fn base_select(columns: &[&str], query: Option<&String>) -> Select {
+        let select = Select::from(TABLE).columns(columns);
+        match query {
+            Some(q) => select.filter(Condition::ilike(
+                "username",
+                format!("%{}%", filter::like_pattern(q)),
+            )),
+            None => select,
         }
+    }
 
-        // Use a CTE to fetch paginated data and total count in one query
-        let pagination = build_paginated_json_query(
-            "USER",
-            "id, username",
-            "'id', id, 'username', username",
-            where_clause,
-            where_params,
-            top,
-            skip,
-        );
-
-        let rows = db::query(&pagination.sql, pagination.params).await?;
-
-        let (users_json, total_count) = if let Some(row) = rows.get(0) {
-            let users_json = row
-                .try_get::<Value, _>("data_json")
-                .unwrap_or(Value::Array(vec![]));
+    /// The columns to select for `fields`, plus `required` and the id roles are loaded by.
+    fn columns(fields: &FieldSet, required: &[&'static str]) -> Vec<&'static str> {
+        let mut columns = fields.columns(required);
+        if fields.includes("roles") && !columns.contains(&"id") {
+            columns.push("id");
+        }
+        columns
+    }
 
-            let total_count = row.try_get::<i64, _>("total").unwrap_or(0);
-            (users_json, total_count)
-        } else {
-            (Value::Array(vec![]), 0)
-        };
+    /// Loads the included resources, then drops the columns only selected for them.
+    async fn complete(
+        db: &mut impl Executor,
+        users: &mut [UserRecord],
+        fields: &FieldSet,
+    ) -> Result<(), sqlx::Error> {
+        if fields.includes("roles") {
+            Self::include_roles(db, users).await?;
+        }
+        for user in users.iter_mut() {
+            user.retain(fields);
+        }
+        Ok(())
+    }
 
-        let page = (pagination.skip / pagination.top) + 1;
-        let total_pages = if pagination.top > 0 {
-            (total_count + pagination.top - 1) / pagination.top
-        } else {
-            1
-        };
+    async fn include_roles(
+        db: &mut impl Executor,
+        users: &mut [UserRecord],
+    ) -> Result<(), sqlx::Error> {
+        let ids = users.iter().filter_map(|user| user.id).collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let assigned: Vec<UserRoleRecord> = db
+            .query_as(
+                r#"SELECT ur.user_id, r.id, r.name
+                   FROM "USER_ROLE" ur JOIN "ROLE" r ON r.id = ur.role_id
+                   WHERE ur.user_id = ANY($1)
+                   ORDER BY r.name"#,
+                vec![DbParam::UuidArray(ids)],
+            )
+            .await?;
+        for user in users.iter_mut() {
+            user.roles = Some(
+                assigned
+                    .iter()
+                    .filter(|assigned| Some(assigned.user_id) == user.id)
+                    .map(|assigned| assigned.role.clone())
+                    .collect(),
+            );
+        }
+        Ok(())
+    }
 
-        let mut result = serde_json::Map::new();
-        result.insert("page".to_string(), Value::Number(page.into()));
-        result.insert("total_pages".to_string(), Value::Number(total_pages.into()));
-        result.insert("data".to_string(), users_json);
+    pub async fn get_all_paginated(
+        &self,
+        mut db: impl Executor,
+        top: Option<i64>,
+        skip: Option<i64>,
+        query: Option<&String>,
+        list: &ListQuery,
+        fields: &FieldSet,
+    ) -> Result<Page<UserRecord>, sqlx::Error> {
+        let select = list.apply(Self::base_select(&Self::columns(fields, &[]), query));
+        let mut page = fetch_page(&mut db, &select, top, skip).await?;
+        Self::complete(&mut db, &mut page.data, fields).await?;
+        Ok(page)
+    }
 
-        Ok(Value::Object(result).to_string())
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_all_by_cursor(
+        &self,
+        mut db: impl Executor,
+        position: &CursorPosition,
+        limit: i64,
+        with_total: bool,
+        query: Option<&String>,
+        list: &ListQuery,
+        fields: &FieldSet,
+    ) -> Result<CursorPage<UserRecord>, CursorError> {
+        let keys = list.sort_keys(SORT_KEYS, SORT_KEYS[1]);
+        // The cursors are built from the key columns, requested or not.
+        let required = keys.iter().map(|key| key.column).collect::<Vec<_>>();
+        let select =
+            list.apply_filters(Self::base_select(&Self::columns(fields, &required), query));
+        let mut page =
+            fetch_cursor_page(&mut db, &select, &keys, position, limit, with_total).await?;
+        Self::complete(&mut db, &mut page.data, fields).await?;
+        Ok(page)
     }
 
-    pub async fn create(&self, user: UserDto) -> Result<Vec<PgRow>, sqlx::Error> {
-        let res = db::query(
-            "
-            INSERT
-            INTO
-                \"USER\" (username, password)
-            VALUES
-                ($1, $2)
-            RETURNING
-                id, username, password
-            ",
-            vec![DbParam::Text(user.username), DbParam::Text(user.password)],
-        )
-        .await;
-
-        res
+    pub async fn create(
+        &self,
+        mut db: impl Executor,
+        user: UserDto,
+    ) -> Result<UserRecord, sqlx::Error> {
+        let insert = Insert::into(TABLE)
+            .value("username", user.username)
+            .value("password", user.password)
+            .returning(COLUMNS)
+            .build();
+
+        db.query_one_as(&insert.sql, insert.params).await
     }
 
-    pub async fn get_one(&self, id: String) -> Result<String, sqlx::Error> {
-        let sql: &str = "
-            SELECT
-                to_jsonb(
-                    json_build_object(
-                        'id', id,
-                        'username', username
-                    )
-                ) AS user_json
-            FROM
-                \"USER\"
-            WHERE
-                id = $1::uuid
-        ";
-
-        let rows: Vec<PgRow> = db::query(sql, vec![DbParam::Text(id)]).await?;
-
-        if let Some(row) = rows.get(0) {
-            let value = row.try_get::<Value, _>("user_json").unwrap_or(Value::Null);
-            Ok(value.to_string())
-        } else {
-            Ok("null".to_string())
-        }
+    pub async fn get_one(
+        &self,
+        mut db: impl Executor,
+        id: Uuid,
+        fields: &FieldSet,
+    ) -> Result<Option<UserRecord>, sqlx::Error> {
+        let select = Select::from(TABLE)
+            .columns(&Self::columns(fields, &[]))
+            .filter(Condition::eq("id", id))
+            .build();
+
+        let user: Option<UserRecord> = db.query_optional_as(&select.sql, select.params).await?;
+        let Some(mut user) = user else {
+            return Ok(None);
+        };
+        Self::complete(&mut db, std::slice::from_mut(&mut user), fields).await?;
+        Ok(Some(user))
     }
 
+    /// Returns `false` when no user has this id.
     pub async fn update_user(
         &self,
-        id: String,
+        mut db: impl Executor,
+        id: Uuid,
         password: String,
-    ) -> Result<Vec<PgRow>, sqlx::Error> {
-        let sql: &str = "
-            UPDATE
-                \"USER\"
-            SET
-                password = $2
-            WHERE
-                id = $1::uuid
-        ";
-
-        db::query(sql, vec![DbParam::Text(id), DbParam::Text(password)]).await
+    ) -> Result<bool, sqlx::Error> {
+        let update = Update::table(TABLE)
+            .set("password", password)
+            .filter(Condition::eq("id", id))
+            .build()
+            .map_err(|err| sqlx::Error::InvalidArgument(err.to_string()))?;
+
+        Ok(db.execute(&update.sql, update.params).await? > 0)
     }
 
-    pub async fn delete_user(&self, id: String) -> Result<Vec<PgRow>, sqlx::Error> {
-        let sql: &str = "
-            DELETE
-            FROM
-                \"USER\"
-            WHERE
-                id = $1::uuid
-        ";
+    /// Returns `false` when no user has this id.
+    pub async fn delete_user(&self, mut db: impl Executor, id: Uuid) -> Result<bool, sqlx::Error> {
+        let delete = Delete::from(TABLE).filter(Condition::eq("id", id)).build();
+
+        Ok(db.execute(&delete.sql, delete.params).await? > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        db::query(sql, vec![DbParam::Text(id)]).await
+    #[test]
+    fn selects_the_id_to_load_includes_by() {
+        let fields = FieldSet::parse(Some("username"), Some("roles"), COLUMNS, INCLUDES).unwrap();
+        assert_eq!(UserRepo::columns(&fields, &[]), ["username", "id"]);
+
+        let fields = FieldSet::parse(Some("username"), None, COLUMNS, INCLUDES).unwrap();
+        assert_eq!(UserRepo::columns(&fields, &[]), ["username"]);
+    }
+
+    #[test]
+    fn escapes_the_search_query() {
+        let query = "50%_off".to_string();
+        let statement = UserRepo::base_select(&["id"], Some(&query)).build();
+        assert!(
+            statement.sql.contains("\"username\" ILIKE $1"),
+            "{}",
+            statement.sql
+        );
+        assert_eq!(
+            format!("{:?}", statement.params),
+            r#"[Text("%50\\%\\_off%")]"#
+        );
     }
 }
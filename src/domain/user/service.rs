@@ -1,8 +1,13 @@
-use super::dto::UserDto;
+use super::dto::{UserDto, UserRecord};
 use super::repo::UserRepo;
+use crate::db;
+use crate::primitives::http::error::AppError;
+use crate::util::fields::FieldSet;
+use crate::util::filter::ListQuery;
+use crate::util::pagination::{CursorPage, CursorPosition, Page};
 use bcrypt::{DEFAULT_COST, hash};
-use sqlx::postgres::PgRow;
 use std::env;
+use uuid::Uuid;
 
 pub struct UserService {
     repo: UserRepo,
@@ -13,49 +18,84 @@ impl UserService {
         Self { repo }
     }
 
+    fn hash_password(password: &str) -> Result<String, AppError> {
+        let cost = env::var("BCRYPT_COST")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_COST);
+
+        hash(password, cost)
+            .map_err(|e| AppError::Internal(format!("Failed to hash password: {}", e)))
+    }
+
     pub async fn get_all_paginated(
         &self,
         top: Option<i64>,
         skip: Option<i64>,
         query: Option<&String>,
-    ) -> Result<String, sqlx::Error> {
-        self.repo.get_all_paginated(top, skip, query).await
+        list: &ListQuery,
+        fields: &FieldSet,
+    ) -> Result<Page<UserRecord>, AppError> {
+        Ok(self
+            .repo
+            .get_all_paginated(db::reader(), top, skip, query, list, fields)
+            .await?)
     }
 
-    pub async fn create_user(&self, mut user: UserDto) -> Result<(), sqlx::Error> {
-        // Hash the password before saving
-        let cost = env::var("BCRYPT_COST")
-            .ok()
-            .and_then(|v| v.parse::<u32>().ok())
-            .unwrap_or(DEFAULT_COST);
+    pub async fn get_all_by_cursor(
+        &self,
+        position: &CursorPosition,
+        limit: i64,
+        with_total: bool,
+        query: Option<&String>,
+        list: &ListQuery,
+        fields: &FieldSet,
+    ) -> Result<CursorPage<UserRecord>, AppError> {
+        Ok(self
+            .repo
+            .get_all_by_cursor(
+                db::reader(),
+                position,
+                limit,
+                with_total,
+                query,
+                list,
+                fields,
+            )
+            .await?)
+    }
 
-        let hashed = hash(&user.password, cost).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+    fn not_found(id: Uuid) -> AppError {
+        AppError::NotFound(format!("User {} not found.", id))
+    }
 
-        user.password = hashed;
+    pub async fn create_user(&self, mut user: UserDto) -> Result<UserRecord, AppError> {
+        // Hash the password before saving
+        user.password = Self::hash_password(&user.password)?;
 
-        self.repo.create(user).await.map(|_| ())
+        Ok(self.repo.create(db::pool(), user).await?)
     }
 
-    pub async fn get_one(&self, id: String) -> Result<String, sqlx::Error> {
-        self.repo.get_one(id).await
+    pub async fn get_one(&self, id: Uuid, fields: &FieldSet) -> Result<UserRecord, AppError> {
+        self.repo
+            .get_one(db::reader(), id, fields)
+            .await?
+            .ok_or_else(|| Self::not_found(id))
     }
 
-    pub async fn update_user(
-        &self,
-        id: String,
-        password: String,
-    ) -> Result<Vec<PgRow>, sqlx::Error> {
-        let cost = env::var("BCRYPT_COST")
-            .ok()
-            .and_then(|v| v.parse::<u32>().ok())
-            .unwrap_or(DEFAULT_COST);
-
-        let hashed = hash(password, cost).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+    pub async fn update_user(&self, id: Uuid, password: String) -> Result<(), AppError> {
+        let hashed = Self::hash_password(&password)?;
 
-        self.repo.update_user(id, hashed).await
+        if !self.repo.update_user(db::pool(), id, hashed).await? {
+            return Err(Self::not_found(id));
+        }
+        Ok(())
     }
 
-    pub async fn delete_user(&self, id: String) -> Result<Vec<PgRow>, sqlx::Error> {
-        self.repo.delete_user(id).await
+    pub async fn delete_user(&self, id: Uuid) -> Result<(), AppError> {
+        if !self.repo.delete_user(db::pool(), id).await? {
+            return Err(Self::not_found(id));
+        }
+        Ok(())
     }
 }
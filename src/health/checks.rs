@@ -0,0 +1,30 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::db;
+
+pub async fn database() -> Result<(), String> {
+    db::query("SELECT 1", vec![])
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Fails while any `_up.sql` file in `MIGRATIONS_DIR` (default: `src/db/migrations`)
+/// has not been applied with `db_cli migrate`.
+pub async fn migrations() -> Result<(), String> {
+    let dir = env::var("MIGRATIONS_DIR").unwrap_or_else(|_| "src/db/migrations".to_string());
+    let pending = db::pending_migration_ids(&PathBuf::from(dir))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} pending migration(s): {}",
+            pending.len(),
+            pending.join(", ")
+        ))
+    }
+}
@@ -0,0 +1,9 @@
+use crate::health::HealthCheck;
+use crate::health::checks;
+
+pub fn init_checks() -> Vec<HealthCheck> {
+    vec![
+        HealthCheck::new("database", checks::database),
+        HealthCheck::new("migrations", checks::migrations),
+    ]
+}
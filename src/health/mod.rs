@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use serde_json::{Map, Value, json};
+use tokio::time::{Duration, timeout};
+
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::route;
+use crate::routing::{BoxFuture, Route, RouteParams};
+
+pub mod checks;
+pub mod init;
+pub use init::init_checks;
+
+pub type CheckFn = Box<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// A named readiness check. Each domain can contribute its own through `init_checks`.
+pub struct HealthCheck {
+    pub name: &'static str,
+    pub check: CheckFn,
+}
+
+impl HealthCheck {
+    pub fn new<F, Fut>(name: &'static str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + 'static,
+    {
+        Self {
+            name,
+            check: Box::new(move || Box::pin(check())),
+        }
+    }
+}
+
+static CHECKS: OnceLock<Vec<HealthCheck>> = OnceLock::new();
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+pub fn init(checks: Vec<HealthCheck>) {
+    let _ = CHECKS.set(checks);
+}
+
+pub fn checks() -> &'static [HealthCheck] {
+    CHECKS.get().map(|c| c.as_slice()).unwrap_or(&[])
+}
+
+/// Marks the server as draining; `/readyz` answers 503 from now on.
+pub fn begin_shutdown() {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+}
+
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+fn check_timeout() -> Duration {
+    let ms = env::var("HEALTH_CHECK_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(2000);
+    Duration::from_millis(ms)
+}
+
+pub fn routes() -> Vec<Route> {
+    vec![
+        Route::new("GET", &["healthz"], vec![route!(liveness)]),
+        Route::new("GET", &["readyz"], vec![route!(readiness)]),
+    ]
+}
+
+fn json_response(status_code: u16, body: Value) -> Response {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    headers.insert("Cache-Control".to_string(), "no-store".to_string());
+    Response {
+        status_code,
+        headers,
+        body: body.to_string(),
+        stream: None,
+    }
+}
+
+/// Liveness only reports that the process is able to serve requests.
+pub async fn liveness(_request: &mut Request, _params: &RouteParams) -> Response {
+    json_response(200, json!({ "status": "ok" }))
+}
+
+pub async fn readiness(_request: &mut Request, _params: &RouteParams) -> Response {
+    report_readiness(checks(), is_shutting_down()).await
+}
+
+/// Runs `checks` and answers 200 only when all of them pass and the server is not draining.
+async fn report_readiness(checks: &[HealthCheck], shutting_down: bool) -> Response {
+    let limit = check_timeout();
+    let mut ready = true;
+    let mut results = Map::new();
+
+    for check in checks {
+        let started = Instant::now();
+        let outcome = match timeout(limit, (check.check)()).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("timed out after {}ms", limit.as_millis())),
+        };
+        let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let entry = match outcome {
+            Ok(()) => json!({ "status": "up", "duration_ms": duration_ms }),
+            Err(error) => {
+                ready = false;
+                json!({ "status": "down", "duration_ms": duration_ms, "error": error })
+            }
+        };
+        results.insert(check.name.to_string(), entry);
+    }
+
+    let status = if shutting_down {
+        "draining"
+    } else if ready {
+        "ready"
+    } else {
+        "not_ready"
+    };
+    let status_code = if ready && !shutting_down { 200 } else { 503 };
+
+    json_response(
+        status_code,
+        json!({
+            "status": status,
+            "shutting_down": shutting_down,
+            "checks": results,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn up() -> HealthCheck {
+        HealthCheck::new("up", || async { Ok(()) })
+    }
+
+    fn down() -> HealthCheck {
+        HealthCheck::new("down", || async { Err("connection refused".to_string()) })
+    }
+
+    fn body(response: &Response) -> Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn ready_when_every_check_passes() {
+        let response = report_readiness(&[up()], false).await;
+        assert_eq!(response.status_code, 200);
+        let body = body(&response);
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["checks"]["up"]["status"], "up");
+    }
+
+    #[tokio::test]
+    async fn not_ready_when_a_check_fails() {
+        let response = report_readiness(&[up(), down()], false).await;
+        assert_eq!(response.status_code, 503);
+        let body = body(&response);
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["checks"]["down"]["error"], "connection refused");
+    }
+
+    #[tokio::test]
+    async fn readiness_fails_while_draining() {
+        let response = report_readiness(&[up()], true).await;
+        assert_eq!(response.status_code, 503);
+        let body = body(&response);
+        assert_eq!(body["status"], "draining");
+        assert_eq!(body["shutting_down"], true);
+        assert_eq!(response.headers["Cache-Control"], "no-store");
+    }
+
+    #[tokio::test]
+    async fn liveness_stays_ok() {
+        let mut request = Request::for_test("GET", "/healthz");
+        let response = liveness(&mut request, &RouteParams::default()).await;
+        assert_eq!(response.status_code, 200);
+        assert_eq!(body(&response)["status"], "ok");
+    }
+}
@@ -1,39 +1,329 @@
+use bytes::Bytes;
 use dotenv::dotenv;
+use h2::RecvStream;
+use h2::server::SendResponse;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::env;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{Semaphore, mpsc};
-use tokio::time::{Duration, sleep};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::time::{Duration, Instant, interval, sleep, sleep_until, timeout};
 
 mod db;
 mod domain;
+mod health;
 mod middlewares;
+mod observability;
 mod primitives;
 mod routing;
+mod static_files;
+mod storage;
+mod telemetry;
 mod util;
 use chrono::Utc;
-use primitives::http::request::Request;
-use routing::{init, init_routes, route};
+use observability::access_log::{self, AccessLogEntry};
+use observability::metrics;
+use primitives::http::compression;
+use primitives::http::error::AppError;
+use primitives::http::form::{self, Form};
+use primitives::http::http2::{self, H2cUpgrade};
+use primitives::http::request::{Connection, Request, find_header, has_token};
+use primitives::http::response::Response;
+use primitives::http::tls;
+use primitives::websocket::WebSocket;
+use routing::timeouts::{self, with_timeout};
+use routing::{find_route, init, init_routes, route, route_params, routes};
+use telemetry::{Span, SpanContext, SpanKind};
+use util::url::form_decode;
+use uuid::Uuid;
 
-async fn handle_connection(mut stream: TcpStream, _permit: tokio::sync::OwnedSemaphorePermit) {
+/// Accepts a client-supplied request ID only if it is short and printable, so
+/// it can be echoed into logs and headers safely.
+fn request_id_from(headers: &HashMap<String, String>) -> String {
+    find_header(headers, "X-Request-Id")
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty() && v.len() <= 128 && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Answers a request that never reached the router (e.g. a read timeout) and closes.
+async fn reject<W: AsyncWrite + Unpin>(stream: &mut W, error: AppError, started: Instant) {
+    let response: Response = error.into();
+    let _ = with_timeout(
+        timeouts::global().write,
+        stream.write_all(&response.to_bytes()),
+    )
+    .await;
+    let _ = stream.shutdown().await;
+    metrics::record_request(None, response.status_code, started.elapsed());
+}
+
+/// Largest body read into memory from `MAX_BODY_SIZE` (default 10 MiB), as sent
+/// before any `Content-Encoding` is decoded.
+fn max_body_size() -> usize {
+    static MAX_BODY_SIZE: OnceLock<usize> = OnceLock::new();
+    *MAX_BODY_SIZE.get_or_init(|| {
+        env::var("MAX_BODY_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(10 * 1024 * 1024)
+    })
+}
+
+/// Reads a body of `len` bytes, or up to the end of the stream when the length is
+/// unknown, into memory, decoding any `Content-Encoding`, and parses it when it is a form.
+/// Bodies above `max_body_size` are rejected with 413.
+async fn read_body<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    headers: &HashMap<String, String>,
+    len: Option<usize>,
+) -> Result<(String, Option<Form>), AppError> {
+    let max = max_body_size();
+    let too_large = || AppError::PayloadTooLarge(format!("Request body exceeds {} bytes.", max));
+    let mut buf = Vec::new();
+    let read = match len {
+        Some(len) if len > max => return Err(too_large()),
+        Some(len) => {
+            buf.resize(len, 0);
+            reader.read_exact(&mut buf).await.map(|_| ())
+        }
+        None => reader
+            .take(max as u64 + 1)
+            .read_to_end(&mut buf)
+            .await
+            .map(|_| ()),
+    };
+    read.map_err(|err| AppError::BadRequest(format!("Failed to read the request body: {}", err)))?;
+    if buf.len() > max {
+        return Err(too_large());
+    }
+    if let Some(encoding) = find_header(headers, "Content-Encoding") {
+        buf = compression::decode_request_body(encoding, buf)?;
+    }
+
+    let content_type = find_header(headers, "Content-Type").unwrap_or("");
+    let parsed = if let Some(boundary) = form::multipart_boundary(content_type) {
+        Some(form::read_multipart(&mut buf.as_slice(), &boundary).await?)
+    } else if form::is_urlencoded(content_type) {
+        Some(form::parse_urlencoded(&buf)?)
+    } else {
+        None
+    };
+    Ok((String::from_utf8_lossy(&buf).to_string(), parsed))
+}
+
+/// Reads the request body within `limit`. Multipart bodies are parsed off the
+/// connection so file parts can go straight to disk.
+async fn read_request_body<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    headers: &HashMap<String, String>,
+    len: Option<usize>,
+    limit: Option<Duration>,
+) -> Result<(String, Option<Form>), AppError> {
+    let content_type = find_header(headers, "Content-Encoding")
+        .is_none()
+        .then(|| find_header(headers, "Content-Type"))
+        .flatten();
+    let result = match content_type.and_then(form::multipart_boundary) {
+        Some(boundary) => {
+            let mut limited = reader.take(len.map_or(u64::MAX, |len| len as u64));
+            with_timeout(limit, form::read_multipart(&mut limited, &boundary))
+                .await
+                .map(|parsed| parsed.map(|parsed| (String::new(), Some(parsed))))
+        }
+        None => with_timeout(limit, read_body(reader, headers, len)).await,
+    };
+    result.unwrap_or_else(|| {
+        Err(AppError::RequestTimeout(
+            "Timed out waiting for the request body.".to_string(),
+        ))
+    })
+}
+
+fn parse_query(url: &str) -> HashMap<String, String> {
+    let mut query_params = HashMap::new();
+    if let Some(idx) = url.find('?') {
+        let query = &url[idx + 1..];
+        for pair in query.split('&') {
+            let mut kv = pair.splitn(2, '=');
+            if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
+                // Malformed escapes are kept as sent.
+                let decode = |s: &str| form_decode(s).unwrap_or_else(|| s.to_string());
+                query_params.insert(decode(k), decode(v));
+            }
+        }
+    }
+    query_params
+}
+
+/// Routes `request` inside its server span and finishes the response (request ID,
+/// compression, span attributes). Shared by HTTP/1.1 and HTTP/2.
+async fn dispatch(request: &mut Request, version: &str) -> (Response, Span) {
+    let remote_parent = request
+        .header("traceparent")
+        .and_then(SpanContext::from_traceparent);
+    let mut span = Span::root(
+        format!("HTTP {}", request.method),
+        SpanKind::Server,
+        remote_parent,
+    );
+
+    let mut response = span.scope(db::read_your_writes(route(request))).await;
+    response
+        .headers
+        .entry("X-Request-Id".to_string())
+        .or_insert_with(|| request.id.clone());
+    compression::compress_response(request.header("Accept-Encoding"), &mut response);
+
+    if span.is_recording() {
+        if let Some(route_def) = request.matched_route.and_then(|i| routes().get(i)) {
+            let pattern = route_def.pattern();
+            span.set_name(format!("{} {}", route_def.method, pattern));
+            span.set_attribute("http.route", pattern);
+        }
+        span.set_attribute("http.request.method", request.method.as_str());
+        span.set_attribute("url.path", request.url.split('?').next().unwrap_or(""));
+        span.set_attribute("network.protocol.version", version);
+        span.set_attribute("http.response.status_code", i64::from(response.status_code));
+        if let Some(addr) = request.remote_addr {
+            span.set_attribute("client.address", addr.ip().to_string());
+        }
+        if response.status_code >= 500 {
+            span.set_error(format!("HTTP {}", response.status_code));
+        }
+    }
+    (response, span)
+}
+
+/// Logs a failed response write and returns the body bytes that were sent.
+fn written_bytes(request: &Request, written: &std::io::Result<usize>) -> usize {
+    match written {
+        Ok(bytes) => *bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::TimedOut => {
+            eprintln!("Timed out writing response [request_id={}]", request.id);
+            0
+        }
+        Err(err) => {
+            eprintln!(
+                "Failed to write response [request_id={}]: {}",
+                request.id, err
+            );
+            0
+        }
+    }
+}
+
+/// Ends the request span and records the metrics and access log entry.
+fn complete(
+    request: &Request,
+    version: &str,
+    status: u16,
+    bytes: usize,
+    started: Instant,
+    span: Span,
+) {
+    span.end();
+
+    let duration = started.elapsed();
+    metrics::record_request(request.matched_route, status, duration);
+    access_log::log(&AccessLogEntry {
+        request_id: &request.id,
+        remote_addr: request.remote_addr,
+        method: &request.method,
+        url: &request.url,
+        version,
+        headers: &request.headers,
+        status,
+        bytes,
+        duration,
+        timestamp: request.timestamp,
+    });
+}
+
+/// Picks the protocol: ALPN decides under TLS, plaintext clients may open with the
+/// HTTP/2 preface (prior knowledge) or upgrade an HTTP/1.1 request with `h2c`.
+async fn handle_connection(
+    stream: TcpStream,
+    _permit: OwnedSemaphorePermit,
+    limiter: Arc<Semaphore>,
+) {
     let remote_addr = stream.peer_addr().ok();
+    let handshake_timeout = timeouts::global().header_read;
+
+    if let Some(acceptor) = tls::acceptor() {
+        let stream = match with_timeout(handshake_timeout, acceptor.accept(stream)).await {
+            Some(Ok(stream)) => stream,
+            // A failed or stalled handshake has nobody to answer.
+            _ => return,
+        };
+        if stream.get_ref().1.alpn_protocol() == Some(b"h2") {
+            serve_http2(Box::new(stream), remote_addr, limiter).await;
+        } else {
+            serve_http1(Box::new(stream), remote_addr, limiter, false).await;
+        }
+        return;
+    }
+
+    let http2_enabled = http2::config().enabled;
+    if http2_enabled
+        && let Some(Ok(true)) = with_timeout(handshake_timeout, http2::has_preface(&stream)).await
+    {
+        serve_http2(Box::new(stream), remote_addr, limiter).await;
+        return;
+    }
+    serve_http1(Box::new(stream), remote_addr, limiter, http2_enabled).await;
+}
+
+async fn serve_http1(
+    mut stream: Connection,
+    remote_addr: Option<SocketAddr>,
+    limiter: Arc<Semaphore>,
+    allow_h2c: bool,
+) {
     let mut buf_reader = BufReader::new(&mut stream);
     let mut http_request = Vec::new();
-    let mut line = String::new();
 
     let timestamp = Utc::now();
+    let started = Instant::now();
 
-    while buf_reader.read_line(&mut line).await.unwrap() > 0 {
-        let trimmed = line.trim_end().to_string();
-        if trimmed.is_empty() {
-            break;
+    let read_headers = async {
+        let mut line = String::new();
+        loop {
+            if buf_reader.read_line(&mut line).await? == 0 {
+                break;
+            }
+            let trimmed = line.trim_end().to_string();
+            if trimmed.is_empty() {
+                break;
+            }
+            http_request.push(trimmed);
+            line.clear();
+        }
+        Ok::<(), std::io::Error>(())
+    };
+
+    match with_timeout(timeouts::global().header_read, read_headers).await {
+        Some(Ok(())) => {}
+        // The client went away or sent garbage; there is nobody to answer.
+        Some(Err(_)) => return,
+        None => {
+            let message = "Timed out waiting for the request headers.".to_string();
+            reject(
+                buf_reader.get_mut(),
+                AppError::RequestTimeout(message),
+                started,
+            )
+            .await;
+            return;
         }
-        http_request.push(trimmed);
-        line.clear();
     }
 
-    let (method, url, _version) = if let Some(request_line) = http_request.get(0) {
+    let (method, url, version) = if let Some(request_line) = http_request.first() {
         let mut parts = request_line.split_whitespace();
         (
             parts.next().unwrap_or("").to_string(),
@@ -51,49 +341,233 @@ async fn handle_connection(mut stream: TcpStream, _permit: tokio::sync::OwnedSem
         }
     }
 
-    let mut body = String::new();
-    if let Some(content_length) = headers.get("Content-Length") {
-        if let Ok(len) = content_length.parse::<usize>() {
-            let mut buf = vec![0u8; len];
-            buf_reader.read_exact(&mut buf).await.unwrap();
-            body = String::from_utf8_lossy(&buf).to_string();
+    let content_length =
+        find_header(&headers, "Content-Length").and_then(|v| v.parse::<usize>().ok());
+
+    // `Upgrade: h2c` is only honored for requests without a body, which can be
+    // replayed as stream 1; anything else is answered over HTTP/1.1.
+    if allow_h2c
+        && has_token(find_header(&headers, "Upgrade"), "h2c")
+        && find_header(&headers, "HTTP2-Settings").is_some()
+        && content_length.unwrap_or(0) == 0
+        && let Some(frame) = http2::upgrade_frame(&method, &url, &headers)
+    {
+        let buffered = buf_reader.buffer().to_vec();
+        let switching =
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+        if let Some(Ok(())) =
+            with_timeout(timeouts::global().write, stream.write_all(switching)).await
+        {
+            let upgraded = H2cUpgrade::new(stream, buffered, frame);
+            serve_http2(Box::new(upgraded), remote_addr, limiter).await;
         }
+        return;
     }
 
-    // Build query_params from URL
-    let mut query_params = HashMap::new();
-    if let Some(idx) = url.find('?') {
-        let query = &url[idx + 1..];
-        for pair in query.split('&') {
-            let mut kv = pair.splitn(2, '=');
-            if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
-                query_params.insert(k.to_string(), v.to_string());
+    let limits = timeouts::resolve(find_route(&method, &url));
+
+    let mut body = String::new();
+    let mut form = None;
+    if let Some(len) = content_length {
+        match read_request_body(&mut buf_reader, &headers, Some(len), limits.body_read).await {
+            Ok((read, parsed)) => {
+                body = read;
+                form = parsed;
+            }
+            Err(err) => {
+                reject(buf_reader.get_mut(), err, started).await;
+                return;
             }
         }
     }
 
     let mut request = Request {
+        id: request_id_from(&headers),
         method,
+        query_params: parse_query(&url),
         url,
         headers,
         body,
-        stream,
+        form,
+        stream: Some(stream),
         remote_addr,
         timestamp,
-        query_params,
+        matched_route: None,
     };
 
-    let response = route(&mut request).await;
+    let (mut response, span) = dispatch(&mut request, &version).await;
 
-    println!("//=====================//");
-    println!("{}", request);
+    let written = match request.stream.as_mut() {
+        Some(stream) => {
+            let head_only = request.method == "HEAD";
+            response.write_to(stream, head_only, limits.write).await
+        }
+        None => Ok(0),
+    };
+    let bytes = written_bytes(&request, &written);
+    // A successful handshake keeps the connection open for the route's WebSocket handler.
+    let websocket = request
+        .matched_route
+        .and_then(|i| routes().get(i))
+        .and_then(|route_def| route_def.websocket.clone())
+        .filter(|_| response.status_code == 101 && written.is_ok());
+    if websocket.is_none()
+        && let Some(stream) = request.stream.as_mut()
+    {
+        let _ = stream.shutdown().await;
+    }
 
-    request
-        .stream
-        .write_all(&response.to_bytes())
-        .await
-        .unwrap();
-    let _ = request.stream.shutdown().await;
+    complete(
+        &request,
+        &version,
+        response.status_code,
+        bytes,
+        started,
+        span,
+    );
+
+    if let Some(handler) = websocket
+        && let Some(stream) = request.stream.take()
+    {
+        let params = route_params(&request);
+        let mut socket = WebSocket::new(request, stream, params, limits.write);
+        routing::run_websocket(&handler, &mut socket).await;
+        socket.finish().await;
+    }
+}
+
+/// How long a draining HTTP/2 connection without open streams may wait for the
+/// client to acknowledge the `GOAWAY` before it is dropped.
+const GOAWAY_GRACE: Duration = Duration::from_secs(5);
+
+/// Serves HTTP/2 streams until the client closes the connection, it stays idle for
+/// `HTTP2_IDLE_TIMEOUT_SECS` or the server shuts down; the latter two send `GOAWAY`
+/// and let open streams finish. Each stream holds a `connection_limiter` permit while
+/// it runs, so streams and HTTP/1.1 connections share one budget; when it is used up,
+/// new streams are refused with `REFUSED_STREAM`, which clients may safely retry.
+async fn serve_http2(io: Connection, remote_addr: Option<SocketAddr>, limiter: Arc<Semaphore>) {
+    let config = http2::config();
+    let handshake = h2::server::Builder::new()
+        .max_concurrent_streams(config.max_concurrent_streams)
+        .handshake::<_, Bytes>(io);
+    let mut connection = match with_timeout(timeouts::global().header_read, handshake).await {
+        Some(Ok(connection)) => connection,
+        _ => return,
+    };
+
+    let open_streams = Rc::new(Cell::new(0usize));
+    let mut idle_since = Instant::now();
+    let mut draining_since: Option<Instant> = None;
+    let mut housekeeping = interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            accepted = connection.accept() => {
+                let (request, mut respond) = match accepted {
+                    Some(Ok(stream)) => stream,
+                    Some(Err(err)) => {
+                        if !err.is_io() && !err.is_go_away() {
+                            eprintln!("HTTP/2 connection error: {}", err);
+                        }
+                        break;
+                    }
+                    None => break,
+                };
+                idle_since = Instant::now();
+                let Ok(permit) = limiter.clone().try_acquire_owned() else {
+                    respond.send_reset(h2::Reason::REFUSED_STREAM);
+                    continue;
+                };
+                open_streams.set(open_streams.get() + 1);
+                let open = open_streams.clone();
+                tokio::task::spawn_local(async move {
+                    handle_stream(request, respond, remote_addr).await;
+                    drop(permit);
+                    open.set(open.get() - 1);
+                });
+            }
+            _ = housekeeping.tick() => {
+                if open_streams.get() > 0 {
+                    idle_since = Instant::now();
+                }
+                let idle = config
+                    .idle_timeout
+                    .is_some_and(|limit| idle_since.elapsed() >= limit);
+                match draining_since {
+                    None if idle || health::is_shutting_down() => {
+                        // `accept` yields `None` once the open streams are done and the
+                        // client acknowledged the `GOAWAY`.
+                        connection.graceful_shutdown();
+                        draining_since = Some(Instant::now());
+                    }
+                    // A client that never acknowledges would hold its permit forever.
+                    Some(since)
+                        if open_streams.get() == 0 && since.elapsed() >= GOAWAY_GRACE =>
+                    {
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+async fn handle_stream(
+    head: http::Request<RecvStream>,
+    mut respond: SendResponse<Bytes>,
+    remote_addr: Option<SocketAddr>,
+) {
+    let timestamp = Utc::now();
+    let started = Instant::now();
+    let version = "HTTP/2.0";
+
+    let (parts, body_stream) = head.into_parts();
+    let (method, url, headers) = http2::request_head(&parts);
+    let limits = timeouts::resolve(find_route(&method, &url));
+
+    let (body, form) = if body_stream.is_end_stream() {
+        (String::new(), None)
+    } else {
+        let len = find_header(&headers, "Content-Length").and_then(|v| v.parse::<usize>().ok());
+        let mut reader = http2::BodyReader::new(body_stream);
+        match read_request_body(&mut reader, &headers, len, limits.body_read).await {
+            Ok(read) => read,
+            Err(err) => {
+                let mut response: Response = err.into();
+                let limit = timeouts::global().write;
+                let _ = http2::write_response(&mut respond, &mut response, false, limit).await;
+                metrics::record_request(None, response.status_code, started.elapsed());
+                return;
+            }
+        }
+    };
+
+    let mut request = Request {
+        id: request_id_from(&headers),
+        method,
+        query_params: parse_query(&url),
+        url,
+        headers,
+        body,
+        form,
+        stream: None,
+        remote_addr,
+        timestamp,
+        matched_route: None,
+    };
+
+    let (mut response, span) = dispatch(&mut request, version).await;
+    let head_only = request.method == "HEAD";
+    let written = http2::write_response(&mut respond, &mut response, head_only, limits.write).await;
+    let bytes = written_bytes(&request, &written);
+    complete(
+        &request,
+        version,
+        response.status_code,
+        bytes,
+        started,
+        span,
+    );
 }
 
 fn main() {
@@ -106,6 +580,7 @@ fn main() {
     const RESET: &str = "\x1b[0m";
 
     init(init_routes());
+    health::init(health::init_checks());
 
     let cores = env::var("CORES")
         .ok()
@@ -116,11 +591,34 @@ fn main() {
                 .unwrap_or(1)
         });
 
+    let shutdown_grace = env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(5);
+    let shutdown_timeout = env::var("SHUTDOWN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(30);
+
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let bind_addr = format!("127.0.0.1:{}", port);
 
     let max_connections = cores * 1024;
-    let connection_limiter = std::sync::Arc::new(Semaphore::new(max_connections));
+    let connection_limiter = Arc::new(Semaphore::new(max_connections));
+
+    let limiter = connection_limiter.clone();
+    metrics::register_gauge(
+        "http_connections_in_flight",
+        "Connections currently holding a limiter permit.",
+        vec![],
+        move || (max_connections - limiter.available_permits()) as f64,
+    );
+    metrics::register_gauge(
+        "http_connections_max",
+        "Maximum concurrent connections allowed by the limiter.",
+        vec![],
+        move || max_connections as f64,
+    );
 
     // Verbose startup logging
     println!("{CYAN}Starting Base Rust Web API...{RESET}");
@@ -138,12 +636,52 @@ fn main() {
     } else {
         println!("{GREEN}Bcrypt cost:{RESET} {MAGENTA}default{RESET}");
     }
+    match telemetry::init_from_env() {
+        Ok(Some(endpoint)) => println!("{GREEN}Tracing:{RESET} {MAGENTA}OTLP -> {endpoint}{RESET}"),
+        Ok(None) => println!("{GREEN}Tracing:{RESET} {MAGENTA}disabled{RESET}"),
+        Err(err) => eprintln!("{YELLOW}Tracing disabled:{RESET} {err}"),
+    }
+    match tls::init() {
+        Ok(setup) => println!("{GREEN}TLS:{RESET} {MAGENTA}{setup}{RESET}"),
+        Err(err) => {
+            eprintln!("{YELLOW}TLS init failed:{RESET} {err}");
+            std::process::exit(1);
+        }
+    }
+    println!(
+        "{GREEN}HTTP/2:{RESET} {MAGENTA}{}{RESET}",
+        if http2::config().enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+    match storage::init() {
+        Ok(backend) => println!("{GREEN}Storage:{RESET} {MAGENTA}{backend}{RESET}"),
+        Err(err) => {
+            eprintln!("{YELLOW}Storage init failed:{RESET} {err}");
+            std::process::exit(1);
+        }
+    }
+    match access_log::init() {
+        Ok(Some(target)) => println!("{GREEN}Access log:{RESET} {MAGENTA}{target}{RESET}"),
+        Ok(None) => println!("{GREEN}Access log:{RESET} {MAGENTA}disabled{RESET}"),
+        Err(err) => eprintln!("{YELLOW}Access log disabled:{RESET} {err}"),
+    }
 
     let mut senders = Vec::with_capacity(cores);
     for _ in 0..cores {
-        let (tx, mut rx) = mpsc::channel::<(TcpStream, tokio::sync::OwnedSemaphorePermit)>(1024);
+        let (tx, mut rx) = mpsc::channel::<(TcpStream, OwnedSemaphorePermit)>(1024);
+        let queue = tx.clone();
+        metrics::register_gauge(
+            "worker_queue_depth",
+            "Accepted connections waiting in a worker's channel.",
+            vec![("worker", senders.len().to_string())],
+            move || (queue.max_capacity() - queue.capacity()) as f64,
+        );
         senders.push(tx);
 
+        let limiter = connection_limiter.clone();
         std::thread::spawn(move || {
             let runtime = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
@@ -153,7 +691,7 @@ fn main() {
 
             runtime.block_on(local.run_until(async move {
                 while let Some((stream, permit)) = rx.recv().await {
-                    tokio::task::spawn_local(handle_connection(stream, permit));
+                    tokio::task::spawn_local(handle_connection(stream, permit, limiter.clone()));
                 }
             }));
         });
@@ -165,17 +703,70 @@ fn main() {
         .unwrap();
 
     runtime.block_on(async move {
-        let _ = db::init_pool()
+        let pool = db::init_pool()
             .await
             .expect("Failed to initialize DB pool");
 
+        let named = db::init_named_pools()
+            .await
+            .expect("Failed to initialize named DB pools");
+        for (name, _) in &named {
+            println!("{GREEN}DB pool:{RESET} {MAGENTA}{name}{RESET}");
+        }
+
+        for (name, pool) in std::iter::once(("primary", pool)).chain(named) {
+            let labels = vec![("pool", name.to_string())];
+            metrics::register_gauge(
+                "db_pool_connections",
+                "Open connections in the DB pool.",
+                labels.clone(),
+                move || pool.size() as f64,
+            );
+            metrics::register_gauge(
+                "db_pool_idle_connections",
+                "Idle connections in the DB pool.",
+                labels.clone(),
+                move || pool.num_idle() as f64,
+            );
+            metrics::register_gauge(
+                "db_pool_max_connections",
+                "Maximum connections allowed in the DB pool.",
+                labels,
+                move || pool.options().get_max_connections() as f64,
+            );
+        }
+
+        match db::listener::start() {
+            Some(channels) => println!(
+                "{GREEN}DB listener:{RESET} {MAGENTA}{}{RESET}",
+                channels.join(", ")
+            ),
+            None => println!("{GREEN}DB listener:{RESET} {MAGENTA}disabled{RESET}"),
+        }
+
         println!("{CYAN}Server is ready and accepting connections!{RESET}");
 
         let listener = TcpListener::bind(&bind_addr).await.unwrap();
         let mut next = 0usize;
 
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        let mut stop_accepting_at: Option<Instant> = None;
+
         loop {
-            let (stream, _) = match listener.accept().await {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = &mut shutdown, if stop_accepting_at.is_none() => {
+                    // Keep serving while the orchestrator notices `/readyz` failing.
+                    health::begin_shutdown();
+                    println!("{CYAN}Shutdown requested, draining for {shutdown_grace}s...{RESET}");
+                    stop_accepting_at = Some(Instant::now() + Duration::from_secs(shutdown_grace));
+                    continue;
+                }
+                _ = sleep_until(stop_accepting_at.unwrap_or_else(Instant::now)), if stop_accepting_at.is_some() => break,
+            };
+
+            let (stream, _) = match accepted {
                 Ok(pair) => pair,
                 Err(err) => {
                     eprintln!("{YELLOW}Accept failed:{RESET} {err}");
@@ -202,5 +793,32 @@ fn main() {
             }
             next = (next + 1) % senders.len();
         }
+
+        drop(listener);
+        println!("{CYAN}Stopped accepting connections, waiting for in-flight requests...{RESET}");
+        let all_permits = connection_limiter.acquire_many(max_connections as u32);
+        match timeout(Duration::from_secs(shutdown_timeout), all_permits).await {
+            Ok(_) => println!("{CYAN}All connections closed, bye!{RESET}"),
+            Err(_) => eprintln!(
+                "{YELLOW}Shutdown timeout reached with {} connection(s) still open{RESET}",
+                max_connections - connection_limiter.available_permits()
+            ),
+        }
     });
 }
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
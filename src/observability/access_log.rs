@@ -0,0 +1,506 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::Duration;
+
+const COMMON_TEMPLATE: &str = "%h %l %u %t \"%r\" %>s %b";
+const COMBINED_TEMPLATE: &str = "%h %l %u %t \"%r\" %>s %b \"%{Referer}i\" \"%{User-Agent}i\"";
+
+static LOGGER: OnceLock<AccessLogger> = OnceLock::new();
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Everything the access log knows about a finished request/response pair.
+pub struct AccessLogEntry<'a> {
+    pub request_id: &'a str,
+    pub remote_addr: Option<SocketAddr>,
+    pub method: &'a str,
+    pub url: &'a str,
+    pub version: &'a str,
+    pub headers: &'a HashMap<String, String>,
+    pub status: u16,
+    pub bytes: usize,
+    pub duration: Duration,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl AccessLogEntry<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn path(&self) -> &str {
+        self.url.split('?').next().unwrap_or("")
+    }
+
+    fn query(&self) -> Option<&str> {
+        self.url.split_once('?').map(|(_, q)| q)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    RemoteHost,
+    Dash,
+    Time,
+    RequestLine,
+    Method,
+    Path,
+    Query,
+    Protocol,
+    Status,
+    BytesClf,
+    Bytes,
+    DurationMicros,
+    DurationSeconds,
+    DurationMillis,
+    RequestId,
+    RequestHeader(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum AccessLogFormat {
+    Common,
+    Combined,
+    Json,
+    Custom(String),
+}
+
+impl AccessLogFormat {
+    fn from_env_value(value: &str) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "common" | "clf" => Self::Common,
+            "combined" => Self::Combined,
+            "json" => Self::Json,
+            _ => Self::Custom(value.to_string()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Common => "common",
+            Self::Combined => "combined",
+            Self::Json => "json",
+            Self::Custom(_) => "custom",
+        }
+    }
+}
+
+enum Formatter {
+    Template(Vec<Token>),
+    Json,
+}
+
+impl Formatter {
+    fn new(format: &AccessLogFormat) -> Self {
+        match format {
+            AccessLogFormat::Common => Self::Template(parse_template(COMMON_TEMPLATE)),
+            AccessLogFormat::Combined => Self::Template(parse_template(COMBINED_TEMPLATE)),
+            AccessLogFormat::Json => Self::Json,
+            AccessLogFormat::Custom(template) => Self::Template(parse_template(template)),
+        }
+    }
+
+    fn format(&self, entry: &AccessLogEntry) -> String {
+        match self {
+            Self::Template(tokens) => format_template(tokens, entry),
+            Self::Json => format_json(entry),
+        }
+    }
+}
+
+/// Parses an Apache `mod_log_config` style template. Supported directives:
+/// `%h %l %u %t %r %m %U %q %H %s %>s %b %B %D %T %{ms}T %L %{Header}i %%`.
+/// `%L` is the request ID.
+/// Unknown directives are kept verbatim.
+fn parse_template(template: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            literal.push(ch);
+            continue;
+        }
+
+        let mut argument = None;
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            let mut arg = String::new();
+            for c in chars.by_ref() {
+                if c == '}' {
+                    break;
+                }
+                arg.push(c);
+            }
+            argument = Some(arg);
+        }
+        if chars.peek() == Some(&'>') {
+            chars.next();
+        }
+
+        let token = match (chars.next(), argument.as_deref()) {
+            (Some('%'), _) => {
+                literal.push('%');
+                continue;
+            }
+            (Some('h'), _) => Token::RemoteHost,
+            (Some('l'), _) | (Some('u'), _) => Token::Dash,
+            (Some('t'), _) => Token::Time,
+            (Some('r'), _) => Token::RequestLine,
+            (Some('m'), _) => Token::Method,
+            (Some('U'), _) => Token::Path,
+            (Some('q'), _) => Token::Query,
+            (Some('H'), _) => Token::Protocol,
+            (Some('s'), _) => Token::Status,
+            (Some('b'), _) => Token::BytesClf,
+            (Some('B'), _) => Token::Bytes,
+            (Some('D'), _) => Token::DurationMicros,
+            (Some('T'), Some("ms")) => Token::DurationMillis,
+            (Some('T'), _) => Token::DurationSeconds,
+            (Some('L'), _) => Token::RequestId,
+            (Some('i'), Some(name)) => Token::RequestHeader(name.to_string()),
+            (Some(other), arg) => {
+                literal.push('%');
+                if let Some(arg) = arg {
+                    literal.push_str(&format!("{{{}}}", arg));
+                }
+                literal.push(other);
+                continue;
+            }
+            (None, _) => {
+                literal.push('%');
+                continue;
+            }
+        };
+
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(std::mem::take(&mut literal)));
+        }
+        tokens.push(token);
+    }
+
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    tokens
+}
+
+fn format_template(tokens: &[Token], entry: &AccessLogEntry) -> String {
+    let mut line = String::new();
+    for token in tokens {
+        match token {
+            Token::Literal(text) => line.push_str(text),
+            Token::RemoteHost => match entry.remote_addr {
+                Some(addr) => line.push_str(&addr.ip().to_string()),
+                None => line.push('-'),
+            },
+            Token::Dash => line.push('-'),
+            Token::Time => {
+                line.push_str(&entry.timestamp.format("[%d/%b/%Y:%H:%M:%S %z]").to_string())
+            }
+            Token::RequestLine => line.push_str(&escape(&format!(
+                "{} {} {}",
+                entry.method, entry.url, entry.version
+            ))),
+            Token::Method => line.push_str(&escape(entry.method)),
+            Token::Path => line.push_str(&escape(entry.path())),
+            Token::Query => {
+                if let Some(query) = entry.query() {
+                    line.push('?');
+                    line.push_str(&escape(query));
+                }
+            }
+            Token::Protocol => line.push_str(&escape(entry.version)),
+            Token::Status => line.push_str(&entry.status.to_string()),
+            Token::BytesClf => {
+                if entry.bytes == 0 {
+                    line.push('-');
+                } else {
+                    line.push_str(&entry.bytes.to_string());
+                }
+            }
+            Token::Bytes => line.push_str(&entry.bytes.to_string()),
+            Token::DurationMicros => line.push_str(&entry.duration.as_micros().to_string()),
+            Token::DurationSeconds => line.push_str(&entry.duration.as_secs().to_string()),
+            Token::DurationMillis => line.push_str(&entry.duration.as_millis().to_string()),
+            Token::RequestId => line.push_str(&escape(entry.request_id)),
+            Token::RequestHeader(name) => line.push_str(&escape(entry.header(name).unwrap_or("-"))),
+        }
+    }
+    line
+}
+
+fn format_json(entry: &AccessLogEntry) -> String {
+    serde_json::json!({
+        "time": entry.timestamp.to_rfc3339(),
+        "request_id": entry.request_id,
+        "remote_addr": entry.remote_addr.map(|a| a.ip().to_string()),
+        "method": entry.method,
+        "path": entry.path(),
+        "query": entry.query(),
+        "protocol": entry.version,
+        "status": entry.status,
+        "bytes": entry.bytes,
+        "duration_ms": entry.duration.as_secs_f64() * 1000.0,
+        "referer": entry.header("Referer"),
+        "user_agent": entry.header("User-Agent"),
+    })
+    .to_string()
+}
+
+/// Escapes quotes and control characters so a header or URL cannot break the line format.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Size-based rotation: `access.log` -> `access.log.1` -> ... -> `access.log.N`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+            return Ok(());
+        }
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+enum Sink {
+    Stdout,
+    File(RotatingFile),
+}
+
+impl Sink {
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            Sink::Stdout => {
+                let mut out = io::stdout().lock();
+                out.write_all(line.as_bytes())?;
+                out.write_all(b"\n")
+            }
+            Sink::File(file) => file.write_line(line),
+        }
+    }
+}
+
+struct AccessLogger {
+    formatter: Formatter,
+    sender: SyncSender<String>,
+}
+
+/// Writes are handed to a dedicated thread so workers never block on disk I/O.
+fn spawn_writer(mut sink: Sink, receiver: Receiver<String>) {
+    std::thread::spawn(move || {
+        while let Ok(line) = receiver.recv() {
+            if let Err(err) = sink.write_line(&line) {
+                eprintln!("Access log write failed: {err}");
+            }
+        }
+    });
+}
+
+/// Configures the access log from the environment. Returns a short description
+/// for the startup banner, or `None` when the access log is disabled.
+///
+/// - `ACCESS_LOG_FORMAT`: `combined` (default), `common`, `json`, `off`, or a custom template
+/// - `ACCESS_LOG_FILE`: log file path (default: stdout)
+/// - `ACCESS_LOG_MAX_SIZE`: rotate after this many bytes (default: 10 MiB, 0 disables rotation)
+/// - `ACCESS_LOG_MAX_FILES`: rotated files to keep (default: 5)
+/// - `ACCESS_LOG_BUFFER`: lines queued for the writer before new ones are dropped (default: 10000)
+pub fn init() -> io::Result<Option<String>> {
+    let format_value = env::var("ACCESS_LOG_FORMAT").unwrap_or_else(|_| "combined".to_string());
+    if format_value.eq_ignore_ascii_case("off") || format_value.eq_ignore_ascii_case("false") {
+        return Ok(None);
+    }
+    let format = AccessLogFormat::from_env_value(&format_value);
+
+    let (sink, destination) = match env::var("ACCESS_LOG_FILE") {
+        Ok(path) if !path.is_empty() => {
+            let max_size = env::var("ACCESS_LOG_MAX_SIZE")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(10 * 1024 * 1024);
+            let max_files = env::var("ACCESS_LOG_MAX_FILES")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(5);
+            let file = RotatingFile::open(PathBuf::from(&path), max_size, max_files)?;
+            (Sink::File(file), path)
+        }
+        _ => (Sink::Stdout, "stdout".to_string()),
+    };
+
+    let buffer = env::var("ACCESS_LOG_BUFFER")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(10_000);
+    let (sender, receiver) = mpsc::sync_channel(buffer);
+    spawn_writer(sink, receiver);
+
+    let description = format!("{} -> {}", format.name(), destination);
+    let _ = LOGGER.set(AccessLogger {
+        formatter: Formatter::new(&format),
+        sender,
+    });
+    Ok(Some(description))
+}
+
+/// Queues the line for the writer thread. When the writer falls behind and the queue
+/// is full, the line is dropped and counted rather than blocking the worker.
+pub fn log(entry: &AccessLogEntry) {
+    if let Some(logger) = LOGGER.get()
+        && let Err(TrySendError::Full(_)) = logger.sender.try_send(logger.formatter.format(entry))
+    {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Lines dropped since startup because the writer queue was full.
+pub fn dropped_lines() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry<'a>(url: &'a str, headers: &'a HashMap<String, String>) -> AccessLogEntry<'a> {
+        AccessLogEntry {
+            request_id: "req-1",
+            remote_addr: None,
+            method: "GET",
+            url,
+            version: "HTTP/1.1",
+            headers,
+            status: 200,
+            bytes: 0,
+            duration: Duration::from_millis(3),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn escapes_request_line_path_and_query() {
+        let headers = HashMap::new();
+        let entry = entry("/a\"b\n?q=\"x\"\r\n", &headers);
+        let line = format_template(&parse_template("\"%r\" %U %q"), &entry);
+        assert_eq!(
+            line,
+            "\"GET /a\\\"b\\x0a?q=\\\"x\\\"\\x0d\\x0a HTTP/1.1\" /a\\\"b\\x0a ?q=\\\"x\\\"\\x0d\\x0a"
+        );
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn escapes_headers_and_defaults_missing_ones() {
+        let mut headers = HashMap::new();
+        headers.insert("user-agent".to_string(), "evil\" 200 -\n".to_string());
+        let entry = entry("/", &headers);
+        let line = format_template(
+            &parse_template("\"%{User-Agent}i\" \"%{Referer}i\""),
+            &entry,
+        );
+        assert_eq!(line, "\"evil\\\" 200 -\\x0a\" \"-\"");
+    }
+
+    #[test]
+    fn escapes_the_protocol() {
+        let headers = HashMap::new();
+        let mut entry = entry("/", &headers);
+        entry.version = "HTTP/1.1\" 500 \n";
+        let line = format_template(&parse_template("%H"), &entry);
+        assert_eq!(line, "HTTP/1.1\\\" 500 \\x0a");
+    }
+
+    #[test]
+    fn parses_directives_and_literals() {
+        assert_eq!(
+            parse_template("%h %>s %{ms}T %% %z"),
+            vec![
+                Token::RemoteHost,
+                Token::Literal(" ".to_string()),
+                Token::Status,
+                Token::Literal(" ".to_string()),
+                Token::DurationMillis,
+                Token::Literal(" % %z".to_string()),
+            ]
+        );
+    }
+}
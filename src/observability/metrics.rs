@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::db;
+use crate::observability::access_log;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::route;
+use crate::routing::{self, Route, RouteParams};
+use crate::telemetry;
+
+/// Upper bounds (seconds) of the request latency histogram buckets.
+const BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Distinct status codes tracked per route before falling back to `status="other"`.
+const STATUS_SLOTS: usize = 16;
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+static GAUGES: Mutex<Vec<Gauge>> = Mutex::new(Vec::new());
+
+/// Counters for one (route, status) pair. `status == 0` marks a free slot.
+struct StatusSlot {
+    status: AtomicU16,
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKETS.len()],
+}
+
+impl StatusSlot {
+    fn new() -> Self {
+        Self {
+            status: AtomicU16::new(0),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        let secs = duration.as_secs_f64();
+        if let Some(index) = BUCKETS.iter().position(|le| secs <= *le) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct RouteMetrics {
+    method: String,
+    pattern: String,
+    slots: [StatusSlot; STATUS_SLOTS],
+    overflow: StatusSlot,
+    panics: AtomicU64,
+}
+
+impl RouteMetrics {
+    fn new(method: &str, pattern: String) -> Self {
+        Self {
+            method: method.to_string(),
+            pattern,
+            slots: std::array::from_fn(|_| StatusSlot::new()),
+            overflow: StatusSlot::new(),
+            panics: AtomicU64::new(0),
+        }
+    }
+
+    /// Finds the slot for `status`, claiming a free one with a CAS if needed.
+    fn slot_for(&self, status: u16) -> &StatusSlot {
+        for slot in &self.slots {
+            let current = slot.status.load(Ordering::Acquire);
+            if current == status {
+                return slot;
+            }
+            if current == 0 {
+                match slot
+                    .status
+                    .compare_exchange(0, status, Ordering::AcqRel, Ordering::Acquire)
+                {
+                    Ok(_) => return slot,
+                    Err(actual) if actual == status => return slot,
+                    Err(_) => continue,
+                }
+            }
+        }
+        &self.overflow
+    }
+}
+
+/// Request metrics are plain atomics indexed by route, so recording never
+/// takes a lock no matter how many worker threads are serving requests.
+struct Registry {
+    routes: Vec<RouteMetrics>,
+    unmatched: RouteMetrics,
+}
+
+fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| Registry {
+        routes: routing::routes()
+            .iter()
+            .map(|r| RouteMetrics::new(r.method, r.pattern()))
+            .collect(),
+        unmatched: RouteMetrics::new("", "unmatched".to_string()),
+    })
+}
+
+/// A value read at scrape time (queue depths, pool sizes, ...).
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    labels: Vec<(&'static str, String)>,
+    read: Box<dyn Fn() -> f64 + Send + Sync>,
+}
+
+/// Registers a gauge sampled on every scrape. Gauges sharing a name must share
+/// the same help text; they are rendered as one metric family.
+pub fn register_gauge<F>(
+    name: &'static str,
+    help: &'static str,
+    labels: Vec<(&'static str, String)>,
+    read: F,
+) where
+    F: Fn() -> f64 + Send + Sync + 'static,
+{
+    GAUGES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Gauge {
+            name,
+            help,
+            labels,
+            read: Box::new(read),
+        });
+}
+
+pub fn record_request(route_index: Option<usize>, status: u16, duration: Duration) {
+    let registry = registry();
+    let route = route_index
+        .and_then(|i| registry.routes.get(i))
+        .unwrap_or(&registry.unmatched);
+    route.slot_for(status).observe(duration);
+}
+
+pub fn record_panic(route_index: Option<usize>) {
+    let registry = registry();
+    let route = route_index
+        .and_then(|i| registry.routes.get(i))
+        .unwrap_or(&registry.unmatched);
+    route.panics.fetch_add(1, Ordering::Relaxed);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_labels(labels: &[(&str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn render_requests(out: &mut String, registry: &Registry) {
+    let mut series = Vec::new();
+    for route in registry.routes.iter().chain(Some(&registry.unmatched)) {
+        for slot in route.slots.iter().chain(Some(&route.overflow)) {
+            let count = slot.count.load(Ordering::Relaxed);
+            if count == 0 {
+                continue;
+            }
+            let status = match slot.status.load(Ordering::Acquire) {
+                0 => "other".to_string(),
+                code => code.to_string(),
+            };
+            series.push((route, slot, status, count));
+        }
+    }
+
+    out.push_str("# HELP http_requests_total Total HTTP requests by route and status.\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    for (route, _, status, count) in &series {
+        let labels = format_labels(&[
+            ("method", route.method.clone()),
+            ("route", route.pattern.clone()),
+            ("status", status.clone()),
+        ]);
+        let _ = writeln!(out, "http_requests_total{} {}", labels, count);
+    }
+
+    out.push_str(
+        "# HELP http_request_duration_seconds HTTP request latency by route and status.\n",
+    );
+    out.push_str("# TYPE http_request_duration_seconds histogram\n");
+    for (route, slot, status, count) in &series {
+        let base = [
+            ("method", route.method.clone()),
+            ("route", route.pattern.clone()),
+            ("status", status.clone()),
+        ];
+        let mut cumulative = 0;
+        for (le, bucket) in BUCKETS.iter().zip(slot.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let mut labels = base.to_vec();
+            labels.push(("le", le.to_string()));
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{} {}",
+                format_labels(&labels),
+                cumulative
+            );
+        }
+        let mut labels = base.to_vec();
+        labels.push(("le", "+Inf".to_string()));
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_bucket{} {}",
+            format_labels(&labels),
+            count
+        );
+        let sum = slot.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_sum{} {}",
+            format_labels(&base),
+            sum
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_count{} {}",
+            format_labels(&base),
+            count
+        );
+    }
+}
+
+fn render_panics(out: &mut String) {
+    let registry = registry();
+    out.push_str("# HELP http_handler_panics_total Handler panics caught by the router.\n");
+    out.push_str("# TYPE http_handler_panics_total counter\n");
+    for route in registry.routes.iter().chain(Some(&registry.unmatched)) {
+        let panics = route.panics.load(Ordering::Relaxed);
+        if panics == 0 {
+            continue;
+        }
+        let labels = format_labels(&[
+            ("method", route.method.clone()),
+            ("route", route.pattern.clone()),
+        ]);
+        let _ = writeln!(out, "http_handler_panics_total{} {}", labels, panics);
+    }
+}
+
+fn render_statement_family(
+    out: &mut String,
+    (name, help, kind): (&str, &str, &str),
+    stats: &[(String, db::StatementStats)],
+    value: impl Fn(&db::StatementStats) -> f64,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (fingerprint, stats) in stats {
+        let labels = format_labels(&[("fingerprint", fingerprint.clone())]);
+        let _ = writeln!(out, "{}{} {}", name, labels, value(stats));
+    }
+}
+
+/// Per-statement totals from `db::statement_stats`, labelled with the statement fingerprint.
+fn render_db_queries(out: &mut String) {
+    let stats = db::statement_stats();
+    out.push_str("# HELP db_statement_info The normalized SQL of each statement fingerprint.\n");
+    out.push_str("# TYPE db_statement_info gauge\n");
+    for (fingerprint, stats) in &stats {
+        let labels = format_labels(&[
+            ("fingerprint", fingerprint.clone()),
+            ("statement", stats.statement.clone()),
+        ]);
+        let _ = writeln!(out, "db_statement_info{} 1", labels);
+    }
+    let family = ("db_queries_total", "Statements run.", "counter");
+    render_statement_family(out, family, &stats, |s| s.calls as f64);
+    let family = (
+        "db_query_errors_total",
+        "Statements that failed.",
+        "counter",
+    );
+    render_statement_family(out, family, &stats, |s| s.errors as f64);
+    let family = (
+        "db_query_rows_total",
+        "Rows returned or affected.",
+        "counter",
+    );
+    render_statement_family(out, family, &stats, |s| s.rows as f64);
+    let family = (
+        "db_slow_queries_total",
+        "Statements slower than DB_SLOW_QUERY_MS.",
+        "counter",
+    );
+    render_statement_family(out, family, &stats, |s| s.slow as f64);
+    let family = (
+        "db_query_duration_seconds_total",
+        "Time spent running statements.",
+        "counter",
+    );
+    render_statement_family(out, family, &stats, |s| s.total.as_secs_f64());
+    let family = (
+        "db_query_duration_seconds_max",
+        "Slowest run of a statement.",
+        "gauge",
+    );
+    render_statement_family(out, family, &stats, |s| s.max.as_secs_f64());
+}
+
+fn render_access_log(out: &mut String) {
+    out.push_str(
+        "# HELP access_log_dropped_lines_total Access log lines dropped because the writer fell behind.\n",
+    );
+    out.push_str("# TYPE access_log_dropped_lines_total counter\n");
+    let _ = writeln!(
+        out,
+        "access_log_dropped_lines_total {}",
+        access_log::dropped_lines()
+    );
+}
+
+fn render_dropped_spans(out: &mut String) {
+    out.push_str(
+        "# HELP trace_dropped_spans_total Spans dropped because the trace exporter fell behind.\n",
+    );
+    out.push_str("# TYPE trace_dropped_spans_total counter\n");
+    let _ = writeln!(
+        out,
+        "trace_dropped_spans_total {}",
+        telemetry::dropped_spans()
+    );
+}
+
+fn render_gauges(out: &mut String) {
+    let gauges = GAUGES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut families: Vec<&'static str> = Vec::new();
+    let mut by_name: HashMap<&'static str, Vec<&Gauge>> = HashMap::new();
+    for gauge in gauges.iter() {
+        if !by_name.contains_key(gauge.name) {
+            families.push(gauge.name);
+        }
+        by_name.entry(gauge.name).or_default().push(gauge);
+    }
+
+    for name in families {
+        let members = &by_name[name];
+        let _ = writeln!(out, "# HELP {} {}", name, members[0].help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for gauge in members {
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                name,
+                format_labels(&gauge.labels),
+                (gauge.read)()
+            );
+        }
+    }
+}
+
+pub fn render() -> String {
+    let mut out = String::new();
+    render_requests(&mut out, registry());
+    render_panics(&mut out);
+    render_db_queries(&mut out);
+    render_access_log(&mut out);
+    render_dropped_spans(&mut out);
+    render_gauges(&mut out);
+    out
+}
+
+pub fn routes() -> Vec<Route> {
+    vec![Route::new("GET", &["metrics"], vec![route!(scrape)])]
+}
+
+pub async fn scrape(_request: &mut Request, _params: &RouteParams) -> Response {
+    let mut headers = HashMap::new();
+    headers.insert(
+        "Content-Type".to_string(),
+        "text/plain; version=0.0.4; charset=utf-8".to_string(),
+    );
+    Response {
+        status_code: 200,
+        headers,
+        body: render(),
+        stream: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry_with(route: RouteMetrics) -> Registry {
+        Registry {
+            routes: vec![route],
+            unmatched: RouteMetrics::new("", "unmatched".to_string()),
+        }
+    }
+
+    fn lines_starting_with<'a>(out: &'a str, prefix: &str) -> Vec<&'a str> {
+        out.lines()
+            .filter(|line| line.starts_with(prefix))
+            .collect()
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(format_labels(&[]), "");
+        assert_eq!(
+            format_labels(&[
+                ("route", "a\"b\\c\nd".to_string()),
+                ("status", "200".to_string())
+            ]),
+            r#"{route="a\"b\\c\nd",status="200"}"#
+        );
+    }
+
+    #[test]
+    fn renders_help_and_type_lines() {
+        let mut out = String::new();
+        render_requests(
+            &mut out,
+            &registry_with(RouteMetrics::new("GET", "/".to_string())),
+        );
+        assert_eq!(
+            out,
+            "# HELP http_requests_total Total HTTP requests by route and status.\n\
+             # TYPE http_requests_total counter\n\
+             # HELP http_request_duration_seconds HTTP request latency by route and status.\n\
+             # TYPE http_request_duration_seconds histogram\n"
+        );
+
+        register_gauge(
+            "test_queue_depth",
+            "Items queued.",
+            vec![("queue", "a\"b".to_string())],
+            || 3.0,
+        );
+        let mut out = String::new();
+        render_gauges(&mut out);
+        assert!(out.contains(
+            "# HELP test_queue_depth Items queued.\n\
+             # TYPE test_queue_depth gauge\n\
+             test_queue_depth{queue=\"a\\\"b\"} 3\n"
+        ));
+    }
+
+    #[test]
+    fn renders_cumulative_histograms() {
+        let route = RouteMetrics::new("GET", "/users/:id".to_string());
+        for millis in [3, 3, 300, 20_000] {
+            route.slot_for(200).observe(Duration::from_millis(millis));
+        }
+        route.slot_for(404).observe(Duration::from_millis(1));
+        let mut out = String::new();
+        render_requests(&mut out, &registry_with(route));
+
+        assert_eq!(
+            lines_starting_with(&out, "http_requests_total"),
+            [
+                r#"http_requests_total{method="GET",route="/users/:id",status="200"} 4"#,
+                r#"http_requests_total{method="GET",route="/users/:id",status="404"} 1"#,
+            ]
+        );
+        let labels = r#"method="GET",route="/users/:id",status="200""#;
+        let buckets: Vec<String> =
+            lines_starting_with(&out, "http_request_duration_seconds_bucket")
+                .into_iter()
+                .filter(|line| line.contains(labels))
+                .map(|line| line.rsplit_once(',').unwrap().1.to_string())
+                .collect();
+        assert_eq!(
+            buckets,
+            [
+                r#"le="0.001"} 0"#,
+                r#"le="0.005"} 2"#,
+                r#"le="0.01"} 2"#,
+                r#"le="0.025"} 2"#,
+                r#"le="0.05"} 2"#,
+                r#"le="0.1"} 2"#,
+                r#"le="0.25"} 2"#,
+                r#"le="0.5"} 3"#,
+                r#"le="1"} 3"#,
+                r#"le="2.5"} 3"#,
+                r#"le="5"} 3"#,
+                r#"le="10"} 3"#,
+                r#"le="+Inf"} 4"#,
+            ]
+        );
+        assert!(out.contains(&format!(
+            "http_request_duration_seconds_sum{{{labels}}} 20.306\n"
+        )));
+        assert!(out.contains(&format!(
+            "http_request_duration_seconds_count{{{labels}}} 4\n"
+        )));
+    }
+
+    #[test]
+    fn overflows_into_status_other() {
+        let route = RouteMetrics::new("GET", "/".to_string());
+        for status in 200..200 + STATUS_SLOTS as u16 + 2 {
+            route.slot_for(status).observe(Duration::from_millis(1));
+        }
+        route.slot_for(200).observe(Duration::from_millis(1));
+        let mut out = String::new();
+        render_requests(&mut out, &registry_with(route));
+
+        let totals = lines_starting_with(&out, "http_requests_total");
+        assert_eq!(totals.len(), STATUS_SLOTS + 1);
+        assert_eq!(
+            totals[0],
+            r#"http_requests_total{method="GET",route="/",status="200"} 2"#
+        );
+        assert_eq!(
+            totals[STATUS_SLOTS],
+            r#"http_requests_total{method="GET",route="/",status="other"} 2"#
+        );
+        assert!(out.contains(
+            r#"http_request_duration_seconds_count{method="GET",route="/",status="other"} 2"#
+        ));
+    }
+}
@@ -0,0 +1,476 @@
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+use tokio::sync::mpsc;
+
+use super::error::AppError;
+use super::response::Response;
+
+/// Server preference when the client accepts several encodings with the same q-value.
+const PREFERENCE: [Encoding; 3] = [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    pub fn token(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    fn from_token(token: &str) -> Option<Self> {
+        match token.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        }
+    }
+}
+
+struct Config {
+    enabled: bool,
+    min_size: usize,
+    max_decompressed_size: usize,
+}
+
+fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(|| Config {
+        enabled: env::var("COMPRESSION")
+            .map(|v| !matches!(v.to_ascii_lowercase().as_str(), "off" | "false" | "0"))
+            .unwrap_or(true),
+        min_size: env::var("COMPRESSION_MIN_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(1024),
+        max_decompressed_size: env::var("MAX_DECOMPRESSED_BODY_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(10 * 1024 * 1024),
+    })
+}
+
+/// Picks the best supported encoding from an `Accept-Encoding` header, honoring
+/// q-values and `*`. Returns `None` when identity should be used.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut explicit: Vec<(Encoding, f32)> = Vec::new();
+    let mut wildcard: Option<f32> = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let token = parts.next().unwrap_or("").trim();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|v| v.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if token == "*" {
+            wildcard = Some(q);
+        } else if let Some(encoding) = Encoding::from_token(token) {
+            explicit.push((encoding, q));
+        }
+    }
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in PREFERENCE {
+        let q = explicit
+            .iter()
+            .find(|(e, _)| *e == encoding)
+            .map(|(_, q)| *q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("json")
+        || mime.ends_with("xml")
+        || mime.ends_with("javascript")
+        || mime == "image/svg+xml"
+        || mime == "application/wasm"
+}
+
+/// Collects encoder output so it can be drained after each write.
+#[derive(Clone, Default)]
+struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuf {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.borrow_mut())
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Dropping the returned writer finishes the compressed stream.
+fn encoder(encoding: Encoding, sink: SharedBuf) -> io::Result<Box<dyn Write>> {
+    Ok(match encoding {
+        Encoding::Gzip => Box::new(flate2::write::GzEncoder::new(
+            sink,
+            flate2::Compression::default(),
+        )),
+        Encoding::Brotli => Box::new(brotli::CompressorWriter::new(sink, 4096, 5, 22)),
+        Encoding::Zstd => Box::new(zstd::stream::write::Encoder::new(sink, 3)?.auto_finish()),
+    })
+}
+
+fn compress(encoding: Encoding, data: &[u8]) -> io::Result<Vec<u8>> {
+    let sink = SharedBuf::default();
+    let mut writer = encoder(encoding, sink.clone())?;
+    writer.write_all(data)?;
+    drop(writer);
+    Ok(sink.take())
+}
+
+/// Compresses a streamed body chunk by chunk, flushing after each one so
+/// long-lived streams are not held back by the encoder.
+fn compress_stream(
+    encoding: Encoding,
+    mut input: mpsc::Receiver<Vec<u8>>,
+) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::task::spawn_local(async move {
+        let sink = SharedBuf::default();
+        let mut writer = match encoder(encoding, sink.clone()) {
+            Ok(writer) => writer,
+            Err(_) => return,
+        };
+        while let Some(chunk) = input.recv().await {
+            if writer
+                .write_all(&chunk)
+                .and_then(|_| writer.flush())
+                .is_err()
+            {
+                return;
+            }
+            let out = sink.take();
+            if !out.is_empty() && tx.send(out).await.is_err() {
+                return;
+            }
+        }
+        drop(writer);
+        let _ = tx.send(sink.take()).await;
+    });
+    rx
+}
+
+fn append_vary(response: &mut Response) {
+    match response.remove_header("Vary") {
+        Some(vary)
+            if vary
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case("accept-encoding")) =>
+        {
+            response.headers.insert("Vary".to_string(), vary);
+        }
+        Some(vary) if vary.trim() == "*" => {
+            response.headers.insert("Vary".to_string(), vary);
+        }
+        Some(vary) => {
+            response
+                .headers
+                .insert("Vary".to_string(), format!("{}, Accept-Encoding", vary));
+        }
+        None => {
+            response
+                .headers
+                .insert("Vary".to_string(), "Accept-Encoding".to_string());
+        }
+    }
+}
+
+/// Compresses `response` in place according to the request's `Accept-Encoding`.
+/// Small bodies, non-text content types and already-encoded responses are left alone.
+pub fn compress_response(accept_encoding: Option<&str>, response: &mut Response) {
+    let config = config();
+    if !config.enabled
+        || matches!(response.status_code, 100..=199 | 204 | 206 | 304)
+        || response.header("Content-Encoding").is_some()
+        || !response.header("Content-Type").is_some_and(is_compressible)
+    {
+        return;
+    }
+
+    let known_size = match &response.stream {
+        Some(_) => response
+            .header("Content-Length")
+            .and_then(|v| v.parse::<usize>().ok()),
+        None => Some(response.body.len()),
+    };
+    if known_size.is_some_and(|size| size < config.min_size) {
+        return;
+    }
+
+    append_vary(response);
+    let Some(encoding) = accept_encoding.and_then(negotiate) else {
+        return;
+    };
+
+    match response.stream.take() {
+        // The compressed length of a stream is not known up front, so it goes out chunked.
+        Some(stream) => {
+            response.stream = Some(compress_stream(encoding, stream));
+            response.remove_header("Content-Length");
+        }
+        None => {
+            let compressed = match compress(encoding, response.body.as_bytes()) {
+                Ok(compressed) => compressed,
+                Err(_) => return,
+            };
+            response.remove_header("Content-Length");
+            response
+                .headers
+                .insert("Content-Length".to_string(), compressed.len().to_string());
+            let (tx, rx) = mpsc::channel(1);
+            let _ = tx.try_send(compressed);
+            response.stream = Some(rx);
+            response.body.clear();
+        }
+    }
+
+    response
+        .headers
+        .insert("Content-Encoding".to_string(), encoding.token().to_string());
+    // The encoded representation differs from the identity one, so a strong
+    // validator must not be shared between them.
+    if let Some(etag) = response.remove_header("ETag") {
+        let weak = if etag.starts_with("W/") {
+            etag
+        } else {
+            format!("W/{}", etag)
+        };
+        response.headers.insert("ETag".to_string(), weak);
+    }
+}
+
+/// Decodes a request body sent with `Content-Encoding`. The decoded size is capped
+/// by `MAX_DECOMPRESSED_BODY_SIZE` to defuse compression bombs.
+pub fn decode_request_body(content_encoding: &str, raw: Vec<u8>) -> Result<Vec<u8>, AppError> {
+    decode_with_limit(content_encoding, raw, config().max_decompressed_size)
+}
+
+fn decode_with_limit(
+    content_encoding: &str,
+    raw: Vec<u8>,
+    limit: usize,
+) -> Result<Vec<u8>, AppError> {
+    let token = content_encoding.trim();
+    if token.is_empty() || token.eq_ignore_ascii_case("identity") {
+        return Ok(raw);
+    }
+    let encoding = Encoding::from_token(token).ok_or_else(|| {
+        AppError::UnsupportedMediaType(format!("Unsupported Content-Encoding '{}'.", token))
+    })?;
+
+    let reader: Box<dyn Read> = match encoding {
+        Encoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(raw.as_slice())),
+        Encoding::Brotli => Box::new(brotli::Decompressor::new(raw.as_slice(), 4096)),
+        Encoding::Zstd => Box::new(
+            zstd::stream::read::Decoder::new(raw.as_slice())
+                .map_err(|e| AppError::BadRequest(format!("Invalid zstd body: {}", e)))?,
+        ),
+    };
+
+    let mut decoded = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| AppError::BadRequest(format!("Invalid {} body: {}", encoding.token(), e)))?;
+    if decoded.len() > limit {
+        return Err(AppError::PayloadTooLarge(format!(
+            "Decompressed body exceeds {} bytes.",
+            limit
+        )));
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn response(status_code: u16, content_type: &str, body: &str) -> Response {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), content_type.to_string());
+        Response {
+            status_code,
+            headers,
+            body: body.to_string(),
+            stream: None,
+        }
+    }
+
+    fn large_body() -> String {
+        "{\"name\":\"Ada Lovelace\"},".repeat(200)
+    }
+
+    #[test]
+    fn negotiates_by_q_value() {
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, br;q=0.8"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("zstd;q=0.1, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn wildcard_covers_only_unlisted_encodings() {
+        assert_eq!(negotiate("*"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("gzip;q=0, *"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("zstd;q=0, br;q=0, *"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("zstd;q=0, br;q=0, gzip;q=0, *"), None);
+        assert_eq!(negotiate("*;q=0"), None);
+    }
+
+    #[test]
+    fn breaks_ties_by_server_preference() {
+        assert_eq!(negotiate("gzip, br, zstd"), Some(Encoding::Zstd));
+        assert_eq!(negotiate("gzip, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip;q=0.5, br;q=0.5"), Some(Encoding::Brotli));
+    }
+
+    #[test]
+    fn compresses_large_text_bodies() {
+        let body = large_body();
+        let mut res = response(200, "application/json", &body);
+        compress_response(Some("gzip"), &mut res);
+
+        assert_eq!(res.header("Content-Encoding"), Some("gzip"));
+        assert_eq!(res.header("Vary"), Some("Accept-Encoding"));
+        assert!(res.body.is_empty());
+        let compressed = res.stream.as_mut().unwrap().try_recv().unwrap();
+        assert_eq!(
+            res.header("Content-Length"),
+            Some(compressed.len().to_string().as_str())
+        );
+        let decoded = decode_request_body("gzip", compressed).unwrap();
+        assert_eq!(decoded, body.as_bytes());
+    }
+
+    #[test]
+    fn skips_small_bodies() {
+        let mut res = response(200, "application/json", "{}");
+        compress_response(Some("gzip"), &mut res);
+        assert_eq!(res.header("Content-Encoding"), None);
+        assert_eq!(res.header("Vary"), None);
+        assert_eq!(res.body, "{}");
+    }
+
+    #[test]
+    fn skips_non_compressible_types() {
+        let mut res = response(200, "image/png", &large_body());
+        compress_response(Some("gzip"), &mut res);
+        assert_eq!(res.header("Content-Encoding"), None);
+        assert!(res.stream.is_none());
+    }
+
+    #[test]
+    fn skips_bodiless_and_partial_statuses() {
+        for status in [204, 206, 304] {
+            let mut res = response(status, "text/plain", &large_body());
+            compress_response(Some("gzip"), &mut res);
+            assert_eq!(res.header("Content-Encoding"), None, "{status}");
+        }
+    }
+
+    #[test]
+    fn varies_even_when_the_client_wants_identity() {
+        let mut res = response(200, "text/plain", &large_body());
+        compress_response(Some("identity"), &mut res);
+        assert_eq!(res.header("Content-Encoding"), None);
+        assert_eq!(res.header("Vary"), Some("Accept-Encoding"));
+    }
+
+    #[test]
+    fn merges_vary() {
+        for (existing, merged) in [
+            ("Origin", "Origin, Accept-Encoding"),
+            ("origin, accept-encoding", "origin, accept-encoding"),
+            ("*", "*"),
+        ] {
+            let mut res = response(200, "text/plain", &large_body());
+            res.headers.insert("Vary".to_string(), existing.to_string());
+            compress_response(Some("br"), &mut res);
+            assert_eq!(res.header("Vary"), Some(merged), "{existing}");
+        }
+    }
+
+    #[test]
+    fn weakens_strong_etags() {
+        for (etag, expected) in [("\"abc\"", "W/\"abc\""), ("W/\"abc\"", "W/\"abc\"")] {
+            let mut res = response(200, "text/plain", &large_body());
+            res.headers.insert("ETag".to_string(), etag.to_string());
+            compress_response(Some("zstd"), &mut res);
+            assert_eq!(res.header("Content-Encoding"), Some("zstd"));
+            assert_eq!(res.header("ETag"), Some(expected));
+        }
+    }
+
+    #[test]
+    fn round_trips_request_bodies() {
+        let body = large_body();
+        for encoding in PREFERENCE {
+            let compressed = compress(encoding, body.as_bytes()).unwrap();
+            let decoded = decode_request_body(encoding.token(), compressed).unwrap();
+            assert_eq!(decoded, body.as_bytes(), "{}", encoding.token());
+        }
+        assert_eq!(
+            decode_request_body("identity", b"raw".to_vec()).unwrap(),
+            b"raw"
+        );
+        assert_eq!(decode_request_body("", b"raw".to_vec()).unwrap(), b"raw");
+    }
+
+    #[test]
+    fn rejects_bodies_that_decompress_past_the_limit() {
+        let compressed = compress(Encoding::Gzip, &[0; 4096]).unwrap();
+        let err = decode_with_limit("gzip", compressed.clone(), 4095).unwrap_err();
+        assert_eq!(err.status_code(), 413);
+        assert_eq!(
+            decode_with_limit("gzip", compressed, 4096).unwrap().len(),
+            4096
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_corrupt_encodings() {
+        let err = decode_request_body("compress", b"raw".to_vec()).unwrap_err();
+        assert_eq!(err.status_code(), 415);
+        let err = decode_request_body("gzip", b"not gzip".to_vec()).unwrap_err();
+        assert_eq!(err.status_code(), 400);
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::json;
+use sqlx::postgres::PgDatabaseError;
+
+use super::response::Response;
+use crate::util::app_env::is_production;
+use crate::util::fields::FieldsError;
+use crate::util::filter::FilterError;
+use crate::util::pagination::CursorError;
+
+/// Application errors, rendered as RFC 7807 `application/problem+json` responses.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    /// Carries the methods the resource does allow, sent back in `Allow`.
+    MethodNotAllowed(Vec<String>),
+    RequestTimeout(String),
+    Conflict(String),
+    PayloadTooLarge(String),
+    UnsupportedMediaType(String),
+    RangeNotSatisfiable(String),
+    UnprocessableEntity(String),
+    UpgradeRequired(String),
+    Internal(String),
+    GatewayTimeout(String),
+    Database(sqlx::Error),
+}
+
+/// How a Postgres integrity constraint violation (SQLSTATE class 23) is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Violation {
+    /// unique_violation, foreign_key_violation, restrict_violation, exclusion_violation
+    Conflict,
+    /// not_null_violation
+    MissingValue,
+    /// check_violation
+    Check,
+}
+
+fn constraint_violation(err: &sqlx::Error) -> Option<Violation> {
+    let sqlx::Error::Database(db) = err else {
+        return None;
+    };
+    match db.code()?.as_ref() {
+        "23505" | "23503" | "23001" | "23P01" => Some(Violation::Conflict),
+        "23502" => Some(Violation::MissingValue),
+        "23514" => Some(Violation::Check),
+        _ => None,
+    }
+}
+
+impl AppError {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            AppError::BadRequest(_) => 400,
+            AppError::Unauthorized(_) => 401,
+            AppError::Forbidden(_) => 403,
+            AppError::NotFound(_) => 404,
+            AppError::MethodNotAllowed(_) => 405,
+            AppError::RequestTimeout(_) => 408,
+            AppError::Conflict(_) => 409,
+            AppError::PayloadTooLarge(_) => 413,
+            AppError::UnsupportedMediaType(_) => 415,
+            AppError::RangeNotSatisfiable(_) => 416,
+            AppError::UnprocessableEntity(_) => 422,
+            AppError::UpgradeRequired(_) => 426,
+            AppError::Internal(_) => 500,
+            AppError::GatewayTimeout(_) => 504,
+            AppError::Database(sqlx::Error::RowNotFound) => 404,
+            AppError::Database(err) => match constraint_violation(err) {
+                Some(Violation::Conflict) => 409,
+                Some(Violation::MissingValue) => 400,
+                Some(Violation::Check) => 422,
+                None => 500,
+            },
+        }
+    }
+
+    fn detail(&self) -> String {
+        self.detail_for(is_production())
+    }
+
+    /// The client-facing detail; `production` drops constraint names and internal messages.
+    fn detail_for(&self, production: bool) -> String {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::RequestTimeout(msg)
+            | AppError::Conflict(msg)
+            | AppError::PayloadTooLarge(msg)
+            | AppError::UnsupportedMediaType(msg)
+            | AppError::RangeNotSatisfiable(msg)
+            | AppError::UnprocessableEntity(msg)
+            | AppError::UpgradeRequired(msg)
+            | AppError::GatewayTimeout(msg) => msg.clone(),
+            AppError::MethodNotAllowed(_) => {
+                "The method is not allowed for this resource.".to_string()
+            }
+            AppError::Database(sqlx::Error::RowNotFound) => "Resource not found.".to_string(),
+            AppError::Database(err @ sqlx::Error::Database(db))
+                if let Some(violation) = constraint_violation(err) =>
+            {
+                let message = match violation {
+                    Violation::Conflict => "The request conflicts with existing data",
+                    Violation::MissingValue => "A required value is missing",
+                    Violation::Check => "A value is not allowed",
+                };
+                let name = match violation {
+                    Violation::MissingValue => db
+                        .try_downcast_ref::<PgDatabaseError>()
+                        .and_then(PgDatabaseError::column),
+                    _ => db.constraint(),
+                };
+                match (name, production) {
+                    (Some(name), false) => format!("{message} ({name})."),
+                    _ => format!("{message}."),
+                }
+            }
+            AppError::Internal(_) | AppError::Database(_) if production => {
+                "An unexpected error occurred.".to_string()
+            }
+            AppError::Internal(msg) => msg.clone(),
+            AppError::Database(err) => err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Database(err) => write!(f, "database error: {}", err),
+            AppError::Internal(msg) => write!(f, "internal error: {}", msg),
+            other => write!(f, "{}", other.detail()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl From<CursorError> for AppError {
+    fn from(err: CursorError) -> Self {
+        match err {
+            CursorError::InvalidCursor => AppError::BadRequest(
+                "Invalid pagination cursor. Use a next_cursor or prev_cursor returned by this listing."
+                    .to_string(),
+            ),
+            CursorError::Build(err) => AppError::Internal(err.to_string()),
+            CursorError::Database(err) => AppError::Database(err),
+        }
+    }
+}
+
+impl From<FilterError> for AppError {
+    fn from(err: FilterError) -> Self {
+        AppError::BadRequest(err.to_string())
+    }
+}
+
+impl From<FieldsError> for AppError {
+    fn from(err: FieldsError) -> Self {
+        AppError::BadRequest(err.to_string())
+    }
+}
+
+impl From<AppError> for Response {
+    fn from(err: AppError) -> Self {
+        let status_code = err.status_code();
+        if status_code >= 500 {
+            eprintln!("{}", err);
+        }
+
+        let mut headers = HashMap::new();
+        headers.insert(
+            "Content-Type".to_string(),
+            "application/problem+json".to_string(),
+        );
+        if let AppError::MethodNotAllowed(allowed) = &err {
+            headers.insert("Allow".to_string(), allowed.join(", "));
+        }
+        let body = json!({
+            "type": "about:blank",
+            "title": Response::status_text(status_code),
+            "status": status_code,
+            "detail": err.detail(),
+        });
+
+        Response {
+            status_code,
+            headers,
+            body: body.to_string(),
+            stream: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_not_allowed_lists_allowed_methods() {
+        let response: Response =
+            AppError::MethodNotAllowed(vec!["GET".to_string(), "POST".to_string()]).into();
+        assert_eq!(response.status_code, 405);
+        assert_eq!(
+            response.headers.get("Allow").map(String::as_str),
+            Some("GET, POST")
+        );
+    }
+
+    /// A driver error carrying just a SQLSTATE and a constraint name.
+    #[derive(Debug)]
+    struct FakeDbError {
+        code: &'static str,
+        constraint: Option<&'static str>,
+    }
+
+    impl fmt::Display for FakeDbError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "error with SQLSTATE {}", self.code)
+        }
+    }
+
+    impl std::error::Error for FakeDbError {}
+
+    impl sqlx::error::DatabaseError for FakeDbError {
+        fn message(&self) -> &str {
+            "fake"
+        }
+
+        fn code(&self) -> Option<std::borrow::Cow<'_, str>> {
+            Some(self.code.into())
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            self.constraint
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> sqlx::error::ErrorKind {
+            sqlx::error::ErrorKind::Other
+        }
+    }
+
+    fn db_error(code: &'static str, constraint: Option<&'static str>) -> AppError {
+        AppError::Database(sqlx::Error::Database(Box::new(FakeDbError {
+            code,
+            constraint,
+        })))
+    }
+
+    #[test]
+    fn maps_database_errors_to_statuses() {
+        assert_eq!(
+            AppError::Database(sqlx::Error::RowNotFound).status_code(),
+            404
+        );
+        assert_eq!(db_error("23505", Some("USER_email_key")).status_code(), 409);
+        assert_eq!(
+            db_error("23503", Some("post_user_id_fkey")).status_code(),
+            409
+        );
+        assert_eq!(db_error("23502", None).status_code(), 400);
+        assert_eq!(db_error("23514", Some("age_positive")).status_code(), 422);
+        assert_eq!(db_error("42P01", None).status_code(), 500);
+        assert_eq!(
+            AppError::Database(sqlx::Error::PoolTimedOut).status_code(),
+            500
+        );
+    }
+
+    #[test]
+    fn names_the_constraint_outside_production() {
+        let err = db_error("23505", Some("USER_email_key"));
+        assert_eq!(
+            err.detail_for(false),
+            "The request conflicts with existing data (USER_email_key)."
+        );
+        assert_eq!(
+            err.detail_for(true),
+            "The request conflicts with existing data."
+        );
+        assert_eq!(
+            db_error("23514", Some("age_positive")).detail_for(true),
+            "A value is not allowed."
+        );
+    }
+
+    #[test]
+    fn suppresses_internal_detail_in_production() {
+        let internal = AppError::Internal("pool exhausted on replica-2".to_string());
+        assert_eq!(internal.detail_for(false), "pool exhausted on replica-2");
+        assert_eq!(internal.detail_for(true), "An unexpected error occurred.");
+
+        let database = db_error("42P01", None);
+        assert_eq!(
+            database.detail_for(false),
+            "error returned from database: error with SQLSTATE 42P01"
+        );
+        assert_eq!(database.detail_for(true), "An unexpected error occurred.");
+
+        let not_found = AppError::Database(sqlx::Error::RowNotFound);
+        assert_eq!(not_found.detail_for(true), "Resource not found.");
+    }
+
+    #[test]
+    fn rejects_unknown_fields_with_400() {
+        let err: AppError = FieldsError::UnknownField("password".to_string()).into();
+        assert_eq!(err.status_code(), 400);
+        assert_eq!(err.detail(), "Unknown field 'password' in 'fields'.");
+    }
+
+    #[test]
+    fn renders_problem_json() {
+        let response: Response = AppError::UnprocessableEntity("Bad value.".to_string()).into();
+        let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(response.status_code, 422);
+        assert_eq!(body["title"], "Unprocessable Entity");
+        assert_eq!(body["detail"], "Bad value.");
+        assert!(!response.headers.contains_key("Allow"));
+    }
+}
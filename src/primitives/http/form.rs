@@ -0,0 +1,613 @@
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use super::error::AppError;
+use crate::util::url::form_decode;
+
+/// A parsed `application/x-www-form-urlencoded` or `multipart/form-data` body.
+#[derive(Debug, Default)]
+pub struct Form {
+    pub fields: Vec<FormField>,
+    pub files: Vec<FormFile>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct FormField {
+    pub name: String,
+    pub value: String,
+    /// Only set for multipart parts that declared one.
+    pub content_type: Option<String>,
+}
+
+/// A multipart part that carried a `filename`.
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct FormFile {
+    pub name: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: u64,
+    data: FileData,
+}
+
+#[derive(Debug)]
+enum FileData {
+    Memory(Vec<u8>),
+    Temp(TempFile),
+}
+
+/// A spooled upload; the file is removed when dropped unless it was persisted.
+#[derive(Debug)]
+struct TempFile {
+    path: PathBuf,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[allow(dead_code)]
+impl Form {
+    /// The first value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| f.value.as_str())
+    }
+
+    /// Every value of the field `name`, in body order (e.g. repeated checkboxes).
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|f| f.name == name)
+            .map(|f| f.value.as_str())
+            .collect()
+    }
+
+    pub fn file(&self, name: &str) -> Option<&FormFile> {
+        self.files.iter().find(|f| f.name == name)
+    }
+
+    /// Takes ownership of the first file named `name`, e.g. to persist it.
+    pub fn take_file(&mut self, name: &str) -> Option<FormFile> {
+        let index = self.files.iter().position(|f| f.name == name)?;
+        Some(self.files.remove(index))
+    }
+}
+
+#[allow(dead_code)]
+impl FormFile {
+    /// Where the upload was spooled, if it was too large to keep in memory.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            FileData::Memory(_) => None,
+            FileData::Temp(temp) => Some(&temp.path),
+        }
+    }
+
+    /// Reads the whole upload into memory.
+    pub async fn bytes(&self) -> io::Result<Vec<u8>> {
+        match &self.data {
+            FileData::Memory(bytes) => Ok(bytes.clone()),
+            FileData::Temp(temp) => tokio::fs::read(&temp.path).await,
+        }
+    }
+
+    /// Moves the upload to `dest`, falling back to a copy across filesystems.
+    pub async fn persist(self, dest: &Path) -> io::Result<()> {
+        match self.data {
+            FileData::Memory(bytes) => tokio::fs::write(dest, bytes).await,
+            FileData::Temp(temp) => {
+                if tokio::fs::rename(&temp.path, dest).await.is_err() {
+                    tokio::fs::copy(&temp.path, dest).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Limits {
+    max_file_size: u64,
+    max_field_size: u64,
+    max_parts: usize,
+    memory_threshold: usize,
+    temp_dir: PathBuf,
+}
+
+fn limits() -> &'static Limits {
+    static LIMITS: OnceLock<Limits> = OnceLock::new();
+    LIMITS.get_or_init(|| {
+        let number = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        Limits {
+            max_file_size: number("MULTIPART_MAX_FILE_SIZE", 50 * 1024 * 1024),
+            max_field_size: number("MULTIPART_MAX_FIELD_SIZE", 1024 * 1024),
+            max_parts: number("MULTIPART_MAX_PARTS", 100) as usize,
+            memory_threshold: number("MULTIPART_MEMORY_THRESHOLD", 256 * 1024) as usize,
+            temp_dir: env::var("MULTIPART_TEMP_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| env::temp_dir()),
+        }
+    })
+}
+
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Splits `; key=value` parameters, unquoting quoted values.
+fn header_params(value: &str) -> Vec<(String, String)> {
+    value
+        .split(';')
+        .skip(1)
+        .filter_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .map(|v| v.replace("\\\"", "\""))
+                .unwrap_or_else(|| value.to_string());
+            Some((key.trim().to_ascii_lowercase(), value))
+        })
+        .collect()
+}
+
+pub fn multipart_boundary(content_type: &str) -> Option<String> {
+    if media_type(content_type) != "multipart/form-data" {
+        return None;
+    }
+    header_params(content_type)
+        .into_iter()
+        .find(|(k, _)| k == "boundary")
+        .map(|(_, v)| v)
+        .filter(|b| !b.is_empty() && b.len() <= 70)
+}
+
+pub fn is_urlencoded(content_type: &str) -> bool {
+    media_type(content_type) == "application/x-www-form-urlencoded"
+}
+
+/// Parses an `application/x-www-form-urlencoded` body. The multipart limits apply:
+/// more than `MULTIPART_MAX_PARTS` fields or a field over `MULTIPART_MAX_FIELD_SIZE`
+/// answers 413.
+pub fn parse_urlencoded(body: &[u8]) -> Result<Form, AppError> {
+    let limits = limits();
+    let invalid = || AppError::BadRequest("Invalid urlencoded form body.".to_string());
+    let body = std::str::from_utf8(body).map_err(|_| invalid())?;
+
+    let mut form = Form::default();
+    for pair in body.split('&').filter(|p| !p.is_empty()) {
+        if form.fields.len() >= limits.max_parts {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Form body has more than {} fields.",
+                limits.max_parts
+            )));
+        }
+        if pair.len() as u64 > limits.max_field_size {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Form field exceeds {} bytes.",
+                limits.max_field_size
+            )));
+        }
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        form.fields.push(FormField {
+            name: form_decode(name).ok_or_else(invalid)?,
+            value: form_decode(value).ok_or_else(invalid)?,
+            content_type: None,
+        });
+    }
+    Ok(form)
+}
+
+const READ_SIZE: usize = 64 * 1024;
+const MAX_PART_HEADERS: usize = 16 * 1024;
+
+/// Incremental reader over a multipart body. Only a window of the body is buffered,
+/// so a part is handed out in chunks as soon as they cannot be the start of a delimiter.
+struct MultipartReader<'r, R> {
+    reader: &'r mut R,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: AsyncRead + Unpin> MultipartReader<'_, R> {
+    async fn fill(&mut self) -> io::Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        let start = self.buf.len();
+        self.buf.resize(start + READ_SIZE, 0);
+        let n = self.reader.read(&mut self.buf[start..]).await?;
+        self.buf.truncate(start + n);
+        if n == 0 {
+            self.eof = true;
+        }
+        Ok(n > 0)
+    }
+
+    /// Reads one CRLF-terminated line, without the line ending.
+    async fn line(&mut self, max: usize) -> Result<Vec<u8>, AppError> {
+        loop {
+            if let Some(pos) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line = self.buf[..pos].to_vec();
+                self.buf.drain(..pos + 2);
+                return Ok(line);
+            }
+            if self.buf.len() > max {
+                return Err(malformed("part headers are too large"));
+            }
+            if !self.fill().await.map_err(read_error)? {
+                return Err(malformed("unexpected end of body"));
+            }
+        }
+    }
+}
+
+fn malformed(reason: &str) -> AppError {
+    AppError::BadRequest(format!("Malformed multipart body: {}.", reason))
+}
+
+fn read_error(err: io::Error) -> AppError {
+    AppError::BadRequest(format!("Failed to read the request body: {}", err))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+struct PartHeaders {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+}
+
+fn parse_part_headers(lines: &[String]) -> Result<PartHeaders, AppError> {
+    let mut disposition = None;
+    let mut content_type = None;
+    for line in lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if key.trim().eq_ignore_ascii_case("Content-Disposition") {
+            disposition = Some(value.trim().to_string());
+        } else if key.trim().eq_ignore_ascii_case("Content-Type") {
+            content_type = Some(value.trim().to_string());
+        }
+    }
+
+    let disposition = disposition.ok_or_else(|| malformed("part without Content-Disposition"))?;
+    let params = header_params(&disposition);
+    let param = |key: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+    let name = param("name").ok_or_else(|| malformed("part without a name"))?;
+    // RFC 5987 `filename*=UTF-8''...` takes precedence over the plain parameter.
+    let filename = param("filename*")
+        .and_then(|v| {
+            v.split_once("''")
+                .and_then(|(_, encoded)| crate::util::url::percent_decode(encoded))
+        })
+        .or_else(|| param("filename"));
+
+    Ok(PartHeaders {
+        name,
+        filename,
+        content_type,
+    })
+}
+
+/// Accumulates a part in memory and spills it to a temp file past the memory threshold.
+struct PartSink {
+    memory: Vec<u8>,
+    file: Option<(File, TempFile)>,
+    size: u64,
+    limit: u64,
+    spill: bool,
+}
+
+impl PartSink {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), AppError> {
+        self.size += chunk.len() as u64;
+        if self.size > self.limit {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Multipart part exceeds {} bytes.",
+                self.limit
+            )));
+        }
+        let limits = limits();
+        if self.spill
+            && self.file.is_none()
+            && self.memory.len() + chunk.len() > limits.memory_threshold
+        {
+            let path = limits
+                .temp_dir
+                .join(format!("upload-{}", Uuid::new_v4().simple()));
+            let temp = TempFile { path };
+            let mut file = File::create(&temp.path).await.map_err(spool_error)?;
+            file.write_all(&self.memory).await.map_err(spool_error)?;
+            self.memory = Vec::new();
+            self.file = Some((file, temp));
+        }
+        match &mut self.file {
+            Some((file, _)) => file.write_all(chunk).await.map_err(spool_error),
+            None => {
+                self.memory.extend_from_slice(chunk);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn spool_error(err: io::Error) -> AppError {
+    AppError::Internal(format!("Failed to spool upload to disk: {}", err))
+}
+
+/// Parses a `multipart/form-data` body (RFC 7578) from `reader` as it arrives.
+/// Parts with a filename larger than `MULTIPART_MEMORY_THRESHOLD` are written to
+/// temp files; size and part-count limits answer 413.
+pub async fn read_multipart<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    boundary: &str,
+) -> Result<Form, AppError> {
+    let limits = limits();
+    let mut reader = MultipartReader {
+        reader,
+        buf: Vec::new(),
+        eof: false,
+    };
+    let dash_boundary = format!("--{}", boundary);
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+
+    // Skip the preamble up to the first boundary line.
+    loop {
+        let line = reader.line(MAX_PART_HEADERS).await?;
+        if line.trim_ascii_end() == dash_boundary.as_bytes() {
+            break;
+        }
+        if line.trim_ascii_end() == format!("{}--", dash_boundary).as_bytes() {
+            return Ok(Form::default());
+        }
+    }
+
+    let mut form = Form::default();
+    let mut parts = 0;
+    loop {
+        parts += 1;
+        if parts > limits.max_parts {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Multipart body has more than {} parts.",
+                limits.max_parts
+            )));
+        }
+
+        let mut lines = Vec::new();
+        let mut header_bytes = 0;
+        loop {
+            let line = reader.line(MAX_PART_HEADERS).await?;
+            if line.is_empty() {
+                break;
+            }
+            header_bytes += line.len();
+            if header_bytes > MAX_PART_HEADERS {
+                return Err(malformed("part headers are too large"));
+            }
+            lines.push(String::from_utf8_lossy(&line).to_string());
+        }
+        let headers = parse_part_headers(&lines)?;
+
+        let is_file = headers.filename.is_some();
+        let mut sink = PartSink {
+            memory: Vec::new(),
+            file: None,
+            size: 0,
+            limit: if is_file {
+                limits.max_file_size
+            } else {
+                limits.max_field_size
+            },
+            spill: is_file,
+        };
+
+        // Hand out everything that cannot be the start of the next delimiter.
+        loop {
+            if let Some(pos) = find(&reader.buf, &delimiter) {
+                sink.write(&reader.buf[..pos]).await?;
+                reader.buf.drain(..pos + delimiter.len());
+                break;
+            }
+            let safe = reader.buf.len().saturating_sub(delimiter.len() - 1);
+            if safe > 0 {
+                sink.write(&reader.buf[..safe]).await?;
+                reader.buf.drain(..safe);
+            }
+            if !reader.fill().await.map_err(read_error)? {
+                return Err(malformed("unexpected end of body"));
+            }
+        }
+
+        match headers.filename {
+            Some(filename) => {
+                let data = match sink.file {
+                    Some((mut file, temp)) => {
+                        file.flush().await.map_err(spool_error)?;
+                        FileData::Temp(temp)
+                    }
+                    None => FileData::Memory(sink.memory),
+                };
+                form.files.push(FormFile {
+                    name: headers.name,
+                    filename,
+                    content_type: headers
+                        .content_type
+                        .unwrap_or_else(|| "application/octet-stream".to_string()),
+                    size: sink.size,
+                    data,
+                });
+            }
+            None => form.fields.push(FormField {
+                name: headers.name,
+                value: String::from_utf8(sink.memory)
+                    .map_err(|_| malformed("field value is not valid UTF-8"))?,
+                content_type: headers.content_type,
+            }),
+        }
+
+        // After a delimiter comes either `--` (end) or CRLF, with optional whitespace.
+        while reader.buf.len() < 2 && reader.fill().await.map_err(read_error)? {}
+        if reader.buf.starts_with(b"--") {
+            return Ok(form);
+        }
+        let rest = reader.line(MAX_PART_HEADERS).await?;
+        if !rest.trim_ascii().is_empty() {
+            return Err(malformed("invalid boundary line"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(name: &str, value: &str) -> String {
+        format!(
+            "--XyZ\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            name, value
+        )
+    }
+
+    async fn parse(body: &[u8]) -> Result<Form, AppError> {
+        let mut reader = body;
+        read_multipart(&mut reader, "XyZ").await
+    }
+
+    #[test]
+    fn reads_the_boundary_parameter() {
+        assert_eq!(
+            multipart_boundary("multipart/form-data; boundary=XyZ").as_deref(),
+            Some("XyZ")
+        );
+        assert_eq!(
+            multipart_boundary("Multipart/Form-Data; charset=utf-8; boundary=\"a b=c\"").as_deref(),
+            Some("a b=c")
+        );
+        assert_eq!(multipart_boundary("multipart/form-data"), None);
+        assert_eq!(
+            multipart_boundary("multipart/form-data; boundary=\"\""),
+            None
+        );
+        assert_eq!(multipart_boundary("multipart/mixed; boundary=XyZ"), None);
+        let long = format!("multipart/form-data; boundary={}", "a".repeat(71));
+        assert_eq!(multipart_boundary(&long), None);
+    }
+
+    #[tokio::test]
+    async fn parses_fields_and_files() {
+        let body = format!(
+            "preamble\r\n{}--XyZ\r\nContent-Disposition: form-data; name=\"avatar\"; filename*=UTF-8''caf%C3%A9.png\r\nContent-Type: image/png\r\n\r\n\x00\x01PNG\r\n--XyZ--\r\n",
+            part("title", "a\r\nb")
+        );
+        let form = parse(body.as_bytes()).await.unwrap();
+        assert_eq!(form.get("title"), Some("a\r\nb"));
+        let file = form.file("avatar").unwrap();
+        assert_eq!(file.filename, "café.png");
+        assert_eq!(file.content_type, "image/png");
+        assert_eq!(file.size, 5);
+        assert_eq!(file.bytes().await.unwrap(), b"\x00\x01PNG");
+        assert!(file.path().is_none());
+    }
+
+    #[tokio::test]
+    async fn spools_large_files_and_removes_them_on_drop() {
+        let content = "x".repeat(limits().memory_threshold + 1);
+        let body = format!(
+            "--XyZ\r\nContent-Disposition: form-data; name=\"f\"; filename=\"big.txt\"\r\n\r\n{}\r\n--XyZ--\r\n",
+            content
+        );
+        let mut form = parse(body.as_bytes()).await.unwrap();
+        let file = form.take_file("f").unwrap();
+        let path = file.path().unwrap().to_path_buf();
+        assert_eq!(tokio::fs::read(&path).await.unwrap().len(), content.len());
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn enforces_multipart_limits() {
+        let limits = limits();
+        let mut body = (0..=limits.max_parts)
+            .map(|i| part(&format!("f{}", i), "v"))
+            .collect::<String>();
+        body.push_str("--XyZ--\r\n");
+        assert_eq!(parse(body.as_bytes()).await.unwrap_err().status_code(), 413);
+
+        let field = "v".repeat(limits.max_field_size as usize + 1);
+        let body = format!("{}--XyZ--\r\n", part("big", &field));
+        assert_eq!(parse(body.as_bytes()).await.unwrap_err().status_code(), 413);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_bodies() {
+        let truncated = "--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nvalue";
+        let no_name = "--XyZ\r\nContent-Disposition: form-data\r\n\r\nv\r\n--XyZ--\r\n";
+        let no_disposition = "--XyZ\r\nContent-Type: text/plain\r\n\r\nv\r\n--XyZ--\r\n";
+        for body in [truncated, no_name, no_disposition] {
+            assert_eq!(
+                parse(body.as_bytes()).await.unwrap_err().status_code(),
+                400,
+                "{body}"
+            );
+        }
+        assert!(parse(b"--XyZ--\r\n").await.unwrap().fields.is_empty());
+    }
+
+    #[test]
+    fn parses_urlencoded_bodies() {
+        let form = parse_urlencoded(b"a=1&b=x+y%21&a=2&flag&=empty").unwrap();
+        assert_eq!(form.get_all("a"), vec!["1", "2"]);
+        assert_eq!(form.get("b"), Some("x y!"));
+        assert_eq!(form.get("flag"), Some(""));
+        assert_eq!(form.get(""), Some("empty"));
+        assert_eq!(parse_urlencoded(b"a=%zz").unwrap_err().status_code(), 400);
+    }
+
+    #[test]
+    fn enforces_urlencoded_limits() {
+        let limits = limits();
+        let many = vec!["a=1"; limits.max_parts + 1].join("&");
+        assert_eq!(
+            parse_urlencoded(many.as_bytes()).unwrap_err().status_code(),
+            413
+        );
+        assert!(parse_urlencoded(vec!["a=1"; limits.max_parts].join("&").as_bytes()).is_ok());
+
+        let big = format!("a={}", "v".repeat(limits.max_field_size as usize));
+        assert_eq!(
+            parse_urlencoded(big.as_bytes()).unwrap_err().status_code(),
+            413
+        );
+    }
+}
@@ -0,0 +1,656 @@
+use std::collections::HashMap;
+use std::env;
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use bytes::Bytes;
+use h2::server::SendResponse;
+use h2::{RecvStream, SendStream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+use super::request::{Connection, find_header};
+use super::response::Response;
+
+/// The client connection preface (RFC 9113 §3.4).
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Frames larger than this are refused until the client raises it; `h2` advertises
+/// the protocol default.
+const MAX_FRAME_SIZE: usize = 16_384;
+
+/// Headers never carried as HTTP/2 fields: the hop-by-hop ones (RFC 9113 §8.2.2) and
+/// `host`, which becomes `:authority`.
+const CONNECTION_HEADERS: [&str; 7] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "http2-settings",
+    "host",
+];
+
+pub struct Http2Config {
+    /// `HTTP2` (default on). Set to `off` to only speak HTTP/1.1.
+    pub enabled: bool,
+    /// `HTTP2_MAX_CONCURRENT_STREAMS`, advertised to clients (default 100).
+    pub max_concurrent_streams: u32,
+    /// `HTTP2_IDLE_TIMEOUT_SECS`: a connection without open streams for this long is
+    /// closed with `GOAWAY` (default 60, 0 disables).
+    pub idle_timeout: Option<Duration>,
+}
+
+pub fn config() -> &'static Http2Config {
+    static CONFIG: OnceLock<Http2Config> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let number = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        Http2Config {
+            enabled: env::var("HTTP2")
+                .map(|v| !matches!(v.to_ascii_lowercase().as_str(), "off" | "false" | "0"))
+                .unwrap_or(true),
+            max_concurrent_streams: number("HTTP2_MAX_CONCURRENT_STREAMS", 100).max(1) as u32,
+            idle_timeout: match number("HTTP2_IDLE_TIMEOUT_SECS", 60) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+        }
+    })
+}
+
+/// Whether a plaintext connection opens with the HTTP/2 preface, i.e. the client
+/// speaks HTTP/2 with prior knowledge. Only peeks, so nothing is consumed.
+pub async fn has_preface(stream: &TcpStream) -> io::Result<bool> {
+    let mut buf = [0u8; PREFACE.len()];
+    loop {
+        let n = stream.peek(&mut buf).await?;
+        if n == 0 || !PREFACE.starts_with(&buf[..n]) {
+            return Ok(false);
+        }
+        if n == PREFACE.len() {
+            return Ok(true);
+        }
+        // A prefix of the preface arrived; wait for the rest to be peekable.
+        sleep(Duration::from_millis(5)).await;
+    }
+}
+
+/// Appends an HPACK integer with an `prefix_bits`-bit prefix (RFC 7541 §5.1).
+fn encode_integer(mut value: usize, prefix_bits: u8, flags: u8, out: &mut Vec<u8>) {
+    let max_prefix = (1usize << prefix_bits) - 1;
+    if value < max_prefix {
+        out.push(flags | value as u8);
+        return;
+    }
+    out.push(flags | max_prefix as u8);
+    value -= max_prefix;
+    while value >= 128 {
+        out.push((value % 128 + 128) as u8);
+        value /= 128;
+    }
+    out.push(value as u8);
+}
+
+/// Encodes headers as "literal without indexing" fields, which leave the decoder's
+/// dynamic table untouched, so the block can be spliced into a client's stream.
+fn encode_header_block(fields: &[(String, String)]) -> Vec<u8> {
+    let mut block = Vec::new();
+    for (name, value) in fields {
+        block.push(0x00);
+        encode_integer(name.len(), 7, 0x00, &mut block);
+        block.extend_from_slice(name.as_bytes());
+        encode_integer(value.len(), 7, 0x00, &mut block);
+        block.extend_from_slice(value.as_bytes());
+    }
+    block
+}
+
+/// Serves an `Upgrade: h2c` request (RFC 7540 §3.2) on top of `h2`, which has no
+/// notion of upgrades: the upgraded request is replayed as a `HEADERS` frame on
+/// stream 1 right after the client's preface and first `SETTINGS` frame, exactly
+/// where the server would otherwise have accepted it.
+pub struct H2cUpgrade {
+    inner: Connection,
+    /// Bytes read from the client but not yet handed to `h2`.
+    head: Vec<u8>,
+    frame: Option<Vec<u8>>,
+}
+
+/// Replays an upgraded request as a `HEADERS` frame on stream 1. Returns `None`
+/// when it does not fit in a single frame, in which case the upgrade is declined.
+pub fn upgrade_frame(
+    method: &str,
+    url: &str,
+    headers: &HashMap<String, String>,
+) -> Option<Vec<u8>> {
+    let mut fields = vec![
+        (":method".to_string(), method.to_string()),
+        (":scheme".to_string(), "http".to_string()),
+        (":path".to_string(), url.to_string()),
+    ];
+    if let Some(host) = find_header(headers, "Host") {
+        fields.push((":authority".to_string(), host.to_string()));
+    }
+    for (name, value) in headers {
+        let name = name.to_ascii_lowercase();
+        if CONNECTION_HEADERS.contains(&name.as_str())
+            || (name == "te" && !value.eq_ignore_ascii_case("trailers"))
+        {
+            continue;
+        }
+        fields.push((name, value.clone()));
+    }
+
+    let block = encode_header_block(&fields);
+    if block.len() > MAX_FRAME_SIZE {
+        return None;
+    }
+    let mut frame = Vec::with_capacity(9 + block.len());
+    frame.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+    // HEADERS with END_STREAM | END_HEADERS on stream 1.
+    frame.extend_from_slice(&[0x1, 0x1 | 0x4, 0, 0, 0, 1]);
+    frame.extend_from_slice(&block);
+    Some(frame)
+}
+
+impl H2cUpgrade {
+    /// `buffered` holds anything the HTTP/1.1 parser read past the request; `frame`
+    /// comes from `upgrade_frame`.
+    pub fn new(inner: Connection, buffered: Vec<u8>, frame: Vec<u8>) -> Self {
+        Self {
+            inner,
+            head: buffered,
+            frame: Some(frame),
+        }
+    }
+
+    /// Length of the preface plus the first frame, once enough bytes arrived.
+    fn splice_point(&self) -> Option<usize> {
+        let header = self.head.get(PREFACE.len()..PREFACE.len() + 9)?;
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let end = PREFACE.len() + 9 + length;
+        (self.head.len() >= end).then_some(end)
+    }
+}
+
+impl AsyncRead for H2cUpgrade {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        while this.frame.is_some() {
+            if let Some(end) = this.splice_point() {
+                let frame = this.frame.take().unwrap_or_default();
+                this.head.splice(end..end, frame);
+                break;
+            }
+            let mut chunk = [0u8; 4096];
+            let mut read = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read))?;
+            if read.filled().is_empty() {
+                // The client went away before finishing its preface.
+                this.frame = None;
+                break;
+            }
+            this.head.extend_from_slice(read.filled());
+        }
+
+        if !this.head.is_empty() {
+            let n = this.head.len().min(buf.remaining());
+            buf.put_slice(&this.head[..n]);
+            this.head.drain(..n);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for H2cUpgrade {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// The request body of a stream as an `AsyncRead`, releasing flow-control capacity
+/// as the data is consumed.
+pub struct BodyReader {
+    body: RecvStream,
+    pending: Bytes,
+}
+
+impl BodyReader {
+    pub fn new(body: RecvStream) -> Self {
+        Self {
+            body,
+            pending: Bytes::new(),
+        }
+    }
+}
+
+impl AsyncRead for BodyReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.pending.is_empty() {
+            match ready!(self.body.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    let _ = self.body.flow_control().release_capacity(data.len());
+                    self.pending = data;
+                }
+                Some(Err(err)) => return Poll::Ready(Err(io::Error::other(err))),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let n = self.pending.len().min(buf.remaining());
+        buf.put_slice(&self.pending.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Converts a request head into the method, URL and header map `Request` uses.
+/// Repeated headers are joined with `, ` (`; ` for cookies) and `:authority` becomes `host`.
+pub fn request_head(parts: &http::request::Parts) -> (String, String, HashMap<String, String>) {
+    let url = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "/".to_string());
+
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for (name, value) in &parts.headers {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        let separator = if name == http::header::COOKIE {
+            "; "
+        } else {
+            ", "
+        };
+        headers
+            .entry(name.as_str().to_string())
+            .and_modify(|existing| {
+                existing.push_str(separator);
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    if let Some(authority) = parts.uri.authority() {
+        headers
+            .entry("host".to_string())
+            .or_insert_with(|| authority.to_string());
+    }
+    (parts.method.to_string(), url, headers)
+}
+
+fn stream_error(err: h2::Error) -> io::Error {
+    match err.get_io() {
+        Some(io) => io::Error::new(io.kind(), err.to_string()),
+        None => io::Error::new(io::ErrorKind::BrokenPipe, err),
+    }
+}
+
+/// Sends `data` as it fits into the peer's flow-control window. `limit` bounds each
+/// wait for capacity, like the per-write deadline on HTTP/1.1.
+async fn send_data(
+    send: &mut SendStream<Bytes>,
+    mut data: Bytes,
+    end: bool,
+    limit: Option<Duration>,
+) -> io::Result<()> {
+    if data.is_empty() {
+        if end {
+            send.send_data(Bytes::new(), true).map_err(stream_error)?;
+        }
+        return Ok(());
+    }
+    while !data.is_empty() {
+        send.reserve_capacity(data.len());
+        let capacity = poll_fn(|cx| send.poll_capacity(cx));
+        let granted = match limit {
+            Some(limit) => timeout(limit, capacity)
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "write timed out"))?,
+            None => capacity.await,
+        };
+        let granted = match granted {
+            Some(result) => result.map_err(stream_error)?,
+            None => return Err(io::ErrorKind::BrokenPipe.into()),
+        };
+        if granted == 0 {
+            continue;
+        }
+        let chunk = data.split_to(granted.min(data.len()));
+        send.send_data(chunk, end && data.is_empty())
+            .map_err(stream_error)?;
+    }
+    Ok(())
+}
+
+/// Writes `response` to an HTTP/2 stream, streaming the body if there is one.
+/// Returns the number of body bytes sent.
+pub async fn write_response(
+    respond: &mut SendResponse<Bytes>,
+    response: &mut Response,
+    head_only: bool,
+    limit: Option<Duration>,
+) -> io::Result<usize> {
+    let mut builder = http::Response::builder().status(response.status_code);
+    for (name, value) in &response.headers {
+        let name = name.to_ascii_lowercase();
+        if CONNECTION_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        builder = builder.header(name, value.as_str());
+    }
+    let no_body = matches!(response.status_code, 100..=199 | 204 | 304);
+    if response.stream.is_none() && !no_body && response.header("Content-Length").is_none() {
+        builder = builder.header("content-length", response.body.len());
+    }
+    let head = builder
+        .body(())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let stream = response.stream.take();
+    let body = std::mem::take(&mut response.body);
+    let end_now = head_only || no_body || (stream.is_none() && body.is_empty());
+    let mut send = respond.send_response(head, end_now).map_err(stream_error)?;
+    if end_now {
+        return Ok(0);
+    }
+
+    let Some(mut stream) = stream else {
+        let sent = body.len();
+        send_data(&mut send, Bytes::from(body), true, limit).await?;
+        return Ok(sent);
+    };
+    let mut sent = 0;
+    while let Some(chunk) = stream.recv().await {
+        sent += chunk.len();
+        send_data(&mut send, Bytes::from(chunk), false, limit).await?;
+    }
+    send_data(&mut send, Bytes::new(), true, limit).await?;
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Connects a client that sends `chunks` (pausing between them) and runs
+    /// `has_preface` on the server side. `None` means it was still waiting after
+    /// the client finished, which the caller bounds with the handshake timeout.
+    async fn peek_preface(chunks: &[&'static [u8]]) -> (Option<bool>, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+
+        let send = async {
+            for chunk in chunks {
+                client.write_all(chunk).await.unwrap();
+                sleep(Duration::from_millis(20)).await;
+            }
+            client.shutdown().await.unwrap();
+        };
+        let detect = timeout(Duration::from_millis(200), has_preface(&server));
+        let (detected, ()) = tokio::join!(detect, send);
+        let mut read = Vec::new();
+        server.read_to_end(&mut read).await.unwrap();
+        (detected.ok().map(Result::unwrap), read)
+    }
+
+    #[tokio::test]
+    async fn detects_the_preface_without_consuming_it() {
+        let (detected, read) = peek_preface(&[PREFACE]).await;
+        assert_eq!(detected, Some(true));
+        assert_eq!(read, PREFACE);
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_split_preface() {
+        let (detected, _) = peek_preface(&[&PREFACE[..10], &PREFACE[10..]]).await;
+        assert_eq!(detected, Some(true));
+    }
+
+    #[tokio::test]
+    async fn rejects_http1_and_closed_connections() {
+        let (detected, read) = peek_preface(&[b"GET / HTTP/1.1\r\n\r\n"]).await;
+        assert_eq!(detected, Some(false));
+        assert_eq!(read, b"GET / HTTP/1.1\r\n\r\n");
+
+        assert_eq!(peek_preface(&[b"PRI * HTTP/1.1\r\n"]).await.0, Some(false));
+        assert_eq!(peek_preface(&[]).await.0, Some(false));
+    }
+
+    #[tokio::test]
+    async fn never_accepts_a_truncated_preface() {
+        let (detected, read) = peek_preface(&[&PREFACE[..10]]).await;
+        assert_eq!(detected, None);
+        assert_eq!(read, &PREFACE[..10]);
+    }
+
+    fn decode_integer(block: &[u8], pos: &mut usize) -> usize {
+        let mut value = (block[*pos] & 0x7f) as usize;
+        *pos += 1;
+        if value < 0x7f {
+            return value;
+        }
+        let mut shift = 0;
+        loop {
+            let byte = block[*pos];
+            *pos += 1;
+            value += ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    /// Decodes the literal, non-Huffman fields `encode_header_block` writes.
+    fn decode_block(block: &[u8]) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        let string = |pos: &mut usize| {
+            let len = decode_integer(block, pos);
+            let value = String::from_utf8(block[*pos..*pos + len].to_vec()).unwrap();
+            *pos += len;
+            value
+        };
+        while pos < block.len() {
+            assert_eq!(block[pos], 0x00, "literal without indexing, new name");
+            pos += 1;
+            let name = string(&mut pos);
+            let value = string(&mut pos);
+            fields.push((name, value));
+        }
+        fields
+    }
+
+    fn upgrade_headers() -> HashMap<String, String> {
+        [
+            ("Host", "localhost:8080"),
+            ("Connection", "Upgrade, HTTP2-Settings"),
+            ("Upgrade", "h2c"),
+            ("HTTP2-Settings", "AAMAAABkAARAAAAAAAIAAAAA"),
+            ("Accept", "application/json"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn replays_the_upgraded_request_as_headers_on_stream_one() {
+        let mut headers = upgrade_headers();
+        headers.insert("TE".to_string(), "gzip".to_string());
+        headers.insert("X-Long".to_string(), "x".repeat(300));
+        let frame = upgrade_frame("GET", "/users?top=1", &headers).unwrap();
+
+        let length = u32::from_be_bytes([0, frame[0], frame[1], frame[2]]) as usize;
+        assert_eq!(frame.len(), 9 + length);
+        assert_eq!(frame[3], 0x1, "HEADERS");
+        assert_eq!(frame[4], 0x1 | 0x4, "END_STREAM | END_HEADERS");
+        assert_eq!(&frame[5..9], &[0, 0, 0, 1]);
+
+        let fields = decode_block(&frame[9..]);
+        assert_eq!(
+            &fields[..4],
+            [
+                (":method".to_string(), "GET".to_string()),
+                (":scheme".to_string(), "http".to_string()),
+                (":path".to_string(), "/users?top=1".to_string()),
+                (":authority".to_string(), "localhost:8080".to_string()),
+            ]
+        );
+        let mut regular: Vec<_> = fields[4..].to_vec();
+        regular.sort();
+        assert_eq!(
+            regular,
+            [
+                ("accept".to_string(), "application/json".to_string()),
+                ("x-long".to_string(), "x".repeat(300)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_te_trailers() {
+        let mut headers = upgrade_headers();
+        headers.insert("te".to_string(), "trailers".to_string());
+        let frame = upgrade_frame("GET", "/", &headers).unwrap();
+        assert!(decode_block(&frame[9..]).contains(&("te".to_string(), "trailers".to_string())));
+    }
+
+    #[test]
+    fn declines_requests_that_do_not_fit_one_frame() {
+        let mut headers = upgrade_headers();
+        headers.insert("Cookie".to_string(), "c".repeat(MAX_FRAME_SIZE));
+        assert_eq!(upgrade_frame("GET", "/", &headers), None);
+    }
+
+    /// Forwards the client's bytes minus its own `HEADERS` on stream 1: with h2c that
+    /// request went out as HTTP/1.1, and the server replays it from `upgrade_frame`.
+    async fn forward_without_stream_one(
+        mut from: impl AsyncRead + Unpin,
+        mut to: impl AsyncWrite + Unpin,
+    ) {
+        let mut preface = [0u8; PREFACE.len()];
+        from.read_exact(&mut preface).await.unwrap();
+        to.write_all(&preface).await.unwrap();
+        let mut header = [0u8; 9];
+        while from.read_exact(&mut header).await.is_ok() {
+            let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let mut payload = vec![0u8; length];
+            from.read_exact(&mut payload).await.unwrap();
+            let stream_id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+            if header[3] == 0x1 && stream_id == 1 {
+                continue;
+            }
+            if to.write_all(&header).await.is_err() || to.write_all(&payload).await.is_err() {
+                break;
+            }
+        }
+        let _ = to.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn serves_an_upgraded_request_over_h2c() {
+        let (client_io, client_end) = duplex(1 << 16);
+        let (server_end, server_io) = duplex(1 << 16);
+        let (client_read, mut client_write) = tokio::io::split(client_end);
+        let (mut server_read, server_write) = tokio::io::split(server_end);
+        tokio::spawn(forward_without_stream_one(client_read, server_write));
+        tokio::spawn(async move {
+            let _ = tokio::io::copy(&mut server_read, &mut client_write).await;
+            let _ = client_write.shutdown().await;
+        });
+
+        let frame = upgrade_frame("GET", "/users?top=1", &upgrade_headers()).unwrap();
+        let server = async move {
+            let upgraded = H2cUpgrade::new(Box::new(server_io), Vec::new(), frame);
+            let mut connection = h2::server::handshake(upgraded).await.unwrap();
+            let (request, mut respond) = connection.accept().await.unwrap().unwrap();
+            assert_eq!(request.uri().scheme_str(), Some("http"));
+
+            let (parts, body) = request.into_parts();
+            assert!(body.is_end_stream());
+            let (method, url, headers) = request_head(&parts);
+            assert_eq!(method, "GET");
+            assert_eq!(url, "/users?top=1");
+            assert_eq!(find_header(&headers, "Host"), Some("localhost:8080"));
+            assert_eq!(find_header(&headers, "Accept"), Some("application/json"));
+            assert_eq!(find_header(&headers, "Upgrade"), None);
+            assert_eq!(find_header(&headers, "HTTP2-Settings"), None);
+
+            let mut response = Response {
+                status_code: 200,
+                headers: HashMap::from([("Content-Type".to_string(), "text/plain".to_string())]),
+                body: "hello over h2c".to_string(),
+                stream: None,
+            };
+            let respond = async {
+                let sent = write_response(&mut respond, &mut response, false, None).await;
+                assert_eq!(sent.unwrap(), 14);
+            };
+            let drive = async { while let Some(Ok(_)) = connection.accept().await {} };
+            tokio::join!(respond, drive);
+        };
+
+        let client = async move {
+            let (mut client, connection) = h2::client::handshake(client_io).await.unwrap();
+            tokio::spawn(connection);
+            let request = http::Request::get("http://localhost:8080/users?top=1")
+                .body(())
+                .unwrap();
+            let (response, _) = client.send_request(request, true).unwrap();
+            let response = response.await.unwrap();
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers()["content-length"], "14");
+            assert_eq!(response.headers()["content-type"], "text/plain");
+
+            let mut body = response.into_body();
+            let mut received = Vec::new();
+            while let Some(chunk) = body.data().await {
+                received.extend_from_slice(&chunk.unwrap());
+            }
+            assert_eq!(received, b"hello over h2c");
+        };
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(server, client);
+        })
+        .await
+        .expect("the h2c exchange finished");
+    }
+}
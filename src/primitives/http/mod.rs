@@ -1,2 +1,8 @@
+pub mod compression;
+pub mod error;
+pub mod form;
+pub mod http2;
 pub mod request;
-pub mod response;
\ No newline at end of file
+pub mod response;
+pub mod sse;
+pub mod tls;
@@ -1,68 +1,90 @@
 use std::collections::HashMap;
-use std::fmt;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
 
 use chrono::{DateTime, Utc};
 use std::net::SocketAddr;
 
+use super::error::AppError;
+use super::form::Form;
+
+/// A client connection, plain TCP or TLS.
+pub trait ClientStream: AsyncRead + AsyncWrite + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> ClientStream for T {}
+
+pub type Connection = Box<dyn ClientStream>;
+
 pub struct Request {
+    /// Taken from a well-formed `X-Request-Id` header, otherwise a new UUID.
+    pub id: String,
     pub method: String,
     pub url: String,
     pub headers: HashMap<String, String>,
+    /// Empty for `multipart/form-data` requests, which are parsed into `form` instead.
     pub body: String,
-    pub stream: TcpStream,
+    /// Set for urlencoded and multipart bodies.
+    pub form: Option<Form>,
+    /// The HTTP/1.1 connection. `None` for HTTP/2 requests, whose connection is shared
+    /// by all of its streams.
+    pub stream: Option<Connection>,
     pub remote_addr: Option<SocketAddr>,
     pub timestamp: DateTime<Utc>,
     pub query_params: HashMap<String, String>,
+    /// Index into `routing::routes()` of the route that handled this request.
+    pub matched_route: Option<usize>,
 }
 
-impl fmt::Display for Request {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // ANSI color codes
-        const CYAN: &str = "\x1b[36m";
-        const GREEN: &str = "\x1b[32m";
-        const YELLOW: &str = "\x1b[33m";
-        const BLUE: &str = "\x1b[34m";
-        const MAGENTA: &str = "\x1b[35m";
-        const RESET: &str = "\x1b[0m";
+/// Case-insensitive lookup in a raw header map.
+pub fn find_header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
 
-        let addr = self
-            .remote_addr
-            .map(|a| format!("{MAGENTA}{}{RESET}", a, MAGENTA = MAGENTA, RESET = RESET))
-            .unwrap_or_else(|| {
-                format!("{MAGENTA}unknown{RESET}", MAGENTA = MAGENTA, RESET = RESET)
-            });
+/// Whether a comma-separated header value such as `Connection` lists `token`.
+pub fn has_token(value: Option<&str>, token: &str) -> bool {
+    value.is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+}
 
-        // Obfuscate Authorization-related headers
-        let mut obfuscated_headers = self.headers.clone();
-        for (key, value) in obfuscated_headers.iter_mut() {
-            let key_lower = key.to_ascii_lowercase();
-            if key_lower == "authorization" || key_lower == "proxy-authorization" {
-                let len = value.len();
-                if len > 4 {
-                    let half = len / 2;
-                    let (first, _) = value.split_at(half);
-                    let masked = format!("{}{}", first, "*".repeat(len - half));
-                    *value = masked;
-                } else {
-                    *value = "****".to_string();
-                }
-            }
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// The parsed form body, or 415 when the request was not a form submission.
+    #[allow(dead_code)]
+    pub fn form(&mut self) -> Result<&mut Form, AppError> {
+        self.form.as_mut().ok_or_else(|| {
+            AppError::UnsupportedMediaType(
+                "Expected an application/x-www-form-urlencoded or multipart/form-data body."
+                    .to_string(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+impl Request {
+    /// A request with no headers or body, for unit tests.
+    pub fn for_test(method: &str, url: &str) -> Self {
+        Self {
+            id: "test".to_string(),
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: HashMap::new(),
+            body: String::new(),
+            form: None,
+            stream: None,
+            remote_addr: None,
+            timestamp: Utc::now(),
+            query_params: HashMap::new(),
+            matched_route: None,
         }
-        write!(
-            f,
-            "{CYAN}[{timestamp}]{RESET} {GREEN}INFO{RESET} {addr} \"{YELLOW}{method}{RESET} {BLUE}{url}{RESET}\"\nHeaders: {:#?}\nBody: {}",
-            obfuscated_headers,
-            self.body,
-            CYAN = CYAN,
-            GREEN = GREEN,
-            YELLOW = YELLOW,
-            BLUE = BLUE,
-            RESET = RESET,
-            timestamp = self.timestamp.to_rfc3339(),
-            addr = addr,
-            method = self.method,
-            url = self.url
-        )
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
     }
 }
@@ -1,30 +1,98 @@
 use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Chunks of a streamed body. The body ends when every sender has been dropped.
+pub type BodyStream = mpsc::Receiver<Vec<u8>>;
 
 pub struct Response {
     pub status_code: u16,
     pub headers: HashMap<String, String>,
     pub body: String,
+    /// When set, the body is read from this channel and `body` is ignored.
+    pub stream: Option<BodyStream>,
+}
+
+async fn write_with_timeout<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    bytes: &[u8],
+    limit: Option<Duration>,
+) -> io::Result<()> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, writer.write_all(bytes))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "write timed out"))?,
+        None => writer.write_all(bytes).await,
+    }
 }
 
 impl Response {
-    fn status_text(code: u16) -> &'static str {
+    /// A response whose body is produced chunk by chunk. Without a `Content-Length`
+    /// header it is sent with `Transfer-Encoding: chunked`.
+    #[allow(dead_code)]
+    pub fn streaming(
+        status_code: u16,
+        headers: HashMap<String, String>,
+        stream: BodyStream,
+    ) -> Self {
+        Self {
+            status_code,
+            headers,
+            body: String::new(),
+            stream: Some(stream),
+        }
+    }
+
+    pub fn status_text(code: u16) -> &'static str {
         match code {
+            101 => "Switching Protocols",
             200 => "OK",
             201 => "Created",
             204 => "No Content",
+            206 => "Partial Content",
+            304 => "Not Modified",
             400 => "Bad Request",
             401 => "Unauthorized",
             403 => "Forbidden",
             404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            415 => "Unsupported Media Type",
+            416 => "Range Not Satisfiable",
+            422 => "Unprocessable Entity",
+            426 => "Upgrade Required",
             500 => "Internal Server Error",
             501 => "Not Implemented",
             502 => "Bad Gateway",
             503 => "Service Unavailable",
+            504 => "Gateway Timeout",
             _ => "Unknown",
         }
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
+    /// Case-insensitive header lookup.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn remove_header(&mut self, name: &str) -> Option<String> {
+        let key = self
+            .headers
+            .keys()
+            .find(|k| k.eq_ignore_ascii_case(name))
+            .cloned()?;
+        self.headers.remove(&key)
+    }
+
+    fn head(&self, chunked: bool) -> String {
         let status_line = format!(
             "HTTP/1.1 {} {}\r\n",
             self.status_code,
@@ -32,14 +100,16 @@ impl Response {
         );
         let mut response = status_line;
 
-        let has_content_length = self.headers.contains_key("Content-Length");
-        let has_connection = self.headers.contains_key("Connection");
+        let has_content_length = self.header("Content-Length").is_some();
+        let has_connection = self.header("Connection").is_some();
 
         for (key, value) in &self.headers {
             response.push_str(&format!("{}: {}\r\n", key, value));
         }
 
-        if !has_content_length {
+        if chunked {
+            response.push_str("Transfer-Encoding: chunked\r\n");
+        } else if !has_content_length && !matches!(self.status_code, 100..=199 | 204 | 304) {
             response.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
         }
 
@@ -47,7 +117,95 @@ impl Response {
             response.push_str("Connection: close\r\n");
         }
         response.push_str("\r\n");
+        response
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut response = self.head(false);
         response.push_str(&self.body);
         response.into_bytes()
     }
+
+    /// Writes the response, streaming the body if there is one. With `head_only` (a
+    /// `HEAD` request) only the head is sent, with the same headers as for `GET`. `limit`
+    /// bounds each individual write. Returns the number of body bytes sent.
+    pub async fn write_to<W: AsyncWrite + Unpin>(
+        &mut self,
+        writer: &mut W,
+        head_only: bool,
+        limit: Option<Duration>,
+    ) -> io::Result<usize> {
+        if head_only {
+            let chunked = self.stream.take().is_some() && self.header("Content-Length").is_none();
+            write_with_timeout(writer, self.head(chunked).as_bytes(), limit).await?;
+            return Ok(0);
+        }
+        let Some(mut stream) = self.stream.take() else {
+            write_with_timeout(writer, &self.to_bytes(), limit).await?;
+            return Ok(self.body.len());
+        };
+
+        let chunked = self.header("Content-Length").is_none();
+        write_with_timeout(writer, self.head(chunked).as_bytes(), limit).await?;
+
+        let mut sent = 0;
+        while let Some(chunk) = stream.recv().await {
+            // An empty chunk would terminate a chunked body early.
+            if chunk.is_empty() {
+                continue;
+            }
+            if chunked {
+                let framed =
+                    [format!("{:X}\r\n", chunk.len()).as_bytes(), &chunk, b"\r\n"].concat();
+                write_with_timeout(writer, &framed, limit).await?;
+            } else {
+                write_with_timeout(writer, &chunk, limit).await?;
+            }
+            writer.flush().await?;
+            sent += chunk.len();
+        }
+
+        if chunked {
+            write_with_timeout(writer, b"0\r\n\r\n", limit).await?;
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn head_only_sends_the_get_headers_without_the_body() {
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        tx.send(b"compressed".to_vec()).await.unwrap();
+        let mut headers = HashMap::new();
+        headers.insert("Content-Encoding".to_string(), "gzip".to_string());
+        let mut response = Response::streaming(200, headers, rx);
+
+        let mut out = Vec::new();
+        let sent = response.write_to(&mut out, true, None).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(sent, 0);
+        assert!(out.contains("Content-Encoding: gzip\r\n"));
+        assert!(out.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(out.ends_with("\r\n\r\n"));
+        assert!(!out.contains("compressed"));
+    }
+
+    #[tokio::test]
+    async fn head_only_keeps_the_length_of_a_buffered_body() {
+        let mut response = Response {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: "{\"ok\":true}".to_string(),
+            stream: None,
+        };
+        let mut out = Vec::new();
+        response.write_to(&mut out, true, None).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Content-Length: 11\r\n"));
+        assert!(out.ends_with("\r\n\r\n"));
+    }
 }
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, interval, sleep_until};
+
+use super::request::Request;
+use super::response::{BodyStream, Response};
+use crate::health;
+
+/// One `text/event-stream` event (see the HTML spec, "Server-sent events").
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+    retry: Option<Duration>,
+}
+
+#[allow(dead_code)]
+impl Event {
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn json<T: Serialize>(value: &T) -> Result<Self, serde_json::Error> {
+        Ok(Self::new(serde_json::to_string(value)?))
+    }
+
+    /// Sets the event name, dispatched to `addEventListener(name, ...)` instead of `onmessage`.
+    pub fn event(mut self, name: impl Into<String>) -> Self {
+        self.event = Some(name.into());
+        self
+    }
+
+    /// Sets the event ID. The browser sends the last one back as `Last-Event-ID`
+    /// when it reconnects.
+    pub fn id(mut self, id: impl ToString) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Tells the client how long to wait before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Serializes the event. Line breaks are not allowed in the single-line fields and
+    /// are dropped; multi-line data is sent as one `data:` line per line.
+    pub fn encode(&self) -> Vec<u8> {
+        let single_line = |value: &str| value.replace(['\r', '\n'], "");
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(id) = &self.id {
+            // An ID containing NUL is ignored by the client.
+            out.push_str(&format!("id: {}\n", single_line(id).replace('\0', "")));
+        }
+        if let Some(retry) = self.retry {
+            out.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        let data = self.data.replace("\r\n", "\n").replace('\r', "\n");
+        for line in data.split('\n') {
+            out.push_str("data: ");
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push('\n');
+        out.into_bytes()
+    }
+}
+
+/// Interval between heartbeat comments from `SSE_HEARTBEAT_SECS` (default 15, 0 disables).
+fn default_heartbeat() -> Option<Duration> {
+    static HEARTBEAT: OnceLock<Option<Duration>> = OnceLock::new();
+    *HEARTBEAT.get_or_init(|| {
+        let secs = env::var("SSE_HEARTBEAT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(15);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    })
+}
+
+/// The ID of the last event the client received before reconnecting.
+#[allow(dead_code)]
+pub fn last_event_id(request: &Request) -> Option<&str> {
+    request
+        .header("Last-Event-ID")
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+}
+
+/// A streaming `text/event-stream` response fed by a channel of events. The stream
+/// ends when every sender is dropped, when the client goes away (noticed on the next
+/// write, at the latest on the next heartbeat) or when the server starts shutting down.
+pub struct Sse {
+    events: mpsc::Receiver<Event>,
+    heartbeat: Option<Duration>,
+    retry: Option<Duration>,
+}
+
+#[allow(dead_code)]
+impl Sse {
+    pub fn new(events: mpsc::Receiver<Event>) -> Self {
+        Self {
+            events,
+            heartbeat: default_heartbeat(),
+            retry: None,
+        }
+    }
+
+    /// Overrides `SSE_HEARTBEAT_SECS` for this stream; `None` disables heartbeats.
+    pub fn heartbeat(mut self, interval: Option<Duration>) -> Self {
+        self.heartbeat = interval;
+        self
+    }
+
+    /// Sends a `retry:` hint before the first event.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// Forwards encoded events to the response body, interleaving `:` comment lines
+/// whenever the stream was quiet for a heartbeat interval. The comments keep proxies
+/// from timing out the connection and surface disconnected clients.
+fn pump(mut sse: Sse) -> BodyStream {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::task::spawn_local(async move {
+        if let Some(retry) = sse.retry
+            && tx
+                .send(format!("retry: {}\n\n", retry.as_millis()).into_bytes())
+                .await
+                .is_err()
+        {
+            return;
+        }
+
+        let far_future = Instant::now() + Duration::from_secs(365 * 24 * 60 * 60);
+        let next_heartbeat = |now: Instant| sse.heartbeat.map_or(far_future, |every| now + every);
+        let mut heartbeat_at = next_heartbeat(Instant::now());
+        let mut shutdown_poll = interval(Duration::from_secs(1));
+        loop {
+            let chunk = tokio::select! {
+                event = sse.events.recv() => match event {
+                    Some(event) => event.encode(),
+                    None => return,
+                },
+                _ = sleep_until(heartbeat_at) => b": heartbeat\n\n".to_vec(),
+                _ = shutdown_poll.tick() => {
+                    if health::is_shutting_down() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            if tx.send(chunk).await.is_err() {
+                return;
+            }
+            heartbeat_at = next_heartbeat(Instant::now());
+        }
+    });
+    rx
+}
+
+impl From<Sse> for Response {
+    fn from(sse: Sse) -> Self {
+        let mut headers = HashMap::new();
+        headers.insert(
+            "Content-Type".to_string(),
+            "text/event-stream; charset=utf-8".to_string(),
+        );
+        headers.insert("Cache-Control".to_string(), "no-cache".to_string());
+        // Stops nginx from buffering the stream.
+        headers.insert("X-Accel-Buffering".to_string(), "no".to_string());
+        Response::streaming(200, headers, pump(sse))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task::LocalSet;
+
+    use super::*;
+
+    fn encoded(event: Event) -> String {
+        String::from_utf8(event.encode()).unwrap()
+    }
+
+    #[test]
+    fn encodes_a_plain_message() {
+        assert_eq!(encoded(Event::new("hello")), "data: hello\n\n");
+        assert_eq!(encoded(Event::new("")), "data: \n\n");
+    }
+
+    #[test]
+    fn splits_multi_line_data() {
+        assert_eq!(
+            encoded(Event::new("one\ntwo\r\nthree\rfour")),
+            "data: one\ndata: two\ndata: three\ndata: four\n\n"
+        );
+        assert_eq!(
+            encoded(Event::new("trailing\n")),
+            "data: trailing\ndata: \n\n"
+        );
+    }
+
+    #[test]
+    fn strips_line_breaks_from_event_and_id() {
+        let event = Event::new("x")
+            .event("user\r\ndata: injected")
+            .id("4\n2")
+            .retry(Duration::from_millis(2500));
+        assert_eq!(
+            encoded(event),
+            "event: userdata: injected\nid: 42\nretry: 2500\ndata: x\n\n"
+        );
+    }
+
+    #[test]
+    fn removes_nul_from_the_id() {
+        assert_eq!(encoded(Event::new("x").id("a\0b")), "id: ab\ndata: x\n\n");
+    }
+
+    #[test]
+    fn encodes_json_data() {
+        let event = Event::json(&serde_json::json!({"name": "ada"})).unwrap();
+        assert_eq!(encoded(event), "data: {\"name\":\"ada\"}\n\n");
+    }
+
+    #[tokio::test]
+    async fn sends_heartbeats_while_quiet() {
+        LocalSet::new()
+            .run_until(async {
+                let (tx, rx) = mpsc::channel(4);
+                let sse = Sse::new(rx)
+                    .heartbeat(Some(Duration::from_millis(20)))
+                    .retry(Duration::from_secs(3));
+                let response = Response::from(sse);
+                assert_eq!(
+                    response.header("Content-Type"),
+                    Some("text/event-stream; charset=utf-8")
+                );
+                let mut body = response.stream.unwrap();
+
+                assert_eq!(body.recv().await.unwrap(), b"retry: 3000\n\n");
+                assert_eq!(body.recv().await.unwrap(), b": heartbeat\n\n");
+                tx.send(Event::new("hi")).await.unwrap();
+                assert_eq!(body.recv().await.unwrap(), b"data: hi\n\n");
+                assert_eq!(body.recv().await.unwrap(), b": heartbeat\n\n");
+
+                drop(tx);
+                assert_eq!(body.recv().await, None);
+            })
+            .await;
+    }
+}
@@ -0,0 +1,73 @@
+use std::env;
+use std::sync::{Arc, OnceLock};
+
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+use super::http2;
+
+static ACCEPTOR: OnceLock<Option<TlsAcceptor>> = OnceLock::new();
+
+/// Loads `TLS_CERT_FILE` (PEM chain) and `TLS_KEY_FILE` (PEM private key). TLS stays
+/// off when neither is set. ALPN offers `h2` ahead of `http/1.1` unless HTTP/2 is disabled.
+/// Returns a description of the setup for the startup banner.
+pub fn init() -> Result<String, String> {
+    let (cert_file, key_file) = match (env::var("TLS_CERT_FILE"), env::var("TLS_KEY_FILE")) {
+        (Ok(cert), Ok(key)) => (cert, key),
+        (Err(_), Err(_)) => {
+            let _ = ACCEPTOR.set(None);
+            return Ok("disabled".to_string());
+        }
+        _ => return Err("TLS_CERT_FILE and TLS_KEY_FILE must be set together".to_string()),
+    };
+
+    let certs = CertificateDer::pem_file_iter(&cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("Failed to read {}: {}", cert_file, err))?;
+    if certs.is_empty() {
+        return Err(format!("{} contains no certificate", cert_file));
+    }
+    let key = PrivateKeyDer::from_pem_file(&key_file)
+        .map_err(|err| format!("Failed to read {}: {}", key_file, err))?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("Invalid TLS certificate or key: {}", err))?;
+    config.alpn_protocols = alpn_protocols(http2::config().enabled);
+
+    let _ = ACCEPTOR.set(Some(TlsAcceptor::from(Arc::new(config))));
+    let protocols = if http2::config().enabled {
+        "h2, http/1.1"
+    } else {
+        "http/1.1"
+    };
+    Ok(format!("{} (ALPN: {})", cert_file, protocols))
+}
+
+/// Protocols offered in ALPN, most preferred first.
+fn alpn_protocols(http2: bool) -> Vec<Vec<u8>> {
+    if http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    }
+}
+
+/// The TLS acceptor, if `init` configured one.
+pub fn acceptor() -> Option<&'static TlsAcceptor> {
+    ACCEPTOR.get().and_then(|acceptor| acceptor.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_h2_first_unless_disabled() {
+        assert_eq!(alpn_protocols(true), [b"h2".to_vec(), b"http/1.1".to_vec()]);
+        assert_eq!(alpn_protocols(false), [b"http/1.1".to_vec()]);
+    }
+}
@@ -0,0 +1,304 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(OpCode::Continuation),
+            0x1 => Some(OpCode::Text),
+            0x2 => Some(OpCode::Binary),
+            0x8 => Some(OpCode::Close),
+            0x9 => Some(OpCode::Ping),
+            0xA => Some(OpCode::Pong),
+            _ => None,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            OpCode::Continuation => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xA,
+        }
+    }
+
+    pub fn is_control(self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: OpCode,
+    pub payload: Vec<u8>,
+}
+
+/// Why a frame was rejected, mapped to the close code sent back.
+#[derive(Debug)]
+pub enum FrameError {
+    /// 1002: reserved bits, unknown opcodes, unmasked client frames, bad control frames.
+    Protocol(&'static str),
+    /// 1009: the frame is larger than the configured maximum.
+    TooLarge,
+}
+
+/// Parses one frame from the front of `buf`, returning it with the number of bytes it
+/// used, or `None` if more data is needed. Client frames must be masked (§5.1).
+pub fn parse(buf: &[u8], max_payload: usize) -> Result<Option<(Frame, usize)>, FrameError> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buf[0] & 0x80 != 0;
+    if buf[0] & 0x70 != 0 {
+        return Err(FrameError::Protocol("reserved bits set"));
+    }
+    let opcode = OpCode::from_u8(buf[0] & 0x0F).ok_or(FrameError::Protocol("unknown opcode"))?;
+    if buf[1] & 0x80 == 0 {
+        return Err(FrameError::Protocol("client frames must be masked"));
+    }
+
+    let (len, mut offset) = match buf[1] & 0x7F {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4)
+        }
+        127 => {
+            if buf.len() < 10 {
+                return Ok(None);
+            }
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&buf[2..10]);
+            (u64::from_be_bytes(bytes), 10)
+        }
+        n => (n as u64, 2),
+    };
+
+    if opcode.is_control() && (len > 125 || !fin) {
+        return Err(FrameError::Protocol("invalid control frame"));
+    }
+    if len > max_payload as u64 {
+        return Err(FrameError::TooLarge);
+    }
+    let len = len as usize;
+
+    if buf.len() < offset + 4 + len {
+        return Ok(None);
+    }
+    let mask = [
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ];
+    offset += 4;
+    let payload = buf[offset..offset + len]
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ mask[i % 4])
+        .collect();
+
+    Ok(Some((
+        Frame {
+            fin,
+            opcode,
+            payload,
+        },
+        offset + len,
+    )))
+}
+
+/// Encodes an unmasked server frame.
+pub fn encode(fin: bool, opcode: OpCode, payload: &[u8], out: &mut Vec<u8>) {
+    out.push(if fin { 0x80 } else { 0 } | opcode.as_u8());
+    match payload.len() {
+        n if n < 126 => out.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            out.push(126);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            out.push(127);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A client frame: `first` is the FIN/RSV/opcode byte, the payload is masked.
+    pub(crate) fn client_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut out = vec![first];
+        match payload.len() {
+            n if n < 126 => out.push(0x80 | n as u8),
+            n if n <= u16::MAX as usize => {
+                out.push(0x80 | 126);
+                out.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                out.push(0x80 | 127);
+                out.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&mask);
+        out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        out
+    }
+
+    fn parse_one(buf: &[u8]) -> Frame {
+        let (frame, used) = parse(buf, 1 << 20).unwrap().expect("a complete frame");
+        assert_eq!(used, buf.len());
+        frame
+    }
+
+    fn protocol_error(buf: &[u8]) -> &'static str {
+        match parse(buf, 1 << 20) {
+            Err(FrameError::Protocol(reason)) => reason,
+            _ => panic!("expected a protocol error"),
+        }
+    }
+
+    #[test]
+    fn unmasks_the_rfc_sample() {
+        // §5.7: a single-frame masked text message containing "Hello".
+        let buf = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let frame = parse_one(&buf);
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OpCode::Text);
+        assert_eq!(frame.payload, b"Hello");
+    }
+
+    #[test]
+    fn reads_all_three_length_forms() {
+        for len in [0, 125, 126, 300, u16::MAX as usize, 70_000] {
+            let payload = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            let buf = client_frame(0x82, &payload);
+            let header = match len {
+                0..=125 => 2,
+                126..=65535 => 4,
+                _ => 10,
+            };
+            assert_eq!(buf.len(), header + 4 + len);
+            let frame = parse_one(&buf);
+            assert_eq!(frame.opcode, OpCode::Binary);
+            assert_eq!(frame.payload, payload);
+        }
+    }
+
+    #[test]
+    fn waits_for_partial_frames() {
+        let buf = client_frame(0x81, &[b'x'; 300]);
+        for end in [0, 1, 2, 3, 4, 7, 8, buf.len() - 1] {
+            assert!(
+                parse(&buf[..end], 1 << 20).unwrap().is_none(),
+                "{} bytes",
+                end
+            );
+        }
+        let long = client_frame(0x82, &vec![0; 70_000]);
+        assert!(parse(&long[..9], 1 << 20).unwrap().is_none());
+    }
+
+    #[test]
+    fn leaves_the_next_frame_in_the_buffer() {
+        let mut buf = client_frame(0x81, b"one");
+        let first = buf.len();
+        buf.extend(client_frame(0x81, b"two"));
+        let (frame, used) = parse(&buf, 1 << 20).unwrap().unwrap();
+        assert_eq!((frame.payload.as_slice(), used), (&b"one"[..], first));
+    }
+
+    #[test]
+    fn rejects_oversized_frames_from_the_header() {
+        // Only the header of a 1 MiB frame has arrived; it is refused without waiting
+        // for (and buffering) the payload.
+        let mut header = vec![0x82, 0x80 | 127];
+        header.extend_from_slice(&(1u64 << 20).to_be_bytes());
+        header.extend_from_slice(&[1, 2, 3, 4]);
+        assert!(matches!(parse(&header, 1024), Err(FrameError::TooLarge)));
+        assert!(matches!(
+            parse(&[0x82, 0x80 | 126, 0x04, 0x01], 1024),
+            Err(FrameError::TooLarge)
+        ));
+        assert!(
+            parse(&client_frame(0x82, &[0; 1024]), 1024)
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_bits_unknown_opcodes_and_unmasked_frames() {
+        for rsv in [0x40, 0x20, 0x10] {
+            assert_eq!(
+                protocol_error(&client_frame(0x81 | rsv, b"x")),
+                "reserved bits set"
+            );
+        }
+        for opcode in [0x3, 0x7, 0xB, 0xF] {
+            assert_eq!(
+                protocol_error(&client_frame(0x80 | opcode, b"")),
+                "unknown opcode"
+            );
+        }
+        assert_eq!(
+            protocol_error(&[0x81, 0x01, b'x']),
+            "client frames must be masked"
+        );
+    }
+
+    #[test]
+    fn limits_control_frames() {
+        assert!(
+            parse(&client_frame(0x89, &[0; 125]), 1 << 20)
+                .unwrap()
+                .is_some()
+        );
+        for opcode in [0x88, 0x89, 0x8A] {
+            assert_eq!(
+                protocol_error(&client_frame(opcode, &[0; 126])),
+                "invalid control frame"
+            );
+            // FIN cleared: control frames cannot be fragmented.
+            assert_eq!(
+                protocol_error(&client_frame(opcode & 0x7F, b"")),
+                "invalid control frame"
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_unmasked_server_frames() {
+        let mut out = vec![];
+        encode(true, OpCode::Text, b"Hi", &mut out);
+        assert_eq!(out, [0x81, 0x02, b'H', b'i']);
+
+        for (len, header) in [(125, vec![0x02, 125]), (126, vec![0x02, 126, 0, 126])] {
+            let mut out = vec![];
+            encode(false, OpCode::Binary, &vec![0; len], &mut out);
+            assert_eq!(out[..header.len()], header[..]);
+            assert_eq!(out.len(), header.len() + len);
+        }
+
+        let mut out = vec![];
+        encode(true, OpCode::Continuation, &vec![0; 65_536], &mut out);
+        assert_eq!(out[..10], [0x80, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha1::{Digest, Sha1};
+
+use crate::primitives::http::error::AppError;
+use crate::primitives::http::request::{Request, has_token};
+use crate::primitives::http::response::Response;
+use crate::routing::RouteParams;
+
+/// RFC 6455 §1.3.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(ACCEPT_GUID.as_bytes());
+    STANDARD.encode(hasher.finalize())
+}
+
+/// Terminal controller of every WebSocket route. It validates the opening handshake
+/// (§4.2.1) after the route's middlewares ran and answers `101 Switching Protocols`;
+/// `handle_connection` then hands the connection to the route's WebSocket handler.
+pub async fn accept(request: &mut Request, _params: &RouteParams) -> Response {
+    if !has_token(request.header("Upgrade"), "websocket")
+        || !has_token(request.header("Connection"), "upgrade")
+    {
+        return AppError::UpgradeRequired(
+            "This route only accepts WebSocket connections.".to_string(),
+        )
+        .into();
+    }
+    if request.header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        let mut response: Response =
+            AppError::UpgradeRequired("Only WebSocket version 13 is supported.".to_string()).into();
+        response
+            .headers
+            .insert("Sec-WebSocket-Version".to_string(), "13".to_string());
+        return response;
+    }
+    let key = request.header("Sec-WebSocket-Key").unwrap_or("").trim();
+    if STANDARD.decode(key).map(|k| k.len()) != Ok(16) {
+        return AppError::BadRequest("Invalid Sec-WebSocket-Key.".to_string()).into();
+    }
+
+    let mut headers = HashMap::new();
+    headers.insert("Upgrade".to_string(), "websocket".to_string());
+    headers.insert("Connection".to_string(), "Upgrade".to_string());
+    headers.insert("Sec-WebSocket-Accept".to_string(), accept_key(key));
+    Response {
+        status_code: 101,
+        headers,
+        body: String::new(),
+        stream: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_the_rfc_sample() {
+        // §1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    fn upgrade() -> Request {
+        Request::for_test("GET", "/ws")
+            .with_header("Upgrade", "websocket")
+            .with_header("Connection", "keep-alive, Upgrade")
+            .with_header("Sec-WebSocket-Version", "13")
+            .with_header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+    }
+
+    #[tokio::test]
+    async fn accepts_a_valid_handshake() {
+        let response = accept(&mut upgrade(), &RouteParams::default()).await;
+        assert_eq!(response.status_code, 101);
+        assert_eq!(
+            response.headers["Sec-WebSocket-Accept"],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_handshakes() {
+        let params = RouteParams::default();
+        let mut plain = Request::for_test("GET", "/ws");
+        assert_eq!(accept(&mut plain, &params).await.status_code, 426);
+
+        let mut old = upgrade().with_header("Sec-WebSocket-Version", "8");
+        let response = accept(&mut old, &params).await;
+        assert_eq!(response.status_code, 426);
+        assert_eq!(response.headers["Sec-WebSocket-Version"], "13");
+
+        let mut short_key = upgrade().with_header("Sec-WebSocket-Key", "c2hvcnQ=");
+        assert_eq!(accept(&mut short_key, &params).await.status_code, 400);
+    }
+}
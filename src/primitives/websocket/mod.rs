@@ -0,0 +1,549 @@
+use std::env;
+use std::io;
+use std::sync::OnceLock;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::{Duration, Instant, sleep_until, timeout};
+
+use crate::primitives::http::request::{Connection, Request};
+use crate::routing::RouteParams;
+
+pub mod frame;
+pub mod handshake;
+
+use frame::{FrameError, OpCode};
+
+/// Limits applied to every WebSocket connection; a handler can adjust its own copy.
+#[derive(Debug, Clone, Copy)]
+pub struct WebSocketConfig {
+    /// Largest accepted frame payload (`WS_MAX_FRAME_SIZE`, default 1 MiB). Outgoing
+    /// messages above it are split into fragments.
+    pub max_frame_size: usize,
+    /// Largest reassembled message (`WS_MAX_MESSAGE_SIZE`, default 16 MiB).
+    pub max_message_size: usize,
+    /// Closes the connection after this long without any frame from the client
+    /// (`WS_IDLE_TIMEOUT_SECS`, default 60, 0 disables). A ping is sent halfway through.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl WebSocketConfig {
+    pub fn global() -> &'static WebSocketConfig {
+        static CONFIG: OnceLock<WebSocketConfig> = OnceLock::new();
+        CONFIG.get_or_init(|| {
+            let number = |name: &str, default: u64| {
+                env::var(name)
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(default)
+            };
+            WebSocketConfig {
+                max_frame_size: number("WS_MAX_FRAME_SIZE", 1024 * 1024) as usize,
+                max_message_size: number("WS_MAX_MESSAGE_SIZE", 16 * 1024 * 1024) as usize,
+                idle_timeout: match number("WS_IDLE_TIMEOUT_SECS", 60) {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs)),
+                },
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// Pings are answered automatically; they are still surfaced to the handler.
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame>),
+}
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+pub const CLOSE_INTERNAL_ERROR: u16 = 1011;
+
+/// How long `finish` waits for the client to answer our close frame.
+const CLOSE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A server-side WebSocket connection (RFC 6455).
+///
+/// Both `recv` and `send` keep their progress in the socket, so `recv` can be raced
+/// against other futures in `tokio::select!` without losing data.
+pub struct WebSocket {
+    request: Request,
+    stream: Connection,
+    params: RouteParams,
+    pub config: WebSocketConfig,
+    write_timeout: Option<Duration>,
+    read_buf: Vec<u8>,
+    /// Encoded frames not yet written to the socket.
+    outbox: Vec<u8>,
+    /// Opcode and payload of a fragmented message being reassembled.
+    fragments: Option<(OpCode, Vec<u8>)>,
+    last_seen: Instant,
+    ping_sent: bool,
+    close_sent: bool,
+    close_received: bool,
+    closed: bool,
+}
+
+#[allow(dead_code)]
+impl WebSocket {
+    pub fn new(
+        request: Request,
+        stream: Connection,
+        params: RouteParams,
+        write_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            request,
+            stream,
+            params,
+            config: *WebSocketConfig::global(),
+            write_timeout,
+            read_buf: Vec::new(),
+            outbox: Vec::new(),
+            fragments: None,
+            last_seen: Instant::now(),
+            ping_sent: false,
+            close_sent: false,
+            close_received: false,
+            closed: false,
+        }
+    }
+
+    /// The upgrade request (headers, query parameters, remote address, ...).
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+
+    pub fn params(&self) -> &RouteParams {
+        &self.params
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed || self.close_sent
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        while !self.outbox.is_empty() {
+            let write = self.stream.write(&self.outbox);
+            let written = match self.write_timeout {
+                Some(limit) => timeout(limit, write)
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "write timed out"))??,
+                None => write.await?,
+            };
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.outbox.drain(..written);
+        }
+        Ok(())
+    }
+
+    fn queue_close(&mut self, code: u16, reason: &str) {
+        if self.close_sent {
+            return;
+        }
+        let mut payload = code.to_be_bytes().to_vec();
+        // Control frames carry at most 125 bytes (§5.5).
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        payload.extend_from_slice(&reason.as_bytes()[..end]);
+        frame::encode(true, OpCode::Close, &payload, &mut self.outbox);
+        self.close_sent = true;
+    }
+
+    /// Sends a close frame for a protocol violation and drops the connection.
+    async fn fail(&mut self, code: u16, reason: &str) {
+        self.queue_close(code, reason);
+        let _ = self.flush().await;
+        let _ = self.stream.shutdown().await;
+        self.closed = true;
+    }
+
+    /// Waits for the next message. Returns `None` once the connection is closed, after
+    /// a close handshake, an idle timeout, a protocol error or a network failure.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            if self.closed {
+                return None;
+            }
+            if self.flush().await.is_err() {
+                self.closed = true;
+                return None;
+            }
+            if self.close_received {
+                let _ = self.stream.shutdown().await;
+                self.closed = true;
+                return None;
+            }
+
+            match frame::parse(&self.read_buf, self.config.max_frame_size) {
+                Ok(Some((frame, used))) => {
+                    self.read_buf.drain(..used);
+                    self.last_seen = Instant::now();
+                    self.ping_sent = false;
+                    match self.handle(frame) {
+                        Ok(Some(message)) => return Some(message),
+                        Ok(None) => continue,
+                        Err((code, reason)) => {
+                            self.fail(code, reason).await;
+                            return None;
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(FrameError::Protocol(reason)) => {
+                    self.fail(CLOSE_PROTOCOL_ERROR, reason).await;
+                    return None;
+                }
+                Err(FrameError::TooLarge) => {
+                    self.fail(CLOSE_TOO_BIG, "frame too large").await;
+                    return None;
+                }
+            }
+
+            let read = match self.config.idle_timeout {
+                None => self.stream.read_buf(&mut self.read_buf).await,
+                Some(idle) => {
+                    let deadline = if self.ping_sent {
+                        self.last_seen + idle
+                    } else {
+                        self.last_seen + idle / 2
+                    };
+                    tokio::select! {
+                        read = self.stream.read_buf(&mut self.read_buf) => read,
+                        _ = sleep_until(deadline) => {
+                            if self.ping_sent {
+                                self.fail(CLOSE_GOING_AWAY, "idle timeout").await;
+                                return None;
+                            }
+                            frame::encode(true, OpCode::Ping, b"", &mut self.outbox);
+                            self.ping_sent = true;
+                            continue;
+                        }
+                    }
+                }
+            };
+            match read {
+                Ok(0) | Err(_) => {
+                    self.closed = true;
+                    return None;
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// Applies one frame to the connection state, returning a complete message if there is one.
+    fn handle(&mut self, frame: frame::Frame) -> Result<Option<Message>, (u16, &'static str)> {
+        match frame.opcode {
+            OpCode::Ping => {
+                if !self.close_sent {
+                    frame::encode(true, OpCode::Pong, &frame.payload, &mut self.outbox);
+                }
+                Ok(Some(Message::Ping(frame.payload)))
+            }
+            OpCode::Pong => Ok(Some(Message::Pong(frame.payload))),
+            OpCode::Close => {
+                let close = match frame.payload.len() {
+                    0 => None,
+                    1 => return Err((CLOSE_PROTOCOL_ERROR, "invalid close payload")),
+                    _ => {
+                        let code = u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
+                        if !matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999) {
+                            return Err((CLOSE_PROTOCOL_ERROR, "invalid close code"));
+                        }
+                        let reason = String::from_utf8(frame.payload[2..].to_vec())
+                            .map_err(|_| (CLOSE_INVALID_DATA, "close reason is not UTF-8"))?;
+                        Some(CloseFrame { code, reason })
+                    }
+                };
+                let code = close.as_ref().map(|c| c.code).unwrap_or(CLOSE_NORMAL);
+                self.queue_close(code, "");
+                self.close_received = true;
+                Ok(Some(Message::Close(close)))
+            }
+            OpCode::Text | OpCode::Binary => {
+                if self.fragments.is_some() {
+                    return Err((CLOSE_PROTOCOL_ERROR, "expected a continuation frame"));
+                }
+                if frame.fin {
+                    return self.complete(frame.opcode, frame.payload).map(Some);
+                }
+                self.fragments = Some((frame.opcode, frame.payload));
+                Ok(None)
+            }
+            OpCode::Continuation => {
+                let Some((_, buffer)) = self.fragments.as_mut() else {
+                    return Err((CLOSE_PROTOCOL_ERROR, "unexpected continuation frame"));
+                };
+                if buffer.len() + frame.payload.len() > self.config.max_message_size {
+                    return Err((CLOSE_TOO_BIG, "message too large"));
+                }
+                buffer.extend_from_slice(&frame.payload);
+                if !frame.fin {
+                    return Ok(None);
+                }
+                let (opcode, payload) = self
+                    .fragments
+                    .take()
+                    .unwrap_or((OpCode::Binary, Vec::new()));
+                self.complete(opcode, payload).map(Some)
+            }
+        }
+    }
+
+    fn complete(&self, opcode: OpCode, payload: Vec<u8>) -> Result<Message, (u16, &'static str)> {
+        if opcode == OpCode::Text {
+            String::from_utf8(payload)
+                .map(Message::Text)
+                .map_err(|_| (CLOSE_INVALID_DATA, "text message is not UTF-8"))
+        } else {
+            Ok(Message::Binary(payload))
+        }
+    }
+
+    /// Sends a message. Text and binary messages larger than `max_frame_size` are
+    /// fragmented; `Message::Close` starts the closing handshake.
+    pub async fn send(&mut self, message: Message) -> io::Result<()> {
+        if self.closed || self.close_sent {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "WebSocket is closing",
+            ));
+        }
+        let (opcode, payload) = match message {
+            Message::Text(text) => (OpCode::Text, text.into_bytes()),
+            Message::Binary(bytes) => (OpCode::Binary, bytes),
+            Message::Ping(payload) | Message::Pong(payload) if payload.len() > 125 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "control frame payloads are limited to 125 bytes",
+                ));
+            }
+            Message::Ping(payload) => (OpCode::Ping, payload),
+            Message::Pong(payload) => (OpCode::Pong, payload),
+            Message::Close(close) => {
+                let (code, reason) = close
+                    .map(|c| (c.code, c.reason))
+                    .unwrap_or((CLOSE_NORMAL, String::new()));
+                self.queue_close(code, &reason);
+                return self.flush().await;
+            }
+        };
+
+        if opcode.is_control() || payload.len() <= self.config.max_frame_size {
+            frame::encode(true, opcode, &payload, &mut self.outbox);
+        } else {
+            let chunks: Vec<&[u8]> = payload.chunks(self.config.max_frame_size.max(1)).collect();
+            let last = chunks.len() - 1;
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let opcode = if i == 0 { opcode } else { OpCode::Continuation };
+                frame::encode(i == last, opcode, chunk, &mut self.outbox);
+            }
+        }
+        self.flush().await
+    }
+
+    pub async fn send_text(&mut self, text: impl Into<String>) -> io::Result<()> {
+        self.send(Message::Text(text.into())).await
+    }
+
+    pub async fn send_binary(&mut self, bytes: impl Into<Vec<u8>>) -> io::Result<()> {
+        self.send(Message::Binary(bytes.into())).await
+    }
+
+    pub async fn close(&mut self, code: u16, reason: &str) -> io::Result<()> {
+        self.send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.to_string(),
+        })))
+        .await
+    }
+
+    /// Completes the closing handshake once the handler returns: sends a normal close
+    /// if the handler did not, then waits briefly for the client's close frame.
+    pub async fn finish(mut self) {
+        if !self.closed {
+            self.queue_close(CLOSE_NORMAL, "");
+            let _ = timeout(CLOSE_HANDSHAKE_TIMEOUT, async {
+                // Also flushes the echo of a close the handler already received.
+                let _ = self.flush().await;
+                while !self.close_received && self.recv().await.is_some() {}
+            })
+            .await;
+        }
+        let _ = self.stream.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame::tests::client_frame;
+    use tokio::io::DuplexStream;
+
+    fn connect() -> (WebSocket, DuplexStream) {
+        let (server, client) = tokio::io::duplex(1 << 20);
+        let mut socket = WebSocket::new(
+            Request::for_test("GET", "/ws"),
+            Box::new(server),
+            RouteParams::default(),
+            None,
+        );
+        socket.config = WebSocketConfig {
+            max_frame_size: 1024,
+            max_message_size: 4096,
+            idle_timeout: None,
+        };
+        (socket, client)
+    }
+
+    /// Reads one (unmasked, short) server frame: the first byte and the payload.
+    async fn server_frame(client: &mut DuplexStream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await.unwrap();
+        let mut payload = vec![0; (header[1] & 0x7F) as usize];
+        client.read_exact(&mut payload).await.unwrap();
+        (header[0], payload)
+    }
+
+    async fn close_code(client: &mut DuplexStream) -> u16 {
+        let (first, payload) = server_frame(client).await;
+        assert_eq!(first, 0x88, "expected a close frame");
+        u16::from_be_bytes([payload[0], payload[1]])
+    }
+
+    fn close_payload(code: u16) -> Vec<u8> {
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"bye");
+        payload
+    }
+
+    #[tokio::test]
+    async fn reassembles_fragments_around_control_frames() {
+        let (mut socket, mut client) = connect();
+        client.write_all(&client_frame(0x01, b"Hel")).await.unwrap();
+        client.write_all(&client_frame(0x89, b"p")).await.unwrap();
+        client
+            .write_all(&client_frame(0x00, b"lo, "))
+            .await
+            .unwrap();
+        client
+            .write_all(&client_frame(0x80, b"world"))
+            .await
+            .unwrap();
+
+        assert_eq!(socket.recv().await, Some(Message::Ping(b"p".to_vec())));
+        assert_eq!(
+            socket.recv().await,
+            Some(Message::Text("Hello, world".to_string()))
+        );
+        // The ping was answered in between.
+        assert_eq!(server_frame(&mut client).await, (0x8A, b"p".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn rejects_continuations_out_of_order() {
+        let (mut socket, mut client) = connect();
+        client.write_all(&client_frame(0x80, b"x")).await.unwrap();
+        assert_eq!(socket.recv().await, None);
+        assert_eq!(close_code(&mut client).await, CLOSE_PROTOCOL_ERROR);
+
+        let (mut socket, mut client) = connect();
+        client.write_all(&client_frame(0x01, b"a")).await.unwrap();
+        client.write_all(&client_frame(0x81, b"b")).await.unwrap();
+        assert_eq!(socket.recv().await, None);
+        assert_eq!(close_code(&mut client).await, CLOSE_PROTOCOL_ERROR);
+    }
+
+    #[tokio::test]
+    async fn limits_frame_and_message_sizes() {
+        let (mut socket, mut client) = connect();
+        client
+            .write_all(&client_frame(0x82, &[0; 1025]))
+            .await
+            .unwrap();
+        assert_eq!(socket.recv().await, None);
+        assert_eq!(close_code(&mut client).await, CLOSE_TOO_BIG);
+
+        let (mut socket, mut client) = connect();
+        for first in [0x02, 0x00, 0x00, 0x00] {
+            client
+                .write_all(&client_frame(first, &[0; 1024]))
+                .await
+                .unwrap();
+        }
+        client.write_all(&client_frame(0x80, b"!")).await.unwrap();
+        assert_eq!(socket.recv().await, None);
+        assert_eq!(close_code(&mut client).await, CLOSE_TOO_BIG);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_utf8_text() {
+        let (mut socket, mut client) = connect();
+        client
+            .write_all(&client_frame(0x81, &[0xC3, 0x28]))
+            .await
+            .unwrap();
+        assert_eq!(socket.recv().await, None);
+        assert_eq!(close_code(&mut client).await, CLOSE_INVALID_DATA);
+    }
+
+    #[tokio::test]
+    async fn echoes_valid_close_codes() {
+        for code in [1000, 1001, 1003, 1007, 1011, 1012, 1013, 1014, 3000, 4999] {
+            let (mut socket, mut client) = connect();
+            client
+                .write_all(&client_frame(0x88, &close_payload(code)))
+                .await
+                .unwrap();
+            assert_eq!(
+                socket.recv().await,
+                Some(Message::Close(Some(CloseFrame {
+                    code,
+                    reason: "bye".to_string()
+                })))
+            );
+            assert_eq!(socket.recv().await, None);
+            assert_eq!(close_code(&mut client).await, code);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_reserved_close_codes() {
+        for code in [999, 1004, 1005, 1006, 1015, 2999, 5000] {
+            let (mut socket, mut client) = connect();
+            client
+                .write_all(&client_frame(0x88, &close_payload(code)))
+                .await
+                .unwrap();
+            assert_eq!(socket.recv().await, None, "code {}", code);
+            assert_eq!(close_code(&mut client).await, CLOSE_PROTOCOL_ERROR);
+        }
+    }
+
+    #[tokio::test]
+    async fn fragments_large_outgoing_messages() {
+        let (mut socket, mut client) = connect();
+        socket.config.max_frame_size = 4;
+        socket.send_text("abcdefghij").await.unwrap();
+        assert_eq!(server_frame(&mut client).await, (0x01, b"abcd".to_vec()));
+        assert_eq!(server_frame(&mut client).await, (0x00, b"efgh".to_vec()));
+        assert_eq!(server_frame(&mut client).await, (0x80, b"ij".to_vec()));
+    }
+}
@@ -1,9 +1,17 @@
-use crate::routing::Route;
+use crate::domain::file::controller::FileController;
 use crate::domain::user::controller::UserController;
+use crate::health;
+use crate::observability::metrics;
+use crate::routing::Route;
+use crate::static_files;
 
 pub fn init_routes() -> Vec<Route> {
     let mut routes = Vec::new();
 
-        routes.extend(UserController::routes());
-routes
+    routes.extend(UserController::routes());
+    routes.extend(FileController::routes());
+    routes.extend(metrics::routes());
+    routes.extend(health::routes());
+    routes.extend(static_files::routes());
+    routes
 }
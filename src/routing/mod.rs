@@ -1,12 +1,22 @@
+use crate::observability::metrics;
+use crate::primitives::http::error::AppError;
 use crate::primitives::http::request::Request;
 use crate::primitives::http::response::Response;
+use crate::primitives::websocket::{self, WebSocket};
+use crate::telemetry::{Span, SpanKind};
+use std::any::Any;
 use std::collections::HashMap;
 use std::future::Future;
+use std::panic::{AssertUnwindSafe, catch_unwind};
 use std::pin::Pin;
 use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 pub mod init;
+pub mod timeouts;
 pub use init::init_routes;
+pub use timeouts::Timeouts;
 
 pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
 pub type ControllerHandler =
@@ -21,14 +31,30 @@ pub type MiddlewareHandler = Box<
         + Sync,
 >;
 
+/// Handlers carry the name they were registered with (e.g. `UserController::get_all`)
+/// so traces can tell middlewares and controllers apart.
 #[allow(dead_code)]
 pub enum HandlerKind {
-    Middleware(MiddlewareHandler),
-    Controller(ControllerHandler),
+    Middleware(&'static str, MiddlewareHandler),
+    Controller(&'static str, ControllerHandler),
 }
 
 pub type Handler = Arc<HandlerKind>;
 
+/// Runs once the WebSocket handshake of a route succeeded and owns the connection
+/// until it returns.
+pub type WebSocketHandler =
+    Arc<dyn for<'a> Fn(&'a mut WebSocket) -> BoxFuture<'a, ()> + Send + Sync>;
+
+/// Pins the higher-ranked signature so `websocket!` closures infer correctly.
+#[allow(dead_code)]
+pub fn websocket_handler<F>(handler: F) -> WebSocketHandler
+where
+    F: for<'a> Fn(&'a mut WebSocket) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+{
+    Arc::new(handler)
+}
+
 #[derive(Debug, Default)]
 pub struct RouteParams {
     params: HashMap<String, String>,
@@ -44,6 +70,10 @@ pub struct Route {
     pub method: &'static str,
     pub path: &'static [&'static str],
     pub handlers: Vec<Handler>,
+    pub timeouts: Timeouts,
+    pub websocket: Option<WebSocketHandler>,
+    /// Only tried when no other route matches the path (see `fallback`).
+    pub fallback: bool,
 }
 
 impl Route {
@@ -56,25 +86,85 @@ impl Route {
             method,
             path,
             handlers,
+            timeouts: Timeouts::default(),
+            websocket: None,
+            fallback: false,
         }
     }
+
+    /// A `GET` route that upgrades to a WebSocket. The middlewares run on the opening
+    /// handshake as for any other request, so authentication works unchanged.
+    #[allow(dead_code)]
+    pub fn websocket(
+        path: &'static [&'static str],
+        mut middlewares: Vec<Handler>,
+        handler: WebSocketHandler,
+    ) -> Self {
+        middlewares.push(crate::route!(websocket::handshake::accept));
+        let mut route = Self::new("GET", path, middlewares);
+        route.websocket = Some(handler);
+        route
+    }
+
+    /// Overrides `BODY_READ_TIMEOUT_MS` for this route. `Duration::ZERO` disables it.
+    #[allow(dead_code)]
+    pub fn body_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.body_read = Some(timeout);
+        self
+    }
+
+    /// Overrides `HANDLER_TIMEOUT_MS` for this route. `Duration::ZERO` disables it.
+    #[allow(dead_code)]
+    pub fn handler_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.handler = Some(timeout);
+        self
+    }
+
+    /// Overrides `WRITE_TIMEOUT_MS` for this route. `Duration::ZERO` disables it.
+    #[allow(dead_code)]
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.write = Some(timeout);
+        self
+    }
+
+    /// Makes this route a fallback: it is only tried when no regular route matches the
+    /// path, and never turns a request for another method into a 405. For catch-alls
+    /// such as static files mounted at `/`.
+    pub fn fallback(mut self) -> Self {
+        self.fallback = true;
+        self
+    }
+
+    /// The path pattern as written in the route table, e.g. `/user/:id`.
+    pub fn pattern(&self) -> String {
+        format!("/{}", self.path.join("/"))
+    }
 }
 
 #[macro_export]
 macro_rules! route {
     ($handler:path) => {
-        std::sync::Arc::new($crate::routing::HandlerKind::Controller(Box::new(
-            |req, params| Box::pin($handler(req, params)),
-        )))
+        std::sync::Arc::new($crate::routing::HandlerKind::Controller(
+            stringify!($handler),
+            Box::new(|req, params| Box::pin($handler(req, params))),
+        ))
     };
 }
 
 #[macro_export]
 macro_rules! middleware {
     ($handler:path) => {
-        std::sync::Arc::new($crate::routing::HandlerKind::Middleware(Box::new(
-            |req, params, handlers| Box::pin($handler(req, params, handlers)),
-        )))
+        std::sync::Arc::new($crate::routing::HandlerKind::Middleware(
+            stringify!($handler),
+            Box::new(|req, params, handlers| Box::pin($handler(req, params, handlers))),
+        ))
+    };
+}
+
+#[macro_export]
+macro_rules! websocket {
+    ($handler:path) => {
+        $crate::routing::websocket_handler(|ws| Box::pin($handler(ws)))
     };
 }
 
@@ -88,8 +178,42 @@ pub fn routes() -> &'static [Route] {
     ROUTES.get().map(|r| r.as_slice()).unwrap_or(&[])
 }
 
-pub async fn route(request: &mut Request) -> Response {
-    let path = request.url.split('?').next().unwrap_or("");
+/// Polls a handler inside `catch_unwind`, so a panicking controller, middleware or
+/// WebSocket handler resolves to `Err(payload)` instead of tearing down the task.
+struct CatchUnwind<'a, T>(BoxFuture<'a, T>);
+
+impl<T> Future for CatchUnwind<'_, T> {
+    type Output = Result<T, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+enum RouteMatch {
+    Found(usize, RouteParams),
+    /// The path exists, but only for these methods.
+    MethodNotAllowed(Vec<String>),
+    NotFound,
+}
+
+fn match_route(method: &str, url: &str) -> RouteMatch {
+    let path = url.split('?').next().unwrap_or("");
 
     let segments: Vec<&str> = path
         .trim_matches('/')
@@ -97,32 +221,121 @@ pub async fn route(request: &mut Request) -> Response {
         .filter(|s| !s.is_empty())
         .collect();
 
-    let routes = routes();
-    let mut path_matched = false;
+    let mut allowed: Vec<String> = Vec::new();
 
-    for route_def in routes {
+    for (index, route_def) in routes().iter().enumerate() {
+        if route_def.fallback {
+            continue;
+        }
         let params = match path_match_params(route_def.path, &segments) {
             Some(params) => params,
             None => continue,
         };
-        path_matched = true;
-        if route_def.method == request.method {
-            let mut handlers = route_def.handlers.clone();
-            handlers.reverse();
-            return next_handler(request, &params, &mut handlers).await;
+        if route_def.method == method {
+            return RouteMatch::Found(index, params);
+        }
+        if !allowed.iter().any(|m| m == route_def.method) {
+            allowed.push(route_def.method.to_string());
         }
     }
+    if !allowed.is_empty() {
+        return RouteMatch::MethodNotAllowed(allowed);
+    }
+
+    for (index, route_def) in routes().iter().enumerate() {
+        if !route_def.fallback || route_def.method != method {
+            continue;
+        }
+        if let Some(params) = path_match_params(route_def.path, &segments) {
+            return RouteMatch::Found(index, params);
+        }
+    }
+    RouteMatch::NotFound
+}
+
+/// The route that would handle `method url`, if any.
+pub fn find_route(method: &str, url: &str) -> Option<&'static Route> {
+    match match_route(method, url) {
+        RouteMatch::Found(index, _) => routes().get(index),
+        _ => None,
+    }
+}
+
+/// Path parameters of the route matched by `request`.
+pub fn route_params(request: &Request) -> RouteParams {
+    match match_route(&request.method, &request.url) {
+        RouteMatch::Found(_, params) => params,
+        _ => RouteParams::default(),
+    }
+}
+
+pub async fn route(request: &mut Request) -> Response {
+    let (index, params) = match match_route(&request.method, &request.url) {
+        RouteMatch::Found(index, params) => (index, params),
+        RouteMatch::MethodNotAllowed(allowed) => return AppError::MethodNotAllowed(allowed).into(),
+        RouteMatch::NotFound => {
+            let path = request.url.split('?').next().unwrap_or("");
+            return AppError::NotFound(format!("No route matches '{}'.", path)).into();
+        }
+    };
+
+    request.matched_route = Some(index);
+    run_route(request, &routes()[index], index, &params).await
+}
 
-    if path_matched {
-        return method_not_allowed();
+/// Runs the handlers of `route_def` under its handler deadline, turning a timeout into
+/// a 504 and a panic into a 500.
+async fn run_route(
+    request: &mut Request,
+    route_def: &Route,
+    index: usize,
+    params: &RouteParams,
+) -> Response {
+    let mut handlers = route_def.handlers.clone();
+    handlers.reverse();
+
+    let limit = timeouts::resolve(Some(route_def)).handler;
+    let chain = Box::pin(next_handler(request, params, &mut handlers));
+    match timeouts::with_timeout(limit, CatchUnwind(chain)).await {
+        Some(Ok(response)) => response,
+        None => AppError::GatewayTimeout(format!(
+            "The request handler did not finish within {}ms.",
+            limit.unwrap_or_default().as_millis()
+        ))
+        .into(),
+        Some(Err(payload)) => {
+            eprintln!(
+                "Handler panicked [request_id={}] {} {}: {}",
+                request.id,
+                route_def.method,
+                route_def.pattern(),
+                panic_message(payload.as_ref())
+            );
+            metrics::record_panic(Some(index));
+            AppError::Internal("The request handler panicked".to_string()).into()
+        }
     }
+}
 
-    let mut headers = HashMap::new();
-    headers.insert("Content-Type".to_string(), "text/plain".to_string());
-    Response {
-        status_code: 404,
-        headers,
-        body: "Not Found".to_string(),
+/// Runs the WebSocket handler of an upgraded connection. A panic is logged and
+/// counted like one in an HTTP handler, and the connection is closed with 1011.
+pub async fn run_websocket(handler: &WebSocketHandler, socket: &mut WebSocket) {
+    let Err(payload) = CatchUnwind(handler(socket)).await else {
+        return;
+    };
+    let request = socket.request();
+    let route_def = request.matched_route.and_then(|i| routes().get(i));
+    eprintln!(
+        "WebSocket handler panicked [request_id={}] {}: {}",
+        request.id,
+        route_def.map(Route::pattern).unwrap_or_default(),
+        panic_message(payload.as_ref())
+    );
+    metrics::record_panic(request.matched_route);
+    if !socket.is_closed() {
+        let _ = socket
+            .close(websocket::CLOSE_INTERNAL_ERROR, "internal error")
+            .await;
     }
 }
 
@@ -132,22 +345,46 @@ pub async fn next_handler(
     handlers: &mut Vec<Handler>,
 ) -> Response {
     if let Some(handler) = handlers.pop() {
-        match &*handler {
-            HandlerKind::Middleware(middleware) => middleware(request, params, handlers).await,
-            HandlerKind::Controller(controller) => controller(request, params).await,
-        }
+        let (kind, name) = match &*handler {
+            HandlerKind::Middleware(name, _) => ("middleware", *name),
+            HandlerKind::Controller(name, _) => ("controller", *name),
+        };
+        let mut span = Span::child(name, SpanKind::Internal);
+        span.set_attribute("handler.kind", kind);
+
+        let response = span
+            .scope(async {
+                match &*handler {
+                    HandlerKind::Middleware(_, middleware) => {
+                        middleware(request, params, handlers).await
+                    }
+                    HandlerKind::Controller(_, controller) => controller(request, params).await,
+                }
+            })
+            .await;
+
+        span.set_attribute("http.response.status_code", i64::from(response.status_code));
+        response
     } else {
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "text/plain".to_string());
-        Response {
-            status_code: 500,
-            headers,
-            body: "Middleware chain ended without controller".to_string(),
-        }
+        AppError::Internal("Middleware chain ended without controller".to_string()).into()
     }
 }
 
+/// Segments starting with `:` capture one path segment. A trailing `*name` segment
+/// captures the rest of the path (possibly empty), joined with `/`.
 fn path_match_params(pattern: &[&str], segments: &[&str]) -> Option<RouteParams> {
+    if let Some((last, prefix)) = pattern.split_last()
+        && let Some(name) = last.strip_prefix('*')
+    {
+        if segments.len() < prefix.len() {
+            return None;
+        }
+        let (head, rest) = segments.split_at(prefix.len());
+        let mut params = path_match_params(prefix, head)?;
+        params.params.insert(name.to_string(), rest.join("/"));
+        return Some(params);
+    }
+
     if pattern.len() != segments.len() {
         return None;
     }
@@ -165,13 +402,3 @@ fn path_match_params(pattern: &[&str], segments: &[&str]) -> Option<RouteParams>
 
     Some(RouteParams { params })
 }
-
-fn method_not_allowed() -> Response {
-    let mut headers = HashMap::new();
-    headers.insert("Content-Type".to_string(), "text/plain".to_string());
-    Response {
-        status_code: 405,
-        headers,
-        body: "Method Not Allowed".to_string(),
-    }
-}
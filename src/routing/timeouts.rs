@@ -0,0 +1,127 @@
+use std::env;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use super::Route;
+
+/// Deadlines for each phase of a request. On a `Route`, `None` inherits the global
+/// value; in the resolved set, `None` means no deadline.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timeouts {
+    pub header_read: Option<Duration>,
+    pub body_read: Option<Duration>,
+    pub handler: Option<Duration>,
+    pub write: Option<Duration>,
+}
+
+static GLOBAL: OnceLock<Timeouts> = OnceLock::new();
+
+fn env_ms(key: &str, default: u64) -> Option<Duration> {
+    let ms = env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(default);
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+/// Global deadlines from `HEADER_READ_TIMEOUT_MS`, `BODY_READ_TIMEOUT_MS`,
+/// `HANDLER_TIMEOUT_MS` and `WRITE_TIMEOUT_MS`. `0` disables a deadline.
+pub fn global() -> Timeouts {
+    *GLOBAL.get_or_init(|| Timeouts {
+        header_read: env_ms("HEADER_READ_TIMEOUT_MS", 10_000),
+        body_read: env_ms("BODY_READ_TIMEOUT_MS", 30_000),
+        handler: env_ms("HANDLER_TIMEOUT_MS", 30_000),
+        write: env_ms("WRITE_TIMEOUT_MS", 30_000),
+    })
+}
+
+/// The deadlines that apply to `route`, falling back to the global ones.
+/// A route override of `Duration::ZERO` disables that deadline.
+pub fn resolve(route: Option<&Route>) -> Timeouts {
+    let global = global();
+    let overrides = route.map(|r| r.timeouts).unwrap_or_default();
+    let pick = |local: Option<Duration>, global: Option<Duration>| {
+        local.or(global).filter(|d| !d.is_zero())
+    };
+    Timeouts {
+        header_read: global.header_read,
+        body_read: pick(overrides.body_read, global.body_read),
+        handler: pick(overrides.handler, global.handler),
+        write: pick(overrides.write, global.write),
+    }
+}
+
+/// Awaits `fut`, giving up after `limit`. Returns `None` on timeout, in which case
+/// `fut` has been dropped (cancelling any DB work it was awaiting).
+pub async fn with_timeout<F: Future>(limit: Option<Duration>, fut: F) -> Option<F::Output> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, fut).await.ok(),
+        None => Some(fut.await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::primitives::http::request::Request;
+    use crate::primitives::http::response::Response;
+    use crate::route;
+    use crate::routing::{RouteParams, run_route};
+
+    async fn slow(_request: &mut Request, _params: &RouteParams) -> Response {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Response {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: "too late".to_string(),
+            stream: None,
+        }
+    }
+
+    #[test]
+    fn route_overrides_beat_the_global_deadlines() {
+        let route = Route::new("GET", &["slow"], vec![])
+            .handler_timeout(Duration::from_millis(50))
+            .body_timeout(Duration::from_secs(120))
+            .write_timeout(Duration::ZERO);
+        let resolved = resolve(Some(&route));
+        assert_eq!(resolved.handler, Some(Duration::from_millis(50)));
+        assert_eq!(resolved.body_read, Some(Duration::from_secs(120)));
+        assert_eq!(resolved.write, None, "ZERO disables the deadline");
+        assert_eq!(resolved.header_read, global().header_read);
+    }
+
+    #[test]
+    fn routes_without_overrides_use_the_global_deadlines() {
+        let route = Route::new("GET", &["plain"], vec![]);
+        let resolved = resolve(Some(&route));
+        assert_eq!(resolved.handler, global().handler);
+        assert_eq!(resolved.body_read, global().body_read);
+        assert_eq!(resolved.write, global().write);
+        assert_eq!(resolve(None).handler, global().handler);
+    }
+
+    #[tokio::test]
+    async fn an_expired_handler_answers_504() {
+        let route = Route::new("GET", &["slow"], vec![route!(slow)])
+            .handler_timeout(Duration::from_millis(20));
+        let mut request = Request::for_test("GET", "/slow");
+
+        let response = run_route(&mut request, &route, 0, &RouteParams::default()).await;
+        assert_eq!(response.status_code, 504);
+        assert_eq!(
+            response.headers.get("Content-Type").map(String::as_str),
+            Some("application/problem+json")
+        );
+        let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(body["status"], 504);
+        assert_eq!(body["title"], "Gateway Timeout");
+        assert_eq!(
+            body["detail"],
+            "The request handler did not finish within 20ms."
+        );
+    }
+}
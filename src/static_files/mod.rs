@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::primitives::http::error::AppError;
+use crate::primitives::http::request::Request;
+use crate::primitives::http::response::Response;
+use crate::routing::{HandlerKind, Route, RouteParams};
+use crate::util::url::percent_decode;
+
+pub mod mime;
+pub mod range;
+
+use range::{ByteRange, RangeRequest};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const INDEX_FILE: &str = "index.html";
+
+struct StaticDir {
+    root: PathBuf,
+    max_age: u64,
+}
+
+/// Mounts the directory from `STATIC_DIR` under `STATIC_PREFIX` (default `/static`).
+/// Nothing is mounted when `STATIC_DIR` is unset.
+pub fn routes() -> Vec<Route> {
+    let Ok(dir) = env::var("STATIC_DIR") else {
+        return Vec::new();
+    };
+    let prefix = env::var("STATIC_PREFIX").unwrap_or_else(|_| "/static".to_string());
+    mount(&prefix, dir)
+}
+
+/// Serves the files under `dir` for `GET`/`HEAD` requests below `prefix`,
+/// e.g. `mount("/assets", "public")` maps `/assets/css/app.css` to `public/css/app.css`.
+/// Mounted at `/`, the routes are fallbacks, so they do not shadow other routes or turn
+/// unknown requests into 405s.
+pub fn mount(prefix: &str, dir: impl Into<PathBuf>) -> Vec<Route> {
+    let dir = dir.into();
+    let root = match dir.canonicalize() {
+        Ok(root) => root,
+        Err(err) => {
+            eprintln!("Static directory {} is not usable: {}", dir.display(), err);
+            dir
+        }
+    };
+    let static_dir = Arc::new(StaticDir {
+        root,
+        max_age: env::var("STATIC_CACHE_MAX_AGE")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0),
+    });
+
+    // Routes borrow their path for the lifetime of the program; mounts happen once at startup.
+    let mut segments: Vec<&'static str> = prefix
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| &*Box::leak(s.to_string().into_boxed_str()))
+        .collect();
+    let fallback = segments.is_empty();
+    segments.push("*path");
+    let path: &'static [&'static str] = Box::leak(segments.into_boxed_slice());
+
+    ["GET", "HEAD"]
+        .into_iter()
+        .map(|method| {
+            let static_dir = static_dir.clone();
+            let handler = HandlerKind::Controller(
+                "static_files::serve",
+                Box::new(move |request, params| {
+                    let static_dir = static_dir.clone();
+                    Box::pin(async move { serve(&static_dir, request, params).await })
+                }),
+            );
+            let route = Route::new(method, path, vec![Arc::new(handler)]);
+            if fallback { route.fallback() } else { route }
+        })
+        .collect()
+}
+
+fn not_found(request: &Request) -> Response {
+    let path = request.url.split('?').next().unwrap_or("");
+    AppError::NotFound(format!("No file matches '{}'.", path)).into()
+}
+
+/// Maps the captured path onto the mounted directory. Dot segments, hidden files and
+/// encoded separators are refused, and the canonical result must stay under the root,
+/// which also catches symlinks pointing outside of it.
+async fn resolve(root: &Path, relative: &str) -> Option<PathBuf> {
+    let mut candidate = root.to_path_buf();
+    for segment in relative.split('/').filter(|s| !s.is_empty()) {
+        let segment = percent_decode(segment)?;
+        if segment.starts_with('.') || segment.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        candidate.push(segment);
+    }
+
+    let mut resolved = tokio::fs::canonicalize(&candidate).await.ok()?;
+    if tokio::fs::metadata(&resolved).await.ok()?.is_dir() {
+        // The index file may itself be a symlink, so it is resolved and checked too.
+        resolved.push(INDEX_FILE);
+        resolved = tokio::fs::canonicalize(&resolved).await.ok()?;
+    }
+    resolved.starts_with(root).then_some(resolved)
+}
+
+fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+fn parse_http_date(value: &str) -> Option<u64> {
+    let parsed = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    u64::try_from(parsed.timestamp()).ok()
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Weak comparison (RFC 9110 §8.8.3.2), as required for `If-None-Match`.
+fn etag_matches(header: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    header
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// `If-Range` only allows the range when the representation is unchanged. Entity
+/// tags must match strongly; dates must equal `Last-Modified`.
+fn if_range_allows(header: &str, etag: &str, modified: u64) -> bool {
+    let header = header.trim();
+    if header.starts_with('"') {
+        header == etag
+    } else if header.starts_with("W/") {
+        false
+    } else {
+        parse_http_date(header) == Some(modified)
+    }
+}
+
+enum Piece {
+    Bytes(Vec<u8>),
+    File(ByteRange),
+}
+
+/// Streams `pieces` from `path` in `CHUNK_SIZE` chunks; the file is never held in memory.
+fn stream_file(path: PathBuf, pieces: Vec<Piece>) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel(4);
+    tokio::task::spawn_local(async move {
+        let mut file = match File::open(&path).await {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Failed to open {}: {}", path.display(), err);
+                return;
+            }
+        };
+        for piece in pieces {
+            let range = match piece {
+                Piece::Bytes(bytes) => {
+                    if tx.send(bytes).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                Piece::File(range) => range,
+            };
+            if file.seek(SeekFrom::Start(range.start)).await.is_err() {
+                return;
+            }
+            let mut remaining = range.len();
+            while remaining > 0 {
+                let mut chunk = vec![0u8; remaining.min(CHUNK_SIZE as u64) as usize];
+                match file.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        remaining -= n as u64;
+                        if tx.send(chunk).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    });
+    rx
+}
+
+async fn serve(static_dir: &StaticDir, request: &mut Request, params: &RouteParams) -> Response {
+    let Some(path) = resolve(&static_dir.root, params.get("path").unwrap_or("")).await else {
+        return not_found(request);
+    };
+    let metadata = match tokio::fs::metadata(&path).await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return not_found(request),
+    };
+
+    let total = metadata.len();
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let modified_nanos = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let etag = format!("\"{:x}-{:x}\"", total, modified_nanos);
+    let content_type = mime::content_type(&path);
+
+    let mut headers = HashMap::new();
+    headers.insert("ETag".to_string(), etag.clone());
+    headers.insert("Last-Modified".to_string(), http_date(modified));
+    headers.insert(
+        "Cache-Control".to_string(),
+        format!("public, max-age={}", static_dir.max_age),
+    );
+    headers.insert("Accept-Ranges".to_string(), "bytes".to_string());
+
+    // If-Modified-Since is only consulted when If-None-Match is absent (RFC 9110 §13.1.3).
+    let not_modified = match request.header("If-None-Match") {
+        Some(header) => etag_matches(header, &etag),
+        None => request
+            .header("If-Modified-Since")
+            .and_then(parse_http_date)
+            .is_some_and(|since| unix_secs(modified) <= since),
+    };
+    if not_modified {
+        return Response {
+            status_code: 304,
+            headers,
+            body: String::new(),
+            stream: None,
+        };
+    }
+
+    let ranges = match request.header("Range") {
+        Some(header)
+            if request
+                .header("If-Range")
+                .is_none_or(|v| if_range_allows(v, &etag, unix_secs(modified))) =>
+        {
+            range::parse(header, total)
+        }
+        _ => RangeRequest::Full,
+    };
+
+    let (status_code, pieces, length) = match ranges {
+        RangeRequest::Full => {
+            headers.insert("Content-Type".to_string(), content_type.to_string());
+            let pieces = if total > 0 {
+                vec![Piece::File(ByteRange {
+                    start: 0,
+                    end: total - 1,
+                })]
+            } else {
+                Vec::new()
+            };
+            (200, pieces, total)
+        }
+        RangeRequest::Unsatisfiable => {
+            let mut response: Response = AppError::RangeNotSatisfiable(format!(
+                "The requested range is outside of the {} byte file.",
+                total
+            ))
+            .into();
+            response
+                .headers
+                .insert("Content-Range".to_string(), format!("bytes */{}", total));
+            return response;
+        }
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            headers.insert("Content-Type".to_string(), content_type.to_string());
+            headers.insert("Content-Range".to_string(), range.content_range(total));
+            (206, vec![Piece::File(range)], range.len())
+        }
+        RangeRequest::Partial(ranges) => {
+            let boundary = Uuid::new_v4().simple().to_string();
+            headers.insert(
+                "Content-Type".to_string(),
+                format!("multipart/byteranges; boundary={}", boundary),
+            );
+            let mut pieces = Vec::with_capacity(ranges.len() * 2 + 1);
+            let mut length = 0;
+            for range in ranges {
+                let part_head = format!(
+                    "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                    boundary,
+                    content_type,
+                    range.content_range(total)
+                );
+                length += part_head.len() as u64 + range.len();
+                pieces.push(Piece::Bytes(part_head.into_bytes()));
+                pieces.push(Piece::File(range));
+            }
+            let closing = format!("\r\n--{}--\r\n", boundary);
+            length += closing.len() as u64;
+            pieces.push(Piece::Bytes(closing.into_bytes()));
+            (206, pieces, length)
+        }
+    };
+
+    headers.insert("Content-Length".to_string(), length.to_string());
+    // HEAD gets the same response as GET, so compression negotiates the same headers;
+    // the writers drop the body.
+    if pieces.is_empty() {
+        return Response {
+            status_code,
+            headers,
+            body: String::new(),
+            stream: None,
+        };
+    }
+    Response::streaming(status_code, headers, stream_file(path, pieces))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etag_matching_is_weak() {
+        let etag = "\"12c1-18dfcf05\"";
+        assert!(etag_matches("\"12c1-18dfcf05\"", etag));
+        assert!(etag_matches("W/\"12c1-18dfcf05\"", etag));
+        assert!(etag_matches("\"other\", W/\"12c1-18dfcf05\"", etag));
+        assert!(etag_matches("*", etag));
+        assert!(!etag_matches("\"other\"", etag));
+        assert!(!etag_matches("\"12c1\"", etag));
+    }
+
+    #[test]
+    fn if_range_needs_a_strong_match_or_the_exact_date() {
+        let etag = "\"12c1-18dfcf05\"";
+        let modified = 1_792_378_798;
+        let date = http_date(UNIX_EPOCH + std::time::Duration::from_secs(modified));
+        assert!(if_range_allows(etag, etag, modified));
+        assert!(!if_range_allows("W/\"12c1-18dfcf05\"", etag, modified));
+        assert!(!if_range_allows("\"other\"", etag, modified));
+        assert!(if_range_allows(&date, etag, modified));
+        assert!(!if_range_allows(&date, etag, modified + 1));
+        assert!(!if_range_allows("yesterday", etag, modified));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn resolve_stays_inside_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let base = env::temp_dir().join(format!("static-test-{}", Uuid::new_v4()));
+        let root = base.join("public");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::create_dir_all(root.join("leak")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(root.join("app.css"), "body{}").unwrap();
+        std::fs::write(root.join("docs").join(INDEX_FILE), "<h1>docs</h1>").unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(
+            outside.join("secret.txt"),
+            root.join("leak").join(INDEX_FILE),
+        )
+        .unwrap();
+        symlink(&outside, root.join("out")).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(resolve(&root, "app.css").await, Some(root.join("app.css")));
+        assert_eq!(
+            resolve(&root, "docs/").await,
+            Some(root.join("docs").join(INDEX_FILE))
+        );
+        assert_eq!(resolve(&root, "leak").await, None);
+        assert_eq!(resolve(&root, "out/secret.txt").await, None);
+        assert_eq!(resolve(&root, "../outside/secret.txt").await, None);
+        assert_eq!(resolve(&root, "docs%2F..%2F..%2Foutside").await, None);
+        assert_eq!(resolve(&root, ".hidden").await, None);
+        assert_eq!(resolve(&root, "missing.css").await, None);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
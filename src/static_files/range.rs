@@ -0,0 +1,196 @@
+/// An inclusive byte range within a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable `Range` header; serve the whole file.
+    Full,
+    Partial(Vec<ByteRange>),
+    /// Well-formed, but no range overlaps the file: 416.
+    Unsatisfiable,
+}
+
+/// More ranges than this are treated as abuse and the whole file is served.
+const MAX_RANGES: usize = 16;
+
+/// Parses a `Range: bytes=...` header against a file of `total` bytes (RFC 9110 §14.2).
+/// Unknown units and malformed values are ignored, as the RFC allows.
+pub fn parse(header: &str, total: u64) -> RangeRequest {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for part in spec.split(',') {
+        let Some((start, end)) = part.trim().split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+
+        let range = if start.is_empty() {
+            // Suffix range: the last N bytes.
+            let Ok(suffix) = end.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if suffix == 0 || total == 0 {
+                continue;
+            }
+            ByteRange {
+                start: total.saturating_sub(suffix),
+                end: total - 1,
+            }
+        } else {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                u64::MAX
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            if start >= total {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(total - 1),
+            }
+        };
+        ranges.push(range);
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+    RangeRequest::Partial(coalesce(ranges))
+}
+
+/// Merges overlapping or adjacent ranges so clients cannot request the same bytes twice.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    if ranges.len() < 2 {
+        return ranges;
+    }
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(
+            parse("bytes=0-99", 1000),
+            RangeRequest::Partial(vec![range(0, 99)])
+        );
+        assert_eq!(
+            parse("bytes=900-", 1000),
+            RangeRequest::Partial(vec![range(900, 999)])
+        );
+        assert_eq!(
+            parse("bytes=-100", 1000),
+            RangeRequest::Partial(vec![range(900, 999)])
+        );
+        assert_eq!(
+            parse("bytes=-5000", 1000),
+            RangeRequest::Partial(vec![range(0, 999)])
+        );
+        assert_eq!(
+            parse("bytes=990-5000", 1000),
+            RangeRequest::Partial(vec![range(990, 999)])
+        );
+        assert_eq!(
+            parse(" bytes=10 - 19 ", 1000),
+            RangeRequest::Partial(vec![range(10, 19)])
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_or_unknown_ranges() {
+        for header in [
+            "items=0-1",
+            "bytes=abc",
+            "bytes=5-1",
+            "bytes=1-x",
+            "bytes=x-",
+            "0-1",
+        ] {
+            assert_eq!(parse(header, 1000), RangeRequest::Full, "{header}");
+        }
+    }
+
+    #[test]
+    fn reports_unsatisfiable_ranges() {
+        assert_eq!(parse("bytes=1000-", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(
+            parse("bytes=2000-3000, 5-9", 1000),
+            RangeRequest::Partial(vec![range(5, 9)])
+        );
+    }
+
+    #[test]
+    fn coalesces_overlapping_and_adjacent_ranges() {
+        assert_eq!(
+            parse("bytes=50-59, 0-9, 5-19, 20-29, 100-", 120),
+            RangeRequest::Partial(vec![range(0, 29), range(50, 59), range(100, 119)])
+        );
+        assert_eq!(
+            coalesce(vec![range(10, 20), range(0, 100)]),
+            vec![range(0, 100)]
+        );
+    }
+
+    #[test]
+    fn serves_everything_for_too_many_ranges() {
+        let header = format!(
+            "bytes={}",
+            (0..=MAX_RANGES)
+                .map(|i| format!("{}-{}", i * 10, i * 10))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        assert_eq!(parse(&header, 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn formats_content_range() {
+        assert_eq!(range(0, 99).content_range(1000), "bytes 0-99/1000");
+        assert_eq!(range(0, 99).len(), 100);
+    }
+}
@@ -0,0 +1,137 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::{Blob, BlobSource, BlobStore, CHUNK_SIZE, StorageError, hex, validate_key};
+use crate::routing::BoxFuture;
+
+/// Stores blobs as files under a root directory.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::create_dir_all(root.as_ref())?;
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+        })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+
+    /// Copies `source` to `dest`, hashing what is written.
+    async fn write(dest: &Path, source: BlobSource<'_>) -> io::Result<String> {
+        let mut out = File::create(dest).await?;
+        let mut hasher = Sha256::new();
+        match source {
+            BlobSource::Bytes(bytes) => {
+                hasher.update(bytes);
+                out.write_all(bytes).await?;
+            }
+            BlobSource::File(path) => {
+                let mut file = File::open(path).await?;
+                let mut chunk = vec![0u8; CHUNK_SIZE];
+                loop {
+                    let n = file.read(&mut chunk).await?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&chunk[..n]);
+                    out.write_all(&chunk[..n]).await?;
+                }
+            }
+        }
+        out.sync_all().await?;
+        Ok(hex(&hasher.finalize()))
+    }
+}
+
+impl BlobStore for LocalStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        source: BlobSource<'a>,
+        sha256: &'a str,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move {
+            let dest = self.path(key)?;
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            // Written next to the destination and renamed, so readers never see a partial blob.
+            let partial = dest.with_extension(format!("partial-{}", Uuid::new_v4().simple()));
+            let actual = match Self::write(&partial, source).await {
+                Ok(actual) => actual,
+                Err(err) => {
+                    let _ = fs::remove_file(&partial).await;
+                    return Err(err.into());
+                }
+            };
+            if actual != sha256 {
+                let _ = fs::remove_file(&partial).await;
+                return Err(StorageError::ChecksumMismatch {
+                    expected: sha256.to_string(),
+                    actual,
+                });
+            }
+            fs::rename(&partial, &dest).await?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Blob, StorageError>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            let mut file = match File::open(&path).await {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    return Err(StorageError::NotFound(key.to_string()));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let size = file.metadata().await?.len();
+
+            let (tx, rx) = mpsc::channel(4);
+            tokio::task::spawn_local(async move {
+                loop {
+                    let mut chunk = vec![0u8; CHUNK_SIZE];
+                    match file.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => {
+                            chunk.truncate(n);
+                            if tx.send(chunk).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            });
+            Ok(Blob { size, stream: rx })
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move {
+            match fs::remove_file(self.path(key)?).await {
+                Ok(()) => Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    Err(StorageError::NotFound(key.to_string()))
+                }
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+}
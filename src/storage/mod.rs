@@ -0,0 +1,254 @@
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use crate::primitives::http::error::AppError;
+use crate::primitives::http::response::BodyStream;
+use crate::routing::BoxFuture;
+use crate::util::signing::hex;
+
+pub mod local;
+pub mod s3;
+pub mod signed_url;
+
+pub use local::LocalStore;
+pub use s3::S3Store;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Data handed to a backend: an in-memory upload or one spooled to disk.
+#[derive(Clone, Copy)]
+pub enum BlobSource<'a> {
+    Bytes(&'a [u8]),
+    File(&'a Path),
+}
+
+/// A stored object, streamed in chunks.
+pub struct Blob {
+    pub size: u64,
+    pub stream: BodyStream,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound(String),
+    ChecksumMismatch { expected: String, actual: String },
+    Io(io::Error),
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound(key) => write!(f, "blob '{}' not found", key),
+            StorageError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "checksum mismatch: expected {}, got {}",
+                    expected, actual
+                )
+            }
+            StorageError::Io(err) => write!(f, "storage I/O error: {}", err),
+            StorageError::Backend(msg) => write!(f, "storage backend error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+impl From<StorageError> for AppError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::NotFound(_) => AppError::NotFound("File content not found.".to_string()),
+            other => AppError::Internal(other.to_string()),
+        }
+    }
+}
+
+/// A blob store backend. Keys are `/`-separated, e.g. `files/<uuid>`.
+pub trait BlobStore: Send + Sync {
+    /// Short name recorded next to each file's metadata, e.g. `local` or `s3`.
+    fn name(&self) -> &'static str;
+
+    /// Stores `source` under `key`. `sha256` is the hex digest the caller computed;
+    /// backends verify the bytes they persist against it.
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        source: BlobSource<'a>,
+        sha256: &'a str,
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Blob, StorageError>>;
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), StorageError>>;
+}
+
+static STORE: OnceLock<Box<dyn BlobStore>> = OnceLock::new();
+
+/// Selects the backend from `STORAGE_BACKEND` (`local` or `s3`) and returns a
+/// description for the startup banner.
+pub fn init() -> Result<String, String> {
+    let backend = env::var("STORAGE_BACKEND").unwrap_or_else(|_| "local".to_string());
+    let (store, description): (Box<dyn BlobStore>, String) = match backend.as_str() {
+        "local" => {
+            let dir = env::var("STORAGE_LOCAL_DIR").unwrap_or_else(|_| "storage".to_string());
+            let store = LocalStore::new(&dir).map_err(|e| format!("{}: {}", dir, e))?;
+            (Box::new(store), format!("local -> {}", dir))
+        }
+        "s3" => {
+            let store = S3Store::from_env()?;
+            let description = format!("s3 -> {}", store.describe());
+            (Box::new(store), description)
+        }
+        other => return Err(format!("unknown STORAGE_BACKEND '{}'", other)),
+    };
+    signed_url::init();
+    let _ = STORE.set(store);
+    Ok(description)
+}
+
+pub fn store() -> &'static dyn BlobStore {
+    STORE
+        .get()
+        .map(|s| s.as_ref())
+        .expect("storage::init must run before storage::store")
+}
+
+/// Hex SHA-256 of `source`, reading spooled files in chunks.
+pub async fn sha256(source: BlobSource<'_>) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    match source {
+        BlobSource::Bytes(bytes) => hasher.update(bytes),
+        BlobSource::File(path) => {
+            let mut file = tokio::fs::File::open(path).await?;
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+            loop {
+                let n = file.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&chunk[..n]);
+            }
+        }
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Keys must be relative, `/`-separated and free of dot segments, so they are safe
+/// as both object names and filesystem paths.
+pub fn validate_key(key: &str) -> Result<(), StorageError> {
+    let valid = !key.is_empty()
+        && key.split('/').all(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('.')
+                && segment
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(StorageError::Backend(format!("invalid blob key '{}'", key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read_all(blob: Blob) -> Vec<u8> {
+        let mut stream = blob.stream;
+        let mut data = Vec::new();
+        while let Some(chunk) = stream.recv().await {
+            data.extend_from_slice(&chunk);
+        }
+        data
+    }
+
+    /// Put, get, checksum rejection and delete, as every backend must behave.
+    async fn round_trip(store: &dyn BlobStore) {
+        let key = format!("test/{}", uuid::Uuid::new_v4());
+        let data = vec![7u8; CHUNK_SIZE * 2 + 10];
+        let digest = sha256(BlobSource::Bytes(&data)).await.unwrap();
+
+        store
+            .put(&key, BlobSource::Bytes(&data), &digest)
+            .await
+            .unwrap();
+        let blob = store.get(&key).await.unwrap();
+        assert_eq!(blob.size, data.len() as u64);
+        assert_eq!(read_all(blob).await, data);
+
+        let other = format!("test/{}", uuid::Uuid::new_v4());
+        let wrong = sha256(BlobSource::Bytes(b"other")).await.unwrap();
+        assert!(
+            store
+                .put(&other, BlobSource::Bytes(&data), &wrong)
+                .await
+                .is_err()
+        );
+        assert!(matches!(
+            store.get(&other).await,
+            Err(StorageError::NotFound(_))
+        ));
+
+        store.delete(&key).await.unwrap();
+        assert!(matches!(
+            store.get(&key).await,
+            Err(StorageError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn validates_keys() {
+        for key in ["files/abc", "a/b-c_d.e", "x"] {
+            assert!(validate_key(key).is_ok(), "{key}");
+        }
+        for key in [
+            "",
+            "/abs",
+            "a//b",
+            "../up",
+            "a/.hidden",
+            "a/b c",
+            "a\\b",
+            "ü",
+        ] {
+            assert!(validate_key(key).is_err(), "{key}");
+        }
+    }
+
+    #[tokio::test]
+    async fn local_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("store-test-{}", uuid::Uuid::new_v4()));
+        let store = LocalStore::new(&dir).unwrap();
+        tokio::task::LocalSet::new()
+            .run_until(round_trip(&store))
+            .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Needs the MinIO from `docker-compose up minio minio-init` and the `S3_*` variables
+    /// in the environment or `.env`: `cargo test s3_store -- --ignored`.
+    #[tokio::test]
+    #[ignore = "needs a running MinIO"]
+    async fn s3_store_round_trip_against_minio() {
+        let _ = dotenv::dotenv();
+        let store = S3Store::from_env().expect("S3_* variables for MinIO");
+        tokio::task::LocalSet::new()
+            .run_until(round_trip(&store))
+            .await;
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use super::{Blob, BlobSource, BlobStore, CHUNK_SIZE, StorageError, validate_key};
+use crate::routing::BoxFuture;
+use crate::util::signing::{hex, hmac_sha256};
+use crate::util::url::percent_encode;
+
+/// SHA-256 of an empty payload, sent with requests that have no body.
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// An S3-compatible backend (AWS S3, MinIO, ...) using path-style requests signed with
+/// AWS Signature Version 4. Like the OTLP exporter, it speaks plain `http://` only,
+/// which is what local stand-ins and in-cluster gateways expose.
+pub struct S3Store {
+    host: String,
+    port: u16,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    timeout: Duration,
+}
+
+struct S3Response {
+    status: u16,
+    headers: HashMap<String, String>,
+    reader: BufReader<TcpStream>,
+}
+
+fn required(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|_| format!("{} is required for the s3 storage backend", name))
+}
+
+impl S3Store {
+    pub fn from_env() -> Result<Self, String> {
+        let endpoint = required("S3_ENDPOINT")?;
+        let authority = endpoint
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("unsupported S3 endpoint '{endpoint}', expected http://"))?
+            .trim_end_matches('/');
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .map_err(|_| format!("invalid port in S3 endpoint '{endpoint}'"))?,
+            ),
+            None => (authority, 80),
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            bucket: required("S3_BUCKET")?,
+            region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            access_key: required("S3_ACCESS_KEY")?,
+            secret_key: required("S3_SECRET_KEY")?,
+            timeout: Duration::from_millis(
+                env::var("S3_TIMEOUT_MS")
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(30000),
+            ),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        format!("http://{}:{}/{}", self.host, self.port, self.bucket)
+    }
+
+    fn host_header(&self) -> String {
+        if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Path-style URI; SigV4 requires every byte but the unreserved set to be encoded.
+    fn uri(&self, key: &str) -> String {
+        let key: Vec<String> = key.split('/').map(percent_encode).collect();
+        format!("/{}/{}", percent_encode(&self.bucket), key.join("/"))
+    }
+
+    fn authorization(&self, method: &str, uri: &str, amz_date: &str, payload_hash: &str) -> String {
+        let date = &amz_date[..8];
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{uri}\n\nhost:{}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+            self.host_header()
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = hmac_sha256(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            date.as_bytes(),
+        );
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, b"s3");
+        let key = hmac_sha256(&key, b"aws4_request");
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key
+        )
+    }
+
+    async fn send(
+        &self,
+        method: &str,
+        key: &str,
+        payload_hash: &str,
+        body: Option<BlobSource<'_>>,
+    ) -> Result<S3Response, StorageError> {
+        validate_key(key)?;
+        let uri = self.uri(key);
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let content_length = match body {
+            None => 0,
+            Some(BlobSource::Bytes(bytes)) => bytes.len() as u64,
+            Some(BlobSource::File(path)) => tokio::fs::metadata(path).await?.len(),
+        };
+
+        let exchange = async {
+            let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+            let head = format!(
+                "{method} {uri} HTTP/1.1\r\nHost: {}\r\nx-amz-date: {amz_date}\r\nx-amz-content-sha256: {payload_hash}\r\nAuthorization: {}\r\nContent-Length: {content_length}\r\nConnection: close\r\n\r\n",
+                self.host_header(),
+                self.authorization(method, &uri, &amz_date, payload_hash),
+            );
+            stream.write_all(head.as_bytes()).await?;
+            match body {
+                None => {}
+                Some(BlobSource::Bytes(bytes)) => stream.write_all(bytes).await?,
+                Some(BlobSource::File(path)) => {
+                    let mut file = tokio::fs::File::open(path).await?;
+                    let mut chunk = vec![0u8; CHUNK_SIZE];
+                    loop {
+                        let n = file.read(&mut chunk).await?;
+                        if n == 0 {
+                            break;
+                        }
+                        stream.write_all(&chunk[..n]).await?;
+                    }
+                }
+            }
+
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).await?;
+            let status = line
+                .split_whitespace()
+                .nth(1)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| std::io::Error::other(format!("bad response: {}", line.trim())))?;
+
+            let mut headers = HashMap::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+                    break;
+                }
+                if let Some((key, value)) = line.trim_end().split_once(':') {
+                    headers.insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
+                }
+            }
+            Ok::<_, std::io::Error>(S3Response {
+                status,
+                headers,
+                reader,
+            })
+        };
+
+        match timeout(self.timeout, exchange).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(StorageError::Backend(format!(
+                "S3 {} {} timed out",
+                method, uri
+            ))),
+        }
+    }
+
+    /// Turns an error response into a `StorageError`, keeping the S3 error code.
+    async fn failure(key: &str, mut response: S3Response) -> StorageError {
+        if response.status == 404 {
+            return StorageError::NotFound(key.to_string());
+        }
+        let mut body = Vec::new();
+        let _ = (&mut response.reader)
+            .take(4096)
+            .read_to_end(&mut body)
+            .await;
+        let body = String::from_utf8_lossy(&body);
+        let code = body
+            .split_once("<Code>")
+            .and_then(|(_, rest)| rest.split_once("</Code>"))
+            .map(|(code, _)| code)
+            .unwrap_or("unknown error");
+        StorageError::Backend(format!("S3 answered {}: {}", response.status, code))
+    }
+}
+
+impl BlobStore for S3Store {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        source: BlobSource<'a>,
+        sha256: &'a str,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        // The digest is signed as `x-amz-content-sha256`, so S3 rejects the upload with
+        // `XAmzContentSHA256Mismatch` if the bytes it received differ.
+        Box::pin(async move {
+            let response = self.send("PUT", key, sha256, Some(source)).await?;
+            if (200..300).contains(&response.status) {
+                Ok(())
+            } else {
+                Err(Self::failure(key, response).await)
+            }
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Blob, StorageError>> {
+        Box::pin(async move {
+            let response = self.send("GET", key, EMPTY_SHA256, None).await?;
+            if response.status != 200 {
+                return Err(Self::failure(key, response).await);
+            }
+            let size = response
+                .headers
+                .get("content-length")
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| {
+                    StorageError::Backend("S3 response without Content-Length".into())
+                })?;
+
+            let (tx, rx) = mpsc::channel(4);
+            let mut reader = response.reader;
+            let read_timeout = self.timeout;
+            tokio::task::spawn_local(async move {
+                let mut remaining = size;
+                while remaining > 0 {
+                    let mut chunk = vec![0u8; remaining.min(CHUNK_SIZE as u64) as usize];
+                    match timeout(read_timeout, reader.read(&mut chunk)).await {
+                        Ok(Ok(n)) if n > 0 => {
+                            chunk.truncate(n);
+                            remaining -= n as u64;
+                            if tx.send(chunk).await.is_err() {
+                                return;
+                            }
+                        }
+                        _ => return,
+                    }
+                }
+            });
+            Ok(Blob { size, stream: rx })
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move {
+            let response = self.send("DELETE", key, EMPTY_SHA256, None).await?;
+            if (200..300).contains(&response.status) {
+                Ok(())
+            } else {
+                Err(Self::failure(key, response).await)
+            }
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::primitives::http::error::AppError;
+use crate::util::signing::{SigningKey, hex, unhex};
+
+static SIGNING_KEY: SigningKey = SigningKey::new("STORAGE_SIGNING_KEY", "signed URLs");
+
+/// Loads `STORAGE_SIGNING_KEY`. Without it a random per-process key is used, so links
+/// stop working on restart and are not shared between instances.
+pub fn init() {
+    SIGNING_KEY.init();
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn payload(path: &str, expires: u64) -> Vec<u8> {
+    format!("{}\n{}", path, expires).into_bytes()
+}
+
+/// Default lifetime of a link, from `STORAGE_URL_TTL_SECS`.
+pub fn default_ttl() -> Duration {
+    Duration::from_secs(
+        env::var("STORAGE_URL_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(300),
+    )
+}
+
+/// `path?expires=<unix seconds>&signature=<hex HMAC-SHA256>`, valid for `ttl`.
+pub fn sign(path: &str, ttl: Duration) -> String {
+    let expires = now() + ttl.as_secs();
+    let signature = hex(&SIGNING_KEY.sign(&payload(path, expires)));
+    format!("{}?expires={}&signature={}", path, expires, signature)
+}
+
+/// Checks the `expires`/`signature` query parameters of a signed link to `path`.
+pub fn verify(path: &str, expires: Option<&str>, signature: Option<&str>) -> Result<(), AppError> {
+    let invalid = || AppError::Forbidden("The download link is invalid.".to_string());
+    let expires = expires
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(invalid)?;
+    let signature = signature.and_then(unhex).ok_or_else(invalid)?;
+    if !SIGNING_KEY.verify(&payload(path, expires), &signature) {
+        return Err(invalid());
+    }
+    if expires < now() {
+        return Err(AppError::Forbidden(
+            "The download link has expired.".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/file/5ffc2d0c-e5ef-4669-a564-5c98766a729b/download";
+
+    fn query(url: &str) -> (String, String) {
+        let (_, query) = url.split_once('?').unwrap();
+        let (expires, signature) = query.split_once('&').unwrap();
+        (
+            expires.strip_prefix("expires=").unwrap().to_string(),
+            signature.strip_prefix("signature=").unwrap().to_string(),
+        )
+    }
+
+    fn status(result: Result<(), AppError>) -> u16 {
+        result.unwrap_err().status_code()
+    }
+
+    #[test]
+    fn signed_links_verify() {
+        let url = sign(PATH, Duration::from_secs(60));
+        assert!(url.starts_with(&format!("{}?expires=", PATH)));
+        let (expires, signature) = query(&url);
+        assert!(verify(PATH, Some(&expires), Some(&signature)).is_ok());
+    }
+
+    #[test]
+    fn tampered_links_are_rejected() {
+        let (expires, signature) = query(&sign(PATH, Duration::from_secs(60)));
+        let later = (expires.parse::<u64>().unwrap() + 3600).to_string();
+        let mut flipped = signature.clone().into_bytes();
+        flipped[0] = if flipped[0] == b'0' { b'1' } else { b'0' };
+        let flipped = String::from_utf8(flipped).unwrap();
+
+        let other = "/file/00000000-0000-0000-0000-000000000000/download";
+        assert_eq!(status(verify(other, Some(&expires), Some(&signature))), 403);
+        assert_eq!(status(verify(PATH, Some(&later), Some(&signature))), 403);
+        assert_eq!(status(verify(PATH, Some(&expires), Some(&flipped))), 403);
+        assert_eq!(
+            status(verify(PATH, Some(&expires), Some(&signature[1..]))),
+            403
+        );
+        assert_eq!(status(verify(PATH, Some(&expires), Some("zz"))), 403);
+        assert_eq!(status(verify(PATH, None, Some(&signature))), 403);
+        assert_eq!(status(verify(PATH, Some(&expires), None)), 403);
+    }
+
+    #[test]
+    fn expired_links_are_rejected() {
+        let expires = now() - 1;
+        let signature = hex(&SIGNING_KEY.sign(&payload(PATH, expires)));
+        let err = verify(PATH, Some(&expires.to_string()), Some(&signature)).unwrap_err();
+        assert_eq!(err.status_code(), 403);
+        assert!(err.to_string().contains("expired"));
+    }
+}
@@ -0,0 +1,261 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use super::{AttributeValue, SpanData, SpanKind, SpanStatus};
+
+/// Receives finished spans in batches, on the exporter thread.
+pub trait SpanExporter: Send {
+    fn export(&mut self, batch: Vec<SpanData>) -> Result<(), String>;
+}
+
+pub struct BatchConfig {
+    pub schedule_delay: Duration,
+    pub max_batch_size: usize,
+    /// Spans queued for the exporter thread before new ones are dropped.
+    pub max_queue_size: usize,
+}
+
+impl BatchConfig {
+    pub fn from_env() -> Self {
+        let delay_ms = env::var("OTEL_BSP_SCHEDULE_DELAY")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(1000);
+        let max_batch_size = env::var("OTEL_BSP_MAX_EXPORT_BATCH_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(512);
+        let max_queue_size = env::var("OTEL_BSP_MAX_QUEUE_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(2048);
+        Self {
+            schedule_delay: Duration::from_millis(delay_ms),
+            max_batch_size: max_batch_size.max(1),
+            max_queue_size,
+        }
+    }
+}
+
+/// Collects spans on a dedicated thread and flushes them every `schedule_delay`
+/// or as soon as `max_batch_size` spans are queued. At most `max_queue_size` spans
+/// wait for the thread, so a slow or unreachable collector cannot grow memory.
+pub fn spawn_batch_processor(
+    mut exporter: Box<dyn SpanExporter>,
+    config: BatchConfig,
+) -> SyncSender<SpanData> {
+    let (sender, receiver) = mpsc::sync_channel::<SpanData>(config.max_queue_size);
+    std::thread::spawn(move || {
+        let mut batch = Vec::new();
+        let mut deadline = Instant::now() + config.schedule_delay;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let disconnected = match receiver.recv_timeout(wait) {
+                Ok(span) => {
+                    batch.push(span);
+                    false
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+
+            let due = Instant::now() >= deadline;
+            let flush = due || disconnected || batch.len() >= config.max_batch_size;
+            if flush
+                && !batch.is_empty()
+                && let Err(err) = exporter.export(std::mem::take(&mut batch))
+            {
+                eprintln!("Trace export failed: {err}");
+            }
+            if due {
+                deadline = Instant::now() + config.schedule_delay;
+            }
+            if disconnected {
+                break;
+            }
+        }
+    });
+    sender
+}
+
+/// Keeps exported spans in memory, for tests and local debugging.
+#[allow(dead_code)]
+#[derive(Clone, Default)]
+pub struct InMemoryExporter {
+    spans: Arc<Mutex<Vec<SpanData>>>,
+}
+
+#[allow(dead_code)]
+impl InMemoryExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spans(&self) -> Vec<SpanData> {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn clear(&self) {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+impl SpanExporter for InMemoryExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> Result<(), String> {
+        self.spans
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend(batch);
+        Ok(())
+    }
+}
+
+/// OTLP/HTTP exporter using the JSON encoding. Only plain `http://` endpoints are
+/// supported; put a local collector in front of TLS-only backends.
+pub struct OtlpHttpExporter {
+    host: String,
+    port: u16,
+    path: String,
+    service_name: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl OtlpHttpExporter {
+    pub fn new(
+        endpoint: &str,
+        service_name: String,
+        headers: Vec<(String, String)>,
+        timeout_ms: u64,
+    ) -> Result<Self, String> {
+        let rest = endpoint
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("unsupported OTLP endpoint '{endpoint}', expected http://"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/v1/traces"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .map_err(|_| format!("invalid port in OTLP endpoint '{endpoint}'"))?,
+            ),
+            None => (authority, 80),
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            service_name,
+            headers,
+            timeout: Duration::from_millis(timeout_ms),
+        })
+    }
+
+    fn post(&self, body: &[u8]) -> io::Result<u16> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::other("OTLP endpoint did not resolve"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.host,
+            self.port,
+            body.len()
+        );
+        for (key, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+
+        let mut status_line = String::new();
+        BufReader::new(&stream).read_line(&mut status_line)?;
+        status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| io::Error::other(format!("bad response: {}", status_line.trim())))
+    }
+}
+
+impl SpanExporter for OtlpHttpExporter {
+    fn export(&mut self, batch: Vec<SpanData>) -> Result<(), String> {
+        let body = encode_otlp_json(&self.service_name, &batch).to_string();
+        match self.post(body.as_bytes()) {
+            Ok(status) if (200..300).contains(&status) => Ok(()),
+            Ok(status) => Err(format!("collector answered {status}")),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+fn encode_attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(v) => json!({ "stringValue": v }),
+        // OTLP/JSON encodes 64-bit integers as strings.
+        AttributeValue::Int(v) => json!({ "intValue": v.to_string() }),
+        AttributeValue::Float(v) => json!({ "doubleValue": v }),
+        AttributeValue::Bool(v) => json!({ "boolValue": v }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn encode_span(span: &SpanData) -> Value {
+    let kind = match span.kind {
+        SpanKind::Internal => 1,
+        SpanKind::Server => 2,
+        SpanKind::Client => 3,
+    };
+    let status = match &span.status {
+        SpanStatus::Unset => json!({ "code": 0 }),
+        SpanStatus::Ok => json!({ "code": 1 }),
+        SpanStatus::Error(message) => json!({ "code": 2, "message": message }),
+    };
+    json!({
+        "traceId": format!("{:032x}", span.context.trace_id),
+        "spanId": format!("{:016x}", span.context.span_id),
+        "parentSpanId": span.parent_span_id.map(|id| format!("{:016x}", id)).unwrap_or_default(),
+        "name": span.name,
+        "kind": kind,
+        "startTimeUnixNano": span.start_unix_nanos.to_string(),
+        "endTimeUnixNano": span.end_unix_nanos.to_string(),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|(k, v)| encode_attribute(k, v))
+            .collect::<Vec<_>>(),
+        "status": status,
+    })
+}
+
+pub fn encode_otlp_json(service_name: &str, spans: &[SpanData]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [encode_attribute(
+                    "service.name",
+                    &AttributeValue::String(service_name.to_string()),
+                )],
+            },
+            "scopeSpans": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans.iter().map(encode_span).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
@@ -0,0 +1,488 @@
+use std::env;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+pub mod exporter;
+use exporter::{BatchConfig, OtlpHttpExporter, SpanExporter};
+
+tokio::task_local! {
+    static CURRENT: SpanContext;
+}
+
+static PIPELINE: OnceLock<SyncSender<SpanData>> = OnceLock::new();
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// W3C trace context identifying one span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub sampled: bool,
+}
+
+impl SpanContext {
+    /// Parses a `traceparent` header: `00-<32 hex trace id>-<16 hex span id>-<2 hex flags>`.
+    pub fn from_traceparent(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+
+        if version.len() != 2
+            || version == "ff"
+            || trace_id.len() != 32
+            || span_id.len() != 16
+            || flags.len() != 2
+        {
+            return None;
+        }
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let span_id = u64::from_str_radix(span_id, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 0x01 == 0x01,
+        })
+    }
+
+    /// Formats the context for propagation to downstream services.
+    #[allow(dead_code)]
+    pub fn to_traceparent(self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.span_id,
+            u8::from(self.sampled)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Internal,
+    Server,
+    Client,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(v: &str) -> Self {
+        Self::String(v.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(v: String) -> Self {
+        Self::String(v)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(v: i64) -> Self {
+        Self::Int(v)
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(v: f64) -> Self {
+        Self::Float(v)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpanStatus {
+    Unset,
+    Ok,
+    Error(String),
+}
+
+/// A finished span, as handed to exporters.
+#[derive(Debug, Clone)]
+pub struct SpanData {
+    pub context: SpanContext,
+    pub parent_span_id: Option<u64>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start_unix_nanos: u128,
+    pub end_unix_nanos: u128,
+    pub attributes: Vec<(String, AttributeValue)>,
+    pub status: SpanStatus,
+}
+
+/// An in-progress span. Exported when ended or dropped; a no-op when tracing is
+/// disabled or the trace is not sampled.
+pub struct Span {
+    context: SpanContext,
+    data: Option<SpanData>,
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+fn random_span_id() -> u64 {
+    loop {
+        let id = Uuid::new_v4().as_u128() as u64;
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+pub fn is_enabled() -> bool {
+    PIPELINE.get().is_some()
+}
+
+/// The span the current task is running under, if any.
+pub fn current() -> Option<SpanContext> {
+    CURRENT.try_with(|ctx| *ctx).ok()
+}
+
+impl Span {
+    /// Starts the entry span of a request, continuing `remote_parent` when the
+    /// caller sent a valid `traceparent`.
+    pub fn root(
+        name: impl Into<String>,
+        kind: SpanKind,
+        remote_parent: Option<SpanContext>,
+    ) -> Self {
+        let context = match remote_parent {
+            Some(parent) => SpanContext {
+                trace_id: parent.trace_id,
+                span_id: random_span_id(),
+                sampled: parent.sampled,
+            },
+            None => SpanContext {
+                trace_id: Uuid::new_v4().as_u128(),
+                span_id: random_span_id(),
+                sampled: true,
+            },
+        };
+        Self::with_context(name.into(), kind, context, remote_parent.map(|p| p.span_id))
+    }
+
+    /// Starts a child of the current span. Outside of a traced task this is a no-op.
+    pub fn child(name: impl Into<String>, kind: SpanKind) -> Self {
+        match current() {
+            Some(parent) => {
+                let context = SpanContext {
+                    trace_id: parent.trace_id,
+                    span_id: random_span_id(),
+                    sampled: parent.sampled,
+                };
+                Self::with_context(name.into(), kind, context, Some(parent.span_id))
+            }
+            None => Self {
+                context: SpanContext {
+                    trace_id: 0,
+                    span_id: 0,
+                    sampled: false,
+                },
+                data: None,
+            },
+        }
+    }
+
+    fn with_context(
+        name: String,
+        kind: SpanKind,
+        context: SpanContext,
+        parent_span_id: Option<u64>,
+    ) -> Self {
+        let data = (context.sampled && is_enabled()).then(|| SpanData {
+            context,
+            parent_span_id,
+            name,
+            kind,
+            start_unix_nanos: unix_nanos(),
+            end_unix_nanos: 0,
+            attributes: Vec::new(),
+            status: SpanStatus::Unset,
+        });
+        Self { context, data }
+    }
+
+    #[allow(dead_code)]
+    pub fn context(&self) -> SpanContext {
+        self.context
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.data.is_some()
+    }
+
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        if let Some(data) = self.data.as_mut() {
+            data.name = name.into();
+        }
+    }
+
+    pub fn set_attribute(&mut self, key: &str, value: impl Into<AttributeValue>) {
+        if let Some(data) = self.data.as_mut() {
+            data.attributes.push((key.to_string(), value.into()));
+        }
+    }
+
+    pub fn set_error(&mut self, message: impl Into<String>) {
+        if let Some(data) = self.data.as_mut() {
+            data.status = SpanStatus::Error(message.into());
+        }
+    }
+
+    /// Runs `fut` with this span as the parent of any span started inside it.
+    pub async fn scope<F: Future>(&self, fut: F) -> F::Output {
+        if self.context.span_id == 0 {
+            return fut.await;
+        }
+        CURRENT.scope(self.context, fut).await
+    }
+
+    pub fn end(self) {}
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(mut data) = self.data.take() {
+            data.end_unix_nanos = unix_nanos();
+            if let Some(pipeline) = PIPELINE.get() {
+                enqueue(pipeline, data);
+            }
+        }
+    }
+}
+
+/// Queues a finished span for the exporter thread, dropping it when the queue is full.
+fn enqueue(pipeline: &SyncSender<SpanData>, data: SpanData) {
+    if let Err(TrySendError::Full(_)) = pipeline.try_send(data) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Spans dropped because the exporter fell more than `OTEL_BSP_MAX_QUEUE_SIZE` behind.
+pub fn dropped_spans() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Installs `exporter` behind a batching thread. Only the first call has an effect.
+pub fn init_with_exporter(exporter: Box<dyn SpanExporter>, config: BatchConfig) {
+    if PIPELINE.get().is_some() {
+        return;
+    }
+    let _ = PIPELINE.set(exporter::spawn_batch_processor(exporter, config));
+}
+
+/// Configures OTLP/HTTP export from the environment. Returns the endpoint for the
+/// startup banner, or `None` when no endpoint is configured.
+///
+/// - `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`: full URL, e.g. `http://localhost:4318/v1/traces`
+/// - `OTEL_EXPORTER_OTLP_ENDPOINT`: base URL; `/v1/traces` is appended
+/// - `OTEL_EXPORTER_OTLP_HEADERS`: extra headers, `key=value,key2=value2`
+/// - `OTEL_EXPORTER_OTLP_TIMEOUT`: export timeout in ms (default: 10000)
+/// - `OTEL_SERVICE_NAME`: `service.name` resource attribute (default: `base-rust-web-api`)
+/// - `OTEL_BSP_SCHEDULE_DELAY` / `OTEL_BSP_MAX_EXPORT_BATCH_SIZE`: batching (default: 1000ms / 512)
+pub fn init_from_env() -> Result<Option<String>, String> {
+    let endpoint = match env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+        Ok(url) if !url.is_empty() => url,
+        _ => match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(base) if !base.is_empty() => format!("{}/v1/traces", base.trim_end_matches('/')),
+            _ => return Ok(None),
+        },
+    };
+
+    let service_name =
+        env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "base-rust-web-api".to_string());
+    let headers = env::var("OTEL_EXPORTER_OTLP_HEADERS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    let timeout_ms = env::var("OTEL_EXPORTER_OTLP_TIMEOUT")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(10_000);
+
+    let exporter = OtlpHttpExporter::new(&endpoint, service_name, headers, timeout_ms)?;
+    init_with_exporter(Box::new(exporter), BatchConfig::from_env());
+    Ok(Some(endpoint))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::exporter::InMemoryExporter;
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parses_traceparent() {
+        let ctx = SpanContext::from_traceparent(TRACEPARENT).unwrap();
+        assert_eq!(ctx.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(ctx.span_id, 0x00f067aa0ba902b7);
+        assert!(ctx.sampled);
+        assert_eq!(ctx.to_traceparent(), TRACEPARENT);
+
+        let unsampled = SpanContext::from_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        )
+        .unwrap();
+        assert!(!unsampled.sampled);
+    }
+
+    #[test]
+    fn accepts_later_versions_with_extra_fields() {
+        let ctx = SpanContext::from_traceparent(
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-future",
+        )
+        .unwrap();
+        assert!(ctx.sampled);
+    }
+
+    #[test]
+    fn rejects_all_zero_ids() {
+        let zero_trace = "00-00000000000000000000000000000000-00f067aa0ba902b7-01";
+        let zero_span = "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01";
+        assert_eq!(SpanContext::from_traceparent(zero_trace), None);
+        assert_eq!(SpanContext::from_traceparent(zero_span), None);
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+        for header in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "0-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b-01",
+            "00-zbf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-zz",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-0100",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-011",
+            "not a traceparent",
+            "00--00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(SpanContext::from_traceparent(header), None, "{header}");
+        }
+    }
+
+    #[test]
+    fn drops_spans_when_the_queue_is_full() {
+        let span = |name: &str| SpanData {
+            context: SpanContext {
+                trace_id: 1,
+                span_id: 1,
+                sampled: true,
+            },
+            parent_span_id: None,
+            name: name.to_string(),
+            kind: SpanKind::Internal,
+            start_unix_nanos: 0,
+            end_unix_nanos: 0,
+            attributes: vec![],
+            status: SpanStatus::Unset,
+        };
+        // No exporter thread drains this queue.
+        let (sender, receiver) = std::sync::mpsc::sync_channel(2);
+        let before = dropped_spans();
+        for name in ["a", "b", "c", "d"] {
+            enqueue(&sender, span(name));
+        }
+        assert!(dropped_spans() >= before + 2);
+        let queued = receiver.try_iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(queued, ["a", "b"]);
+    }
+
+    /// The only test that installs a pipeline, since it is global to the process.
+    #[tokio::test]
+    async fn exports_spans_to_the_in_memory_exporter() {
+        let exporter = InMemoryExporter::new();
+        init_with_exporter(
+            Box::new(exporter.clone()),
+            exporter::BatchConfig {
+                schedule_delay: Duration::from_millis(10),
+                max_batch_size: 1,
+                max_queue_size: 16,
+            },
+        );
+
+        let remote = SpanContext::from_traceparent(TRACEPARENT);
+        let mut root = Span::root("GET /users", SpanKind::Server, remote);
+        root.set_attribute("http.response.status_code", 200_i64);
+        let root_ctx = root.context();
+        root.scope(async {
+            let mut child = Span::child("db.query", SpanKind::Client);
+            child.set_error("timeout");
+        })
+        .await;
+        root.end();
+
+        let unsampled = SpanContext::from_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        );
+        let dropped = Span::root("GET /skipped", SpanKind::Server, unsampled);
+        assert!(!dropped.is_recording());
+        dropped.end();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while exporter.spans().len() < 2 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let spans = exporter.spans();
+        assert_eq!(spans.len(), 2);
+
+        let child = spans.iter().find(|s| s.name == "db.query").unwrap();
+        let root = spans.iter().find(|s| s.name == "GET /users").unwrap();
+        assert_eq!(root.context, root_ctx);
+        assert_eq!(root.context.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(root.parent_span_id, Some(0x00f067aa0ba902b7));
+        assert_eq!(
+            root.attributes,
+            vec![(
+                "http.response.status_code".to_string(),
+                AttributeValue::Int(200)
+            )]
+        );
+        assert_eq!(child.context.trace_id, root.context.trace_id);
+        assert_eq!(child.parent_span_id, Some(root.context.span_id));
+        assert_eq!(child.kind, SpanKind::Client);
+        assert_eq!(child.status, SpanStatus::Error("timeout".to_string()));
+        assert!(child.end_unix_nanos >= child.start_unix_nanos);
+    }
+}
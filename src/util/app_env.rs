@@ -0,0 +1,33 @@
+use std::env;
+use std::sync::OnceLock;
+
+/// Whether the app runs with production behaviour, read once from `APP_ENV`. Only an explicit
+/// `development` (or `dev`/`local`) turns it off, so an unset or misspelled variable still hides
+/// internal error details from clients and keeps diagnostics that cost extra queries off.
+pub fn is_production() -> bool {
+    static PRODUCTION: OnceLock<bool> = OnceLock::new();
+    *PRODUCTION.get_or_init(|| !env::var("APP_ENV").is_ok_and(|v| names_development(&v)))
+}
+
+fn names_development(value: &str) -> bool {
+    let value = value.trim();
+    ["development", "dev", "local"]
+        .iter()
+        .any(|name| value.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_development() {
+        assert!(names_development("development"));
+        assert!(names_development("DEV"));
+        assert!(names_development(" Local\n"));
+        assert!(!names_development("production"));
+        assert!(!names_development("develpoment"));
+        assert!(!names_development("staging"));
+        assert!(!names_development(""));
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldsError {
+    UnknownField(String),
+    UnknownInclude(String),
+}
+
+impl fmt::Display for FieldsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldsError::UnknownField(field) => {
+                write!(f, "Unknown field '{}' in 'fields'.", field)
+            }
+            FieldsError::UnknownInclude(name) => {
+                write!(f, "Cannot include '{}'.", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FieldsError {}
+
+/// A parsed `?fields=...&include=...`, checked against what one entity exposes.
+///
+/// - `fields` is a comma-separated list of columns to return. Absent or empty means all
+///   of them.
+/// - `include` is a comma-separated list of related resources to embed.
+#[derive(Debug, Clone, Default)]
+pub struct FieldSet {
+    fields: Vec<&'static str>,
+    includes: Vec<&'static str>,
+}
+
+impl FieldSet {
+    pub fn parse(
+        fields: Option<&str>,
+        include: Option<&str>,
+        allowed: &[&'static str],
+        includable: &[&'static str],
+    ) -> Result<Self, FieldsError> {
+        let fields = match split(fields) {
+            names if names.is_empty() => allowed.to_vec(),
+            names => {
+                if let Some(unknown) = names.iter().find(|name| !allowed.contains(name)) {
+                    return Err(FieldsError::UnknownField(unknown.to_string()));
+                }
+                // Whitelist order, each column once.
+                allowed
+                    .iter()
+                    .copied()
+                    .filter(|field| names.contains(field))
+                    .collect()
+            }
+        };
+
+        let names = split(include);
+        if let Some(unknown) = names.iter().find(|name| !includable.contains(name)) {
+            return Err(FieldsError::UnknownInclude(unknown.to_string()));
+        }
+        let includes = includable
+            .iter()
+            .copied()
+            .filter(|name| names.contains(name))
+            .collect();
+
+        Ok(Self { fields, includes })
+    }
+
+    /// Whether `field` should be serialized.
+    pub fn contains(&self, field: &str) -> bool {
+        self.fields.contains(&field)
+    }
+
+    pub fn includes(&self, name: &str) -> bool {
+        self.includes.contains(&name)
+    }
+
+    /// The columns to select: the requested ones, plus `required` ones the query needs
+    /// itself (cursor keys, ids to load includes by). Only the requested ones should be
+    /// serialized.
+    pub fn columns(&self, required: &[&'static str]) -> Vec<&'static str> {
+        let mut columns = self.fields.clone();
+        for column in required {
+            if !columns.contains(column) {
+                columns.push(column);
+            }
+        }
+        columns
+    }
+}
+
+fn split(list: Option<&str>) -> Vec<&str> {
+    list.unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLUMNS: &[&str] = &["id", "username", "created_at"];
+    const INCLUDES: &[&str] = &["roles"];
+
+    fn parse(fields: Option<&str>, include: Option<&str>) -> Result<FieldSet, FieldsError> {
+        FieldSet::parse(fields, include, COLUMNS, INCLUDES)
+    }
+
+    #[test]
+    fn selects_every_column_by_default() {
+        for fields in [None, Some(""), Some(" , ")] {
+            let set = parse(fields, None).unwrap();
+            assert_eq!(set.columns(&[]), COLUMNS);
+            assert!(COLUMNS.iter().all(|column| set.contains(column)));
+        }
+    }
+
+    #[test]
+    fn keeps_whitelist_order_without_duplicates() {
+        let set = parse(Some("created_at, username,created_at"), None).unwrap();
+        assert_eq!(set.columns(&[]), ["username", "created_at"]);
+        assert!(!set.contains("id"));
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_includes() {
+        let err = parse(Some("username,password"), None).unwrap_err();
+        assert_eq!(err, FieldsError::UnknownField("password".to_string()));
+        assert_eq!(err.to_string(), "Unknown field 'password' in 'fields'.");
+
+        let err = parse(None, Some("roles,sessions")).unwrap_err();
+        assert_eq!(err, FieldsError::UnknownInclude("sessions".to_string()));
+        assert_eq!(err.to_string(), "Cannot include 'sessions'.");
+    }
+
+    #[test]
+    fn always_selects_required_columns() {
+        let set = parse(Some("username"), None).unwrap();
+        assert_eq!(set.columns(&["id"]), ["username", "id"]);
+        assert_eq!(set.columns(&["username", "id"]), ["username", "id"]);
+        // Selected for the query only, not serialized.
+        assert!(!set.contains("id"));
+    }
+
+    #[test]
+    fn parses_includes() {
+        assert!(parse(None, Some("roles")).unwrap().includes("roles"));
+        assert!(parse(None, Some(" roles ,")).unwrap().includes("roles"));
+        assert!(!parse(None, None).unwrap().includes("roles"));
+    }
+}
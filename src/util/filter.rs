@@ -0,0 +1,466 @@
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::db::builder::{CompareOp, Condition, Order, Select};
+use crate::db::{DbParam, DbType};
+use crate::util::pagination::SortKey;
+
+/// A column a list endpoint exposes to `?filter=` and `?sort=`.
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub ty: DbType,
+    pub filterable: bool,
+    pub sortable: bool,
+}
+
+impl Field {
+    pub const fn new(name: &'static str, ty: DbType) -> Self {
+        Self {
+            name,
+            ty,
+            filterable: true,
+            sortable: true,
+        }
+    }
+
+    pub const fn filter_only(mut self) -> Self {
+        self.sortable = false;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub const fn sort_only(mut self) -> Self {
+        self.filterable = false;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    Syntax(String),
+    UnknownField(String),
+    UnsupportedOperator { field: String, op: String },
+    InvalidValue { field: String, value: String },
+    NotSortable(String),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Syntax(term) => write!(
+                f,
+                "Invalid filter '{}'. Expected field:operator:value.",
+                term
+            ),
+            FilterError::UnknownField(field) => write!(f, "Unknown field '{}'.", field),
+            FilterError::UnsupportedOperator { field, op } => {
+                write!(
+                    f,
+                    "Operator '{}' is not supported for field '{}'.",
+                    op, field
+                )
+            }
+            FilterError::InvalidValue { field, value } => {
+                write!(f, "Invalid value '{}' for field '{}'.", value, field)
+            }
+            FilterError::NotSortable(field) => write!(f, "Cannot sort by '{}'.", field),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// Operators of the filter grammar and the column types they apply to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Like,
+    ILike,
+    In,
+    Null,
+}
+
+impl Op {
+    fn parse(op: &str) -> Option<Self> {
+        Some(match op {
+            "eq" => Op::Eq,
+            "ne" => Op::Ne,
+            "lt" => Op::Lt,
+            "lte" => Op::Lte,
+            "gt" => Op::Gt,
+            "gte" => Op::Gte,
+            "like" => Op::Like,
+            "ilike" => Op::ILike,
+            "in" => Op::In,
+            "null" => Op::Null,
+            _ => return None,
+        })
+    }
+
+    fn applies_to(self, ty: DbType) -> bool {
+        match self {
+            Op::Eq | Op::Ne | Op::Null => true,
+            Op::In => matches!(
+                ty,
+                DbType::Int32 | DbType::Int64 | DbType::Text | DbType::Uuid
+            ),
+            Op::Lt | Op::Lte | Op::Gt | Op::Gte => matches!(
+                ty,
+                DbType::Int32
+                    | DbType::Int64
+                    | DbType::Float64
+                    | DbType::Numeric
+                    | DbType::Text
+                    | DbType::Timestamptz
+            ),
+            Op::Like | Op::ILike => ty == DbType::Text,
+        }
+    }
+}
+
+/// A parsed `?filter=...&sort=...`, checked against the fields of one entity.
+///
+/// - `filter` is a comma-separated list of `field:op:value` terms, all of which must
+///   match. Operators: `eq`, `ne`, `lt`, `lte`, `gt`, `gte`, `like`, `ilike` (`*` is the
+///   wildcard), `in` (values separated by `|`) and `null` (`true` or `false`).
+/// - `sort` is a comma-separated list of fields, descending when prefixed with `-`.
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    pub conditions: Vec<Condition>,
+    pub sort: Vec<(&'static str, Order, DbType)>,
+}
+
+impl ListQuery {
+    pub fn parse(
+        filter: Option<&str>,
+        sort: Option<&str>,
+        fields: &[Field],
+    ) -> Result<Self, FilterError> {
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|field| field.name == name)
+                .ok_or_else(|| FilterError::UnknownField(name.to_string()))
+        };
+
+        let mut conditions = vec![];
+        for term in filter.unwrap_or("").split(',').filter(|t| !t.is_empty()) {
+            let mut parts = term.splitn(3, ':');
+            let (Some(name), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(FilterError::Syntax(term.to_string()));
+            };
+            let field = field(name)?;
+            let unsupported = || FilterError::UnsupportedOperator {
+                field: name.to_string(),
+                op: op.to_string(),
+            };
+            if !field.filterable {
+                return Err(unsupported());
+            }
+            let op = Op::parse(op)
+                .filter(|op| op.applies_to(field.ty))
+                .ok_or_else(unsupported)?;
+            conditions.push(Self::condition(field, op, value)?);
+        }
+
+        let mut keys = vec![];
+        for key in sort
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+        {
+            let (name, order) = match key.strip_prefix('-') {
+                Some(name) => (name, Order::Desc),
+                None => (key.strip_prefix('+').unwrap_or(key), Order::Asc),
+            };
+            let field = field(name)?;
+            if !field.sortable {
+                return Err(FilterError::NotSortable(name.to_string()));
+            }
+            keys.push((field.name, order, field.ty));
+        }
+
+        Ok(Self {
+            conditions,
+            sort: keys,
+        })
+    }
+
+    fn condition(field: &Field, op: Op, value: &str) -> Result<Condition, FilterError> {
+        let invalid = || FilterError::InvalidValue {
+            field: field.name.to_string(),
+            value: value.to_string(),
+        };
+        let column = field.name;
+        let compare = |op: CompareOp| -> Result<Condition, FilterError> {
+            let value = parse_value(value, field.ty).ok_or_else(invalid)?;
+            Ok(Condition::compare(column, op, value))
+        };
+        match op {
+            Op::Eq => compare(CompareOp::Eq),
+            Op::Ne => compare(CompareOp::Ne),
+            Op::Lt => compare(CompareOp::Lt),
+            Op::Lte => compare(CompareOp::Lte),
+            Op::Gt => compare(CompareOp::Gt),
+            Op::Gte => compare(CompareOp::Gte),
+            Op::Like => Ok(Condition::like(column, like_pattern(value))),
+            Op::ILike => Ok(Condition::ilike(column, like_pattern(value))),
+            Op::Null => match value {
+                "true" => Ok(Condition::is_null(column)),
+                "false" => Ok(Condition::is_not_null(column)),
+                _ => Err(invalid()),
+            },
+            Op::In => {
+                let values = value.split('|');
+                let array = match field.ty {
+                    DbType::Int32 => DbParam::Int32Array(
+                        values
+                            .map(|v| v.parse().ok())
+                            .collect::<Option<_>>()
+                            .ok_or_else(invalid)?,
+                    ),
+                    DbType::Int64 => DbParam::Int64Array(
+                        values
+                            .map(|v| v.parse().ok())
+                            .collect::<Option<_>>()
+                            .ok_or_else(invalid)?,
+                    ),
+                    DbType::Uuid => DbParam::UuidArray(
+                        values
+                            .map(|v| v.parse().ok())
+                            .collect::<Option<_>>()
+                            .ok_or_else(invalid)?,
+                    ),
+                    _ => DbParam::TextArray(values.map(str::to_string).collect()),
+                };
+                Ok(Condition::is_in(column, array))
+            }
+        }
+    }
+
+    /// Adds the conditions and the `ORDER BY` to `select`.
+    pub fn apply(&self, select: Select) -> Select {
+        // `parse` only accepted sortable fields.
+        let columns = self
+            .sort
+            .iter()
+            .map(|&(column, _, _)| column)
+            .collect::<Vec<_>>();
+        let mut select = self.apply_filters(select).sortable(&columns);
+        for &(column, order, _) in &self.sort {
+            select = select
+                .order_by(column, order)
+                .expect("sort columns are whitelisted above");
+        }
+        select
+    }
+
+    /// The sort as cursor keys, falling back to `default` and ending with `tiebreaker`
+    /// (a unique column) so the keyset is unique.
+    pub fn sort_keys(&self, default: &[SortKey], tiebreaker: SortKey) -> Vec<SortKey> {
+        let mut keys = if self.sort.is_empty() {
+            default.to_vec()
+        } else {
+            self.sort
+                .iter()
+                .map(|&(column, order, ty)| SortKey { column, order, ty })
+                .collect()
+        };
+        if !keys.iter().any(|key| key.column == tiebreaker.column) {
+            keys.push(tiebreaker);
+        }
+        keys
+    }
+
+    /// Adds only the conditions; the cursor pagination orders by its sort keys.
+    pub fn apply_filters(&self, mut select: Select) -> Select {
+        for condition in &self.conditions {
+            select = select.filter(condition.clone());
+        }
+        select
+    }
+}
+
+/// Turns `*` into `%` and escapes the characters `LIKE` treats specially.
+pub fn like_pattern(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('*', "%")
+}
+
+/// Parses a filter value for a column of type `ty`. Timestamps also accept a plain
+/// date, meaning midnight UTC.
+fn parse_value(value: &str, ty: DbType) -> Option<DbParam> {
+    Some(match ty {
+        DbType::Int32 => DbParam::Int32(value.parse().ok()?),
+        DbType::Int64 => DbParam::Int64(value.parse().ok()?),
+        DbType::Float64 => DbParam::Float64(value.parse().ok()?),
+        DbType::Bool => DbParam::Bool(value.parse().ok()?),
+        DbType::Text => DbParam::Text(value.to_string()),
+        DbType::Uuid => DbParam::Uuid(value.parse().ok()?),
+        DbType::Numeric => DbParam::Numeric(value.parse().ok()?),
+        DbType::Timestamptz => DbParam::Timestamptz(match DateTime::parse_from_rfc3339(value) {
+            Ok(at) => at.with_timezone(&Utc),
+            Err(_) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)?
+                .and_utc(),
+        }),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[Field] = &[
+        Field::new("id", DbType::Uuid).filter_only(),
+        Field::new("username", DbType::Text),
+        Field::new("age", DbType::Int64),
+        Field::new("created_at", DbType::Timestamptz),
+        Field::new("score", DbType::Float64).sort_only(),
+    ];
+
+    fn parse(filter: &str, sort: &str) -> Result<ListQuery, FilterError> {
+        ListQuery::parse(Some(filter), Some(sort), FIELDS)
+    }
+
+    /// The `WHERE ... ORDER BY ...` part of the query and its parameters.
+    fn render(filter: &str, sort: &str) -> (String, String) {
+        let query = parse(filter, sort).unwrap();
+        let statement = query.apply(Select::from("T")).build();
+        let sql = statement
+            .sql
+            .trim_start_matches("SELECT * FROM \"T\"")
+            .trim();
+        (sql.to_string(), format!("{:?}", statement.params))
+    }
+
+    #[test]
+    fn parses_comparisons() {
+        let (sql, params) = render("age:gte:18,age:lt:65,username:ne:root", "");
+        assert_eq!(
+            sql,
+            "WHERE (\"age\" >= $1) AND (\"age\" < $2) AND (\"username\" <> $3)"
+        );
+        assert_eq!(params, r#"[Int64(18), Int64(65), Text("root")]"#);
+    }
+
+    #[test]
+    fn keeps_colons_in_values() {
+        let (sql, params) = render("created_at:gt:2026-10-19T08:30:00Z", "");
+        assert_eq!(sql, "WHERE \"created_at\" > $1");
+        assert_eq!(params, "[Timestamptz(2026-10-19T08:30:00Z)]");
+
+        let (_, params) = render("created_at:lte:2026-10-19", "");
+        assert_eq!(params, "[Timestamptz(2026-10-19T00:00:00Z)]");
+    }
+
+    #[test]
+    fn escapes_like_patterns() {
+        let (sql, params) = render("username:ilike:a_b%c*", "");
+        assert_eq!(sql, "WHERE \"username\" ILIKE $1");
+        assert_eq!(params, r#"[Text("a\\_b\\%c%")]"#);
+    }
+
+    #[test]
+    fn parses_in_and_null() {
+        let (sql, params) = render("age:in:1|2|3,username:null:false", "");
+        assert_eq!(
+            sql,
+            "WHERE (\"age\" = ANY($1)) AND (\"username\" IS NOT NULL)"
+        );
+        assert_eq!(params, "[Int64Array([1, 2, 3])]");
+
+        let (sql, _) = render("created_at:null:true", "");
+        assert_eq!(sql, "WHERE \"created_at\" IS NULL");
+    }
+
+    #[test]
+    fn parses_sort_keys() {
+        let (sql, _) = render("", " -created_at, +username ,score");
+        assert_eq!(
+            sql,
+            "ORDER BY \"created_at\" DESC, \"username\" ASC, \"score\" ASC"
+        );
+
+        let query = ListQuery::parse(None, None, FIELDS).unwrap();
+        assert!(query.conditions.is_empty() && query.sort.is_empty());
+    }
+
+    #[test]
+    fn ends_sort_keys_with_the_tiebreaker() {
+        let id = SortKey {
+            column: "id",
+            order: Order::Asc,
+            ty: DbType::Uuid,
+        };
+        let columns = |query: &ListQuery| {
+            query
+                .sort_keys(&[], id)
+                .iter()
+                .map(|key| key.column)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(columns(&parse("", "-age").unwrap()), ["age", "id"]);
+        assert_eq!(columns(&parse("", "").unwrap()), ["id"]);
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        let error = |filter: &str, sort: &str| parse(filter, sort).unwrap_err();
+        let unsupported = |field: &str, op: &str| FilterError::UnsupportedOperator {
+            field: field.to_string(),
+            op: op.to_string(),
+        };
+        let invalid = |field: &str, value: &str| FilterError::InvalidValue {
+            field: field.to_string(),
+            value: value.to_string(),
+        };
+
+        assert_eq!(
+            error("username:eq", ""),
+            FilterError::Syntax("username:eq".to_string())
+        );
+        assert_eq!(
+            error("password:eq:x", ""),
+            FilterError::UnknownField("password".to_string())
+        );
+        assert_eq!(error("age:like:1*", ""), unsupported("age", "like"));
+        assert_eq!(error("age:between:1", ""), unsupported("age", "between"));
+        assert_eq!(error("id:lt:x", ""), unsupported("id", "lt"));
+        assert_eq!(error("score:eq:1", ""), unsupported("score", "eq"));
+        assert_eq!(error("age:eq:ten", ""), invalid("age", "ten"));
+        assert_eq!(error("age:in:1|x", ""), invalid("age", "1|x"));
+        assert_eq!(error("id:eq:42", ""), invalid("id", "42"));
+        assert_eq!(
+            error("username:null:maybe", ""),
+            invalid("username", "maybe")
+        );
+        assert_eq!(
+            error("created_at:gt:yesterday", ""),
+            invalid("created_at", "yesterday")
+        );
+        assert_eq!(error("", "-id"), FilterError::NotSortable("id".to_string()));
+        assert_eq!(
+            error("", "password"),
+            FilterError::UnknownField("password".to_string())
+        );
+        assert_eq!(
+            error("username:eq", "").to_string(),
+            "Invalid filter 'username:eq'. Expected field:operator:value."
+        );
+    }
+}
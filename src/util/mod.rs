@@ -1 +1,6 @@
+pub mod app_env;
+pub mod fields;
+pub mod filter;
 pub mod pagination;
+pub mod signing;
+pub mod url;
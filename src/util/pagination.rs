@@ -1,79 +1,475 @@
-use crate::db::DbParam;
+use std::fmt;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::{Value, json};
+use sqlx::Row;
+use sqlx::postgres::PgRow;
+use uuid::Uuid;
+
+use crate::db::builder::{BuildError, CompareOp, Condition, Order, Select, Statement};
+use crate::db::{DbParam, DbType, Executor, FromRow};
+use crate::util::signing::SigningKey;
+
+/// Largest page `top` or a cursor page `limit` may ask for; larger values are clamped.
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Page size used when the request has no `top`.
+pub const DEFAULT_PAGE_SIZE: i64 = 10;
 
 pub struct PaginationQuery {
-    pub sql: String,
-    pub params: Vec<DbParam>,
+    pub statement: Statement,
     pub top: i64,
     pub skip: i64,
 }
 
-pub fn build_paginated_json_query(
-    table: &str,
-    select_columns: &str,
-    json_object: &str,
-    where_clause: Option<&str>,
-    mut where_params: Vec<DbParam>,
+/// One page of results, serialized as `{"page", "total_pages", "data"}`.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub page: i64,
+    pub total_pages: i64,
+    pub data: Vec<T>,
+}
+
+impl<T> Page<T> {
+    pub fn new(data: Vec<T>, total: i64, top: i64, skip: i64) -> Self {
+        // With `top = 0` every page is empty; report a single page instead of dividing by zero.
+        let (page, total_pages) = if top > 0 {
+            (skip / top + 1, (total + top - 1) / top)
+        } else {
+            (1, 1)
+        };
+        Self {
+            page,
+            total_pages,
+            data,
+        }
+    }
+}
+
+/// Adds `OFFSET`/`LIMIT` to `select`, with `top` defaulting to `DEFAULT_PAGE_SIZE` and clamped to `MAX_PAGE_SIZE`.
+/// Every row carries the number of matching rows in a `total` column (a window count, evaluated before `OFFSET`/`LIMIT`).
+pub fn build_paginated_query(
+    select: &Select,
     top: Option<i64>,
     skip: Option<i64>,
 ) -> PaginationQuery {
-    let top_val = top.unwrap_or(10);
-    let skip_val = skip.unwrap_or(0);
+    let top = top.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let mut select = select.clone().column_expr("COUNT(*) OVER () AS total");
+    if let Some(skip) = skip {
+        select = select.offset(skip);
+    }
+    select = select.limit(top);
+
+    PaginationQuery {
+        statement: select.build(),
+        top,
+        skip: skip.unwrap_or(0),
+    }
+}
+
+/// Runs `build_paginated_query` and maps the rows into `T`. A page past the end has
+/// no row to read `total` from, so the matching rows are counted separately.
+pub async fn fetch_page<T: FromRow>(
+    mut db: impl Executor,
+    select: &Select,
+    top: Option<i64>,
+    skip: Option<i64>,
+) -> Result<Page<T>, sqlx::Error> {
+    let pagination = build_paginated_query(select, top, skip);
+    let rows = db
+        .query(&pagination.statement.sql, pagination.statement.params)
+        .await?;
+
+    let total = match rows.first() {
+        Some(row) => row.try_get::<i64, _>("total")?,
+        None if pagination.skip > 0 => {
+            let count = select.count();
+            match db.query(&count.sql, count.params).await?.first() {
+                Some(row) => row.try_get::<i64, _>("total")?,
+                None => 0,
+            }
+        }
+        None => 0,
+    };
+    let data = rows.iter().map(T::from_row).collect::<Result<_, _>>()?;
+
+    Ok(Page::new(data, total, pagination.top, pagination.skip))
+}
+
+/// One column of the sort key of a cursor-paginated listing. The columns together
+/// must be unique and `NOT NULL` (end with the primary key), or rows get skipped.
+#[derive(Debug, Clone, Copy)]
+pub struct SortKey {
+    pub column: &'static str,
+    pub order: Order,
+    /// How the value is stored in the cursor. Integers, floats, booleans, text,
+    /// UUIDs, timestamps and numerics are supported.
+    pub ty: DbType,
+}
+
+/// Where a cursor-paginated listing starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CursorPosition {
+    First,
+    /// The rows after the `next_cursor` of a page.
+    After(String),
+    /// The rows before the `prev_cursor` of a page.
+    Before(String),
+}
+
+/// A page of a keyset-paginated listing. A cursor is `None` when there is nothing
+/// in that direction; `total` is only present when it was requested.
+#[derive(Debug, Serialize)]
+pub struct CursorPage<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum CursorError {
+    /// The cursor was tampered with, is malformed or belongs to another listing.
+    InvalidCursor,
+    Build(BuildError),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorError::InvalidCursor => write!(f, "Invalid pagination cursor"),
+            CursorError::Build(err) => write!(f, "{}", err),
+            CursorError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
 
-    let mut params = vec![];
-    params.append(&mut where_params);
-    let mut clauses = vec![];
+impl std::error::Error for CursorError {}
 
-    if skip.is_some() {
-        clauses.push(format!("OFFSET ${}", params.len() + 1));
-        params.push(DbParam::Int64(skip_val));
+impl From<sqlx::Error> for CursorError {
+    fn from(err: sqlx::Error) -> Self {
+        CursorError::Database(err)
     }
+}
 
-    if top.is_some() {
-        clauses.push(format!("LIMIT ${}", params.len() + 1));
-        params.push(DbParam::Int64(top_val));
+impl From<BuildError> for CursorError {
+    fn from(err: BuildError) -> Self {
+        CursorError::Build(err)
     }
+}
+
+/// `PAGINATION_CURSOR_KEY`. Without it a random per-process key is used, so cursors
+/// stop working on restart and are not shared between instances.
+static CURSOR_KEY: SigningKey = SigningKey::new("PAGINATION_CURSOR_KEY", "cursors");
 
-    let clause_str = if !clauses.is_empty() {
-        format!(" {}", clauses.join(" "))
+/// The sort key of `row` as JSON.
+fn key_value(row: &PgRow, key: &SortKey) -> Result<Value, sqlx::Error> {
+    let column = key.column;
+    Ok(match key.ty {
+        DbType::Int32 => json!(row.try_get::<i32, _>(column)?),
+        DbType::Int64 => json!(row.try_get::<i64, _>(column)?),
+        DbType::Float64 => json!(row.try_get::<f64, _>(column)?),
+        DbType::Bool => json!(row.try_get::<bool, _>(column)?),
+        DbType::Text => json!(row.try_get::<String, _>(column)?),
+        DbType::Uuid => json!(row.try_get::<Uuid, _>(column)?.to_string()),
+        DbType::Timestamptz => json!(
+            row.try_get::<DateTime<Utc>, _>(column)?
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        ),
+        DbType::Numeric => json!(row.try_get::<Decimal, _>(column)?.to_string()),
+        ty => {
+            return Err(sqlx::Error::Decode(
+                format!("{:?} column '{}' cannot be a cursor key", ty, column).into(),
+            ));
+        }
+    })
+}
+
+/// The inverse of `key_value`.
+fn key_param(value: &Value, ty: DbType) -> Option<DbParam> {
+    Some(match ty {
+        DbType::Int32 => DbParam::Int32(i32::try_from(value.as_i64()?).ok()?),
+        DbType::Int64 => DbParam::Int64(value.as_i64()?),
+        DbType::Float64 => DbParam::Float64(value.as_f64()?),
+        DbType::Bool => DbParam::Bool(value.as_bool()?),
+        DbType::Text => DbParam::Text(value.as_str()?.to_string()),
+        DbType::Uuid => DbParam::Uuid(Uuid::parse_str(value.as_str()?).ok()?),
+        DbType::Timestamptz => DbParam::Timestamptz(
+            DateTime::parse_from_rfc3339(value.as_str()?)
+                .ok()?
+                .with_timezone(&Utc),
+        ),
+        DbType::Numeric => DbParam::Numeric(value.as_str()?.parse().ok()?),
+        _ => return None,
+    })
+}
+
+/// `base64url(payload).base64url(HMAC-SHA256(payload))`, where the payload names the
+/// key columns so a cursor cannot be replayed against a listing sorted differently.
+fn encode_cursor(row: &PgRow, keys: &[SortKey]) -> Result<String, sqlx::Error> {
+    let values = keys
+        .iter()
+        .map(|key| key_value(row, key))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sign_cursor(&CURSOR_KEY, keys, &values))
+}
+
+fn sign_cursor(signing_key: &SigningKey, keys: &[SortKey], values: &[Value]) -> String {
+    let columns = keys.iter().map(|key| key.column).collect::<Vec<_>>();
+    let payload = json!({ "c": columns, "v": values }).to_string();
+    let signature = signing_key.sign(payload.as_bytes());
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(signature)
+    )
+}
+
+fn decode_cursor(cursor: &str, keys: &[SortKey]) -> Result<Vec<DbParam>, CursorError> {
+    let invalid = || CursorError::InvalidCursor;
+    let (payload, signature) = cursor.split_once('.').ok_or_else(invalid)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+    if !CURSOR_KEY.verify(&payload, &signature) {
+        return Err(invalid());
+    }
+
+    let payload: Value = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+    let columns = payload["c"].as_array().ok_or_else(invalid)?;
+    let values = payload["v"].as_array().ok_or_else(invalid)?;
+    if columns.len() != keys.len() || values.len() != keys.len() {
+        return Err(invalid());
+    }
+    keys.iter()
+        .zip(columns)
+        .zip(values)
+        .map(|((key, column), value)| {
+            if column.as_str() != Some(key.column) {
+                return None;
+            }
+            key_param(value, key.ty)
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)
+}
+
+/// Rows strictly past `values` in the given direction of the sort: for keys `(a, b)`,
+/// `a > $1 OR (a = $1 AND b > $2)`, with `<` for descending keys.
+fn keyset_condition(keys: &[SortKey], values: &[DbParam], forward: bool) -> Condition {
+    let branches = (0..keys.len())
+        .map(|i| {
+            let mut parts = (0..i)
+                .map(|j| Condition::eq(keys[j].column, values[j].clone()))
+                .collect::<Vec<_>>();
+            let op = match (keys[i].order, forward) {
+                (Order::Asc, true) | (Order::Desc, false) => CompareOp::Gt,
+                (Order::Asc, false) | (Order::Desc, true) => CompareOp::Lt,
+            };
+            parts.push(Condition::compare(keys[i].column, op, values[i].clone()));
+            Condition::and(parts)
+        })
+        .collect();
+    Condition::or(branches)
+}
+
+/// Keyset pagination: fetches `limit` rows (at most `MAX_PAGE_SIZE`) of `select` in
+/// `keys` order, starting past the cursor in `position`, without `OFFSET`. Rows inserted
+/// meanwhile neither shift nor repeat the following pages. `with_total` adds a
+/// `COUNT(*)` over the whole listing.
+pub async fn fetch_cursor_page<T: FromRow>(
+    mut db: impl Executor,
+    select: &Select,
+    keys: &[SortKey],
+    position: &CursorPosition,
+    limit: i64,
+    with_total: bool,
+) -> Result<CursorPage<T>, CursorError> {
+    let (cursor, forward) = match position {
+        CursorPosition::First => (None, true),
+        CursorPosition::After(cursor) => (Some(cursor), true),
+        CursorPosition::Before(cursor) => (Some(cursor), false),
+    };
+
+    let mut page_select = select.clone();
+    if let Some(cursor) = cursor {
+        let values = decode_cursor(cursor, keys)?;
+        page_select = page_select.filter(keyset_condition(keys, &values, forward));
+    }
+    for key in keys {
+        // `before` walks backwards from the cursor; the rows are flipped back below.
+        let order = match (key.order, forward) {
+            (order, true) => order,
+            (Order::Asc, false) => Order::Desc,
+            (Order::Desc, false) => Order::Asc,
+        };
+        page_select = page_select.order_by(key.column, order)?;
+    }
+    // One extra row tells whether there is more in this direction.
+    let limit = limit.clamp(0, MAX_PAGE_SIZE);
+    let statement = page_select.limit(limit.saturating_add(1)).build();
+    let mut rows = db.query(&statement.sql, statement.params).await?;
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    if !forward {
+        rows.reverse();
+    }
+
+    let cursor_at = |row: Option<&PgRow>| row.map(|row| encode_cursor(row, keys)).transpose();
+    let (next_cursor, prev_cursor) = if forward {
+        let next = if has_more {
+            cursor_at(rows.last())?
+        } else {
+            None
+        };
+        let prev = if cursor.is_some() {
+            cursor_at(rows.first())?
+        } else {
+            None
+        };
+        (next, prev)
     } else {
-        String::new()
+        let prev = if has_more {
+            cursor_at(rows.first())?
+        } else {
+            None
+        };
+        (cursor_at(rows.last())?, prev)
     };
 
-    let where_str = if let Some(where_clause) = where_clause {
-        format!(" WHERE {}", where_clause)
+    let total = if with_total {
+        let count = select.count();
+        let rows = db.query(&count.sql, count.params).await?;
+        Some(match rows.first() {
+            Some(row) => row.try_get::<i64, _>("total")?,
+            None => 0,
+        })
     } else {
-        String::new()
+        None
     };
 
-    let sql = format!(
-        "WITH page AS (
-            SELECT
-                {select_columns}
-            FROM \"{table}\"
-            {where_str} {clause_str}
-        )\n\
-        SELECT (
-                SELECT
-                    COUNT(*)
-                FROM \"{table}\"
-                {where_str}
-            ) AS total,\n\
-            COALESCE(
-                json_agg(json_build_object({json_object})),
-                '[]'
-            ) AS data_json\n\
-        FROM page;",
-        select_columns = select_columns,
-        table = table,
-        where_str = where_str,
-        clause_str = clause_str,
-        json_object = json_object
-    );
+    Ok(CursorPage {
+        data: rows.iter().map(T::from_row).collect::<Result<_, _>>()?,
+        next_cursor,
+        prev_cursor,
+        total,
+    })
+}
 
-    PaginationQuery {
-        sql,
-        params,
-        top: top_val,
-        skip: skip_val,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: &[SortKey] = &[
+        SortKey {
+            column: "created_at",
+            order: Order::Desc,
+            ty: DbType::Timestamptz,
+        },
+        SortKey {
+            column: "id",
+            order: Order::Asc,
+            ty: DbType::Uuid,
+        },
+    ];
+
+    fn values() -> Vec<Value> {
+        vec![
+            json!("2026-10-19T08:30:00.123456Z"),
+            json!("67e55044-10b1-426f-9247-bb680e5fe0c8"),
+        ]
+    }
+
+    fn params(cursor: &str, keys: &[SortKey]) -> Result<String, String> {
+        decode_cursor(cursor, keys)
+            .map(|params| format!("{:?}", params))
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = sign_cursor(&CURSOR_KEY, KEYS, &values());
+        assert_eq!(
+            params(&cursor, KEYS).unwrap(),
+            "[Timestamptz(2026-10-19T08:30:00.123456Z), \
+             Uuid(67e55044-10b1-426f-9247-bb680e5fe0c8)]"
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_cursors() {
+        let cursor = sign_cursor(&CURSOR_KEY, KEYS, &values());
+        let (payload, signature) = cursor.split_once('.').unwrap();
+
+        let mut forged = URL_SAFE_NO_PAD.decode(payload).unwrap();
+        let at = forged.iter().position(|&b| b == b'8').unwrap();
+        forged[at] = b'9';
+        let forged = format!("{}.{}", URL_SAFE_NO_PAD.encode(forged), signature);
+        assert!(params(&forged, KEYS).is_err());
+
+        let mut flipped = URL_SAFE_NO_PAD.decode(signature).unwrap();
+        flipped[0] ^= 1;
+        let flipped = format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(flipped));
+        assert!(params(&flipped, KEYS).is_err());
+
+        for garbage in ["", ".", "abc", "!!.!!", payload] {
+            assert_eq!(
+                params(garbage, KEYS).unwrap_err(),
+                "Invalid pagination cursor"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_cursors_signed_with_another_key() {
+        let other = SigningKey::new("PAGINATION_TEST_OTHER_KEY_UNSET", "cursors");
+        let cursor = sign_cursor(&other, KEYS, &values());
+        assert!(params(&cursor, KEYS).is_err());
+    }
+
+    #[test]
+    fn rejects_cursors_of_another_sort() {
+        let cursor = sign_cursor(&CURSOR_KEY, KEYS, &values());
+        let reordered = [KEYS[1], KEYS[0]];
+        assert!(params(&cursor, &reordered).is_err());
+        assert!(params(&cursor, &KEYS[..1]).is_err());
+
+        let retyped = [
+            KEYS[0],
+            SortKey {
+                ty: DbType::Int64,
+                ..KEYS[1]
+            },
+        ];
+        assert!(params(&cursor, &retyped).is_err());
+    }
+
+    #[test]
+    fn clamps_the_page_size() {
+        let select = Select::from("USER").columns(&["id"]);
+        let query = build_paginated_query(&select, Some(i64::MAX), Some(0));
+        assert_eq!(query.top, MAX_PAGE_SIZE);
+        assert!(format!("{:?}", query.statement.params).contains("Int64(1000)"));
+
+        let page = Page::<()>::new(vec![], 2500, query.top, 2000);
+        assert_eq!((page.page, page.total_pages), (3, 3));
+    }
+
+    #[test]
+    fn limits_to_the_default_page_size() {
+        let select = Select::from("USER").columns(&["id"]);
+        let query = build_paginated_query(&select, None, None);
+        assert_eq!(query.top, DEFAULT_PAGE_SIZE);
+        assert!(
+            query.statement.sql.contains("LIMIT"),
+            "{}",
+            query.statement.sql
+        );
+        assert!(format!("{:?}", query.statement.params).contains("Int64(10)"));
     }
 }
@@ -0,0 +1,118 @@
+use std::env;
+use std::sync::OnceLock;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The inverse of `hex`; `None` for odd lengths or non-hex characters.
+pub fn unhex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn mac(key: &[u8], data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    mac(key, data).finalize().into_bytes().to_vec()
+}
+
+/// An HMAC-SHA256 key read from the environment variable `var` on first use. Without
+/// it a random per-process key is used, so whatever was signed with it (`purpose`)
+/// stops verifying on restart and is not shared between instances.
+pub struct SigningKey {
+    var: &'static str,
+    purpose: &'static str,
+    key: OnceLock<Vec<u8>>,
+}
+
+impl SigningKey {
+    pub const fn new(var: &'static str, purpose: &'static str) -> Self {
+        Self {
+            var,
+            purpose,
+            key: OnceLock::new(),
+        }
+    }
+
+    /// Loads the key now, e.g. at startup so the warning shows up in the banner.
+    pub fn init(&self) {
+        self.key();
+    }
+
+    fn key(&self) -> &[u8] {
+        self.key.get_or_init(|| match env::var(self.var) {
+            Ok(key) if !key.is_empty() => key.into_bytes(),
+            _ => {
+                eprintln!(
+                    "{} is not set, {} will not survive a restart",
+                    self.var, self.purpose
+                );
+                format!("{}{}", Uuid::new_v4(), Uuid::new_v4()).into_bytes()
+            }
+        })
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        hmac_sha256(self.key(), data)
+    }
+
+    /// Whether `signature` is the signature of `data`, compared in constant time.
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
+        mac(self.key(), data).verify_slice(signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static KEY: SigningKey = SigningKey::new("SIGNING_TEST_KEY_UNSET", "test tokens");
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(hex(&[0x00, 0xab, 0x7f]), "00ab7f");
+        assert_eq!(unhex("00ab7f"), Some(vec![0x00, 0xab, 0x7f]));
+        assert_eq!(unhex("00AB7F"), Some(vec![0x00, 0xab, 0x7f]));
+        assert_eq!(unhex("abc"), None);
+        assert_eq!(unhex("zz"), None);
+        assert_eq!(unhex("é0"), None);
+    }
+
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        // Test case 2 of RFC 4231.
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn verifies_only_untampered_signatures() {
+        let signature = KEY.sign(b"payload");
+        assert!(KEY.verify(b"payload", &signature));
+        assert!(!KEY.verify(b"payload2", &signature));
+        assert!(!KEY.verify(b"payload", &signature[..31]));
+
+        let mut flipped = signature.clone();
+        flipped[0] ^= 1;
+        assert!(!KEY.verify(b"payload", &flipped));
+
+        let other = SigningKey::new("SIGNING_TEST_OTHER_KEY_UNSET", "test tokens");
+        assert!(!other.verify(b"payload", &signature));
+    }
+}